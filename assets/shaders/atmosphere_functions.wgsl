@@ -0,0 +1,192 @@
+#define_import_path bevy_experiments::atmosphere
+
+// Based on https://www.shadertoy.com/view/wlBXWK
+
+fn calculate_scattering(
+    _start: vec3<f32>,
+    dir: vec3<f32>,
+    max_dist: f32,
+    scene_color: vec3<f32>,
+    light_dir: vec3<f32>,
+    light_intensity: vec3<f32>,
+    planet_position: vec3<f32>,
+    planet_radius: f32,
+    atmo_radius: f32,
+    beta_ray: vec3<f32>,
+    beta_mie: vec3<f32>,
+    beta_absorption: vec3<f32>,
+    beta_ambient: vec3<f32>,
+    g: f32,
+    height_ray: f32,
+    height_mie: f32,
+    height_absorption: f32,
+    absorption_falloff: f32,
+    steps_i: i32,
+    steps_l: i32,
+) -> vec3<f32> {
+    let start = _start - planet_position;
+    var a = dot(dir, dir);
+    var b = 2.0 * dot(dir, start);
+    var c = dot(start, start) - (atmo_radius * atmo_radius);
+    var d = (b * b)- 4.0 * a * c;
+
+    // stop early if there is no intersect
+    if (d < 0.0) { return scene_color; }
+
+    // calculate the ray length
+    var ray_length = vec2(
+        max((-b - sqrt(d)) / (2.0 * a), 0.0),
+        min((-b + sqrt(d)) / (2.0 * a), max_dist)
+    );
+
+    // if the ray did not hit the atmosphere, return a black color
+    if (ray_length.x > ray_length.y) { return scene_color; }
+
+    // prevent the mie glow from appearing if there's an object in front of the camera
+    let allow_mie = max_dist > ray_length.y;
+    // make sure the ray is no longer than allowed
+    ray_length.y = min(ray_length.y, max_dist);
+    ray_length.x = max(ray_length.x, 0.0);
+    // get the step size of the ray
+    let step_size_i = (ray_length.y - ray_length.x) / f32(steps_i);
+
+    // next, set how far we are along the ray, so we can calculate the position of the sample
+    // if the camera is outside the atmosphere, the ray should start at the edge of the atmosphere
+    // if it's inside, it should start at the position of the camera
+    // the min statement makes sure of that
+    var ray_pos_i = ray_length.x + step_size_i * 0.5;
+
+    // these are the values we use to gather all the scattered light
+    var total_ray = vec3(0.0); // for rayleigh
+    var total_mie = vec3(0.0); // for mie
+
+    // initialize the optical depth. This is used to calculate how much air was in the ray
+    var opt_i = vec3(0.0);
+
+    // also init the scale height, avoids some vec2's later on
+    let scale_height = vec2(height_ray, height_mie);
+
+    // Calculate the Rayleigh and Mie phases.
+    // This is the color that will be scattered for this ray
+    // mu, mumu and gg are used quite a lot in the calculation, so to speed it up, precalculate them
+    let mu = dot(dir, light_dir);
+    let mumu = mu * mu;
+    let gg = g * g;
+    let phase_ray = 3.0 / (50.2654824574 /* (16 * pi) */) * (1.0 + mumu);
+    var phase_mie = 0.0;
+    if allow_mie { phase_mie = 3.0 / (25.1327412287 /* (8 * pi) */) * ((1.0 - gg) * (mumu + 1.0)) / (pow(1.0 + gg - 2.0 * mu * g, 1.5) * (2.0 + gg)); }
+
+    // now we need to sample the 'primary' ray. this ray gathers the light that gets scattered onto it
+    for (var i = 0; i < steps_i; i++) {
+        // calculate where we are along this ray
+        let pos_i = start + dir * ray_pos_i;
+
+        // and how high we are above the surface
+        let height_i = length(pos_i) - planet_radius;
+
+        // now calculate the density of the particles (both for rayleigh and mie)
+        var density = vec3(exp(-height_i / scale_height), 0.0);
+
+        // and the absorption density. this is for ozone, which scales together with the rayleigh,
+        // but absorbs the most at a specific height, so use the sech function for a nice curve falloff for this height
+        // clamp it to avoid it going out of bounds. This prevents weird black spheres on the night side
+        let denom = (height_absorption - height_i) / absorption_falloff;
+        density.z = (1.0 / (denom * denom + 1.0)) * density.x;
+
+        // multiply it by the step size here
+        // we are going to use the density later on as well
+        density *= step_size_i;
+
+        // Add these densities to the optical depth, so that we know how many particles are on this ray.
+        opt_i += density;
+
+        // Calculate the step size of the light ray.
+        // again with a ray sphere intersect
+        // a, b, c and d are already defined
+        a = dot(light_dir, light_dir);
+        b = 2.0 * dot(light_dir, pos_i);
+        c = dot(pos_i, pos_i) - (atmo_radius * atmo_radius);
+        d = (b * b) - 4.0 * a * c;
+
+        // no early stopping, this one should always be inside the atmosphere
+        // calculate the ray length
+        let step_size_l = (-b + sqrt(d)) / (2.0 * a * f32(steps_l));
+
+        // and the position along this ray
+        // this time we are sure the ray is in the atmosphere, so set it to 0
+        var ray_pos_l = step_size_l * 0.5;
+
+        // and the optical depth of this ray
+        var opt_l = vec3(0.0);
+
+        // now sample the light ray
+        // this is similar to what we did before
+        for (var l = 0; l < steps_l; l++) {
+
+            // calculate where we are along this ray
+            let pos_l = pos_i + light_dir * ray_pos_l;
+
+            // the heigth of the position
+            let height_l = length(pos_l) - planet_radius;
+
+            // calculate the particle density, and add it
+            // this is a bit verbose
+            // first, set the density for ray and mie
+            var density_l = vec3(exp(-height_l / scale_height), 0.0);
+
+            // then, the absorption
+            let denom = (height_absorption - height_l) / absorption_falloff;
+            density_l.z = (1.0 / (denom * denom + 1.0)) * density_l.x;
+
+            // multiply the density by the step size
+            density_l *= step_size_l;
+
+            // and add it to the total optical depth
+            opt_l += density_l;
+
+            // and increment where we are along the light ray.
+            ray_pos_l += step_size_l;
+
+        }
+
+        // Now we need to calculate the attenuation
+        // this is essentially how much light reaches the current sample point due to scattering
+        let attn = exp(-beta_ray * (opt_i.x + opt_l.x) - beta_mie * (opt_i.y + opt_l.y) - beta_absorption * (opt_i.z + opt_l.z));
+
+        // accumulate the scattered light (how much will be scattered towards the camera)
+        total_ray += density.x * attn;
+        total_mie += density.y * attn;
+
+        // and increment the position on this ray
+        ray_pos_i += step_size_i;
+
+    }
+
+    // calculate how much light can pass through the atmosphere
+    let opacity = exp(-(beta_mie * opt_i.y + beta_ray * opt_i.x + beta_absorption * opt_i.z));
+
+	// calculate and return the final color
+    return (
+        	phase_ray * beta_ray * total_ray // rayleigh color
+       		+ phase_mie * beta_mie * total_mie // mie
+            + opt_i.x * beta_ambient // and ambient
+    ) * light_intensity + scene_color * opacity; // now make sure the background is rendered correctly
+
+
+}
+
+
+/*
+A ray-sphere intersect
+This was previously used in the atmosphere as well, but it's only used for the planet intersect now, since the atmosphere has this
+ray sphere intersect built in
+*/
+fn ray_sphere_intersect(start: vec3<f32>, dir: vec3<f32>, radius: f32) -> vec2<f32> {
+	let a: f32 = dot(dir, dir);
+	let b: f32 = 2. * dot(dir, start);
+	let c: f32 = dot(start, start) - radius * radius;
+	let d: f32 = b * b - 4. * a * c;
+	if (d < 0.) {	return vec2<f32>(100000., -100000.);
+ }
+	return vec2<f32>((-b - sqrt(d)) / (2. * a), (-b + sqrt(d)) / (2. * a));
+}
@@ -0,0 +1,132 @@
+#import bevy_pbr::mesh_view_bindings::view
+#import bevy_experiments::atmosphere::ray_sphere_intersect
+
+// Matches `AtmosphereSettings`, lengths are in meters
+struct AtmosphereSettings {
+    sun_direction: vec3<f32>,
+    sun_intensity: f32,
+    rayleigh_beta: vec3<f32>,
+    planet_radius: f32,
+    mie_beta: vec3<f32>,
+    atmosphere_radius: f32,
+    absorption_beta: vec3<f32>,
+    mie_g: f32,
+    ambient_beta: vec3<f32>,
+    rayleigh_height: f32,
+    mie_height: f32,
+    absorption_height: f32,
+    absorption_falloff: f32,
+    altitude: f32,
+    primary_steps: u32,
+}
+
+@group(2) @binding(0) var<uniform> settings: AtmosphereSettings;
+@group(2) @binding(1) var transmittance_lut: texture_2d<f32>;
+@group(2) @binding(2) var transmittance_sampler: sampler;
+@group(2) @binding(3) var multiscattering_lut: texture_2d<f32>;
+@group(2) @binding(4) var multiscattering_sampler: sampler;
+
+const PI: f32 = 3.141592653589793;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) ndc: vec2<f32>,
+}
+
+@vertex
+fn vertex(@location(0) position: vec3<f32>) -> VertexOutput {
+    var out: VertexOutput;
+    // the mesh is a triangle that covers the screen, placed on the far plane so only the
+    // background is left for it
+    out.position = vec4(position.xy, 0.0, 1.0);
+    out.ndc = position.xy;
+    return out;
+}
+
+// Both lookup tables are indexed by the cosine of the sun with the up direction and the height,
+// as in `atmosphere::lut`
+fn lut_uv(height: f32, sun_mu: f32) -> vec2<f32> {
+    let thickness = settings.atmosphere_radius - settings.planet_radius;
+    return vec2(0.5 + 0.5 * sun_mu, clamp(height / thickness, 0.0, 1.0));
+}
+
+// densities of the air, the haze and the ozone
+fn densities(height: f32) -> vec3<f32> {
+    let rayleigh = exp(-height / settings.rayleigh_height);
+    let mie = exp(-height / settings.mie_height);
+    let ozone = (settings.absorption_height - height) / settings.absorption_falloff;
+    return vec3(rayleigh, mie, rayleigh / (ozone * ozone + 1.0));
+}
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    // the direction from the camera through this pixel, via a point on the near plane
+    let near = view.world_from_clip * vec4(in.ndc, 1.0, 1.0);
+    let dir = normalize(near.xyz / near.w - view.world_position);
+    let sun = settings.sun_direction;
+
+    // the scene is a small patch on top of the planet
+    let height = max(view.world_position.y + settings.altitude, 1.0);
+    let origin = vec3(0.0, settings.planet_radius + height, 0.0);
+
+    // the sun disk, hidden by the ground below the horizon
+    var scene_color = vec3(0.0);
+    if dot(dir, sun) > 0.9998 {
+        scene_color = vec3(3.0);
+    }
+    let atmosphere_intersect = ray_sphere_intersect(origin, dir, settings.atmosphere_radius);
+    var max_dist = atmosphere_intersect.y;
+    let planet_intersect = ray_sphere_intersect(origin, dir, settings.planet_radius);
+    if 0.0 < planet_intersect.y {
+        max_dist = max(planet_intersect.x, 0.0);
+        scene_color = vec3(0.0);
+    }
+    let start = max(atmosphere_intersect.x, 0.0);
+    if max_dist <= start {
+        return vec4(1.0 - exp(-scene_color), 1.0);
+    }
+
+    let mu = dot(dir, sun);
+    let phase_ray = 3.0 / (16.0 * PI) * (1.0 + mu * mu);
+    var phase_mie = 0.0;
+    // no glow around the sun when the ground is in front of it
+    if planet_intersect.y <= 0.0 {
+        let g = settings.mie_g;
+        let gg = g * g;
+        phase_mie = 3.0 / (8.0 * PI) * ((1.0 - gg) * (mu * mu + 1.0))
+            / (pow(1.0 + gg - 2.0 * mu * g, 1.5) * (2.0 + gg));
+    }
+
+    let step_size = (max_dist - start) / f32(settings.primary_steps);
+    var transmittance = vec3(1.0);
+    var luminance = vec3(0.0);
+    for (var i = 0u; i < settings.primary_steps; i++) {
+        let position = origin + dir * (start + (f32(i) + 0.5) * step_size);
+        let radius = length(position);
+        let sample_height = radius - settings.planet_radius;
+        let sun_mu = dot(position / radius, sun);
+        let uv = lut_uv(sample_height, sun_mu);
+        let sun_transmittance = textureSampleLevel(transmittance_lut, transmittance_sampler, uv, 0.0).rgb;
+        let multiscattering = textureSampleLevel(multiscattering_lut, multiscattering_sampler, uv, 0.0).rgb;
+
+        let density = densities(sample_height);
+        let rayleigh = settings.rayleigh_beta * density.x;
+        let mie = settings.mie_beta * density.y;
+        let extinction = rayleigh + mie + settings.absorption_beta * density.z;
+
+        let scattered = settings.sun_intensity * (
+            sun_transmittance * (rayleigh * phase_ray + mie * phase_mie)
+            + multiscattering * (rayleigh + mie)
+        ) + settings.ambient_beta * density.x;
+
+        // integrate the scattered light over the step, as it is dimmed along the way
+        let step_transmittance = exp(-extinction * step_size);
+        luminance += transmittance * scattered * (1.0 - step_transmittance) / max(extinction, vec3(1e-12));
+        transmittance *= step_transmittance;
+    }
+
+    // apply exposure, as in the 2D atmosphere
+    let col = 1.0 - exp(-(luminance + scene_color * transmittance));
+
+    return vec4(col, 1.0);
+}
@@ -1,5 +1,6 @@
 const TEXTURE_SIZE: u32 = 128;
 const TEXTURE_SIZE_F32: f32 = f32(TEXTURE_SIZE);
+const MAX_BOIDS_COUNT: u32 = TEXTURE_SIZE * TEXTURE_SIZE;
 const BOX_SIZE: u32 = 1000;
 const BOX_SIZE_F32: f32 = f32(BOX_SIZE);
 const HALF_BOX_SIZE_F32: f32 = BOX_SIZE_F32 * 0.5;
@@ -18,11 +19,86 @@ struct Config {
     max_speed: f32,
 };
 
+struct Heightfield {
+    origin: vec2f,
+    size: f32,
+    base_height: f32,
+    resolution: u32,
+    min_altitude: f32,
+    avoid_factor: f32,
+};
+
+struct Route {
+    // xyz is the position of a waypoint, w its arrival radius
+    waypoints: array<vec4f, #{MAX_WAYPOINTS}>,
+    count: u32,
+    current: u32,
+    weight: f32,
+};
+
+struct Cull {
+    frustum: array<vec4f, 6>,
+    camera_position: vec3f,
+    boid_radius: f32,
+    lod_distances: vec2f,
+    frustum_culling: u32,
+};
+
+struct Trail {
+    length: u32,
+    head: u32,
+    filled: u32,
+    color_by_speed: u32,
+    max_speed: f32,
+    width: f32,
+};
+
+struct FlockReduction {
+    // The sum over the boids in the partial results, the mean in the final summary
+    position: vec3f,
+    count: u32,
+    velocity: vec3f,
+    speed_min: f32,
+    aabb_min: vec3f,
+    speed_max: f32,
+    aabb_max: vec3f,
+};
+
+struct DrawIndexedIndirectArgs {
+    index_count: u32,
+    instance_count: atomic<u32>,
+    first_index: u32,
+    base_vertex: i32,
+    first_instance: u32,
+};
+
 @group(0) @binding(0) var<uniform> config: Config;
+@group(0) @binding(1) var<uniform> heightfield: Heightfield;
+// Terrain heights above `heightfield.base_height`, texel (x, y) is the grid point at world (x, z)
+@group(0) @binding(2) var heightmap: texture_2d<f32>;
+@group(0) @binding(3) var<uniform> route: Route;
 
 @group(1) @binding(0) var position_map: texture_storage_2d<rgba32float, read_write>;
 @group(1) @binding(1) var velocity_map: texture_storage_2d<rgba32float, read_write>;
 
+@group(2) @binding(0) var<uniform> cull_config: Cull;
+// One bucket of MAX_BOIDS_COUNT boid indices per LOD
+@group(2) @binding(1) var<storage, read_write> visible_instances: array<u32>;
+@group(2) @binding(2) var<storage, read_write> indirect_args: array<DrawIndexedIndirectArgs, #{LOD_COUNT}>;
+
+// Shares group 2 with the cull bindings, the reduce passes bind a different bind group there
+@group(2) @binding(3) var<storage, read_write> flock_partials: array<FlockReduction>;
+@group(2) @binding(4) var<storage, read_write> flock_summary: FlockReduction;
+
+@group(3) @binding(0) var<uniform> trail_config: Trail;
+// A ring buffer of `trail_config.length` points per boid, xyz is the position and w the speed
+@group(3) @binding(1) var<storage, read_write> trail_points: array<vec4f>;
+
+// Boid i is stored at texel (i / TEXTURE_SIZE, i % TEXTURE_SIZE), matching the lookup in boids_material.wgsl
+fn texel_from_index(index: u32) -> vec2u {
+    return vec2u(index / TEXTURE_SIZE, index % TEXTURE_SIZE);
+}
+
 fn steer_towards(velocity_self: vec3f, velocity_towards: vec3f) -> vec3f {
     let max_steer_force = 0.01;
     let v = normalize(velocity_towards) * config.max_speed - velocity_self;
@@ -34,7 +110,13 @@ fn steer_towards(velocity_self: vec3f, velocity_towards: vec3f) -> vec3f {
     return v;
 }
 
-fn loop_through_neighbors(position: vec3f, velocity: vec3f) -> vec3f {
+struct Steering {
+    velocity: vec3f,
+    // Number of boids within the align range, stored in the w channel of the velocity map
+    neighbors: u32,
+};
+
+fn loop_through_neighbors(position: vec3f, velocity: vec3f) -> Steering {
     let delta_time = 0.033;
     var avoid_velocity = vec3f();
     var result_velocity = velocity;
@@ -44,35 +126,30 @@ fn loop_through_neighbors(position: vec3f, velocity: vec3f) -> vec3f {
     var avg_velocity = vec3f();
     var averaging_neighbors = 0;
     var centering_neighbors = 0;
-    var i: u32 = 0;
 
     // Loop through all boids for now even though it's slow
-    for (var x: u32 = 0; x < TEXTURE_SIZE; x++) {
-        for (var y: u32 = 0; y < TEXTURE_SIZE; y++) {
-            if i > config.boids_count { break; }
-
-            let other_position = textureLoad(position_map, vec2u(x, y)).xyz;
-            let offset = position - other_position;
-            let distance_squared = offset.x * offset.x + offset.y * offset.y + offset.z * offset.z;
-            i++;
-
-            if distance_squared < EPSILON * EPSILON {
-                continue;
-            }
-
-            if distance_squared < config.align_range * config.align_range {
-                avg_velocity += textureLoad(velocity_map, vec2u(x, y)).xyz;
-                averaging_neighbors++;
-            }
-
-            if distance_squared < config.avoid_range * config.avoid_range {
-                avoid_velocity += offset;
-            }
-
-            if distance_squared < config.centering_range * config.centering_range {
-                center += other_position;
-                centering_neighbors++;
-            }
+    for (var i: u32 = 0; i < config.boids_count; i++) {
+        let texel = texel_from_index(i);
+        let other_position = textureLoad(position_map, texel).xyz;
+        let offset = position - other_position;
+        let distance_squared = offset.x * offset.x + offset.y * offset.y + offset.z * offset.z;
+
+        if distance_squared < EPSILON * EPSILON {
+            continue;
+        }
+
+        if distance_squared < config.align_range * config.align_range {
+            avg_velocity += textureLoad(velocity_map, texel).xyz;
+            averaging_neighbors++;
+        }
+
+        if distance_squared < config.avoid_range * config.avoid_range {
+            avoid_velocity += offset;
+        }
+
+        if distance_squared < config.centering_range * config.centering_range {
+            center += other_position;
+            centering_neighbors++;
         }
     }
 
@@ -90,7 +167,7 @@ fn loop_through_neighbors(position: vec3f, velocity: vec3f) -> vec3f {
         acceleration += steer_towards(velocity, avg_velocity / f32(averaging_neighbors)) * config.align_factor;
     }
 
-    return velocity + acceleration * delta_time;
+    return Steering(velocity + acceleration * delta_time, u32(averaging_neighbors));
 }
 
 fn keep_boid_within_bounds(position: vec3f) -> vec3f {
@@ -120,6 +197,68 @@ fn keep_boid_within_bounds(position: vec3f) -> vec3f {
     return velocity_diff;
 }
 
+// Bilinearly interpolated world space height of the terrain below `xz`
+fn terrain_height(xz: vec2f) -> f32 {
+    let resolution = f32(heightfield.resolution);
+    let grid = saturate((xz - heightfield.origin) / heightfield.size) * resolution;
+    let cell = min(vec2u(grid), vec2u(heightfield.resolution - 1u));
+    let t = grid - vec2f(cell);
+
+    let h00 = textureLoad(heightmap, cell, 0).r;
+    let h10 = textureLoad(heightmap, cell + vec2u(1u, 0u), 0).r;
+    let h01 = textureLoad(heightmap, cell + vec2u(0u, 1u), 0).r;
+    let h11 = textureLoad(heightmap, cell + vec2u(1u, 1u), 0).r;
+    return heightfield.base_height + mix(mix(h00, h10, t.x), mix(h01, h11, t.x), t.y);
+}
+
+fn is_above_terrain(xz: vec2f) -> bool {
+    let local = xz - heightfield.origin;
+    return all(local >= vec2f()) && all(local <= vec2f(heightfield.size));
+}
+
+// Push boids up when they fly lower than the minimum altitude, and steer them away from the
+// slope of the terrain when they are about to fly into a peak
+fn avoid_terrain(position: vec3f, velocity: vec3f) -> vec3f {
+    let look_ahead_frames = 30.0;
+    let min_altitude = max(heightfield.min_altitude, EPSILON);
+    var velocity_diff = vec3f();
+
+    if is_above_terrain(position.xz) {
+        let altitude = position.y - terrain_height(position.xz);
+        velocity_diff.y += saturate(1.0 - altitude / min_altitude) * config.bounds_turn_factor;
+    }
+
+    let ahead = position + velocity * look_ahead_frames;
+    if is_above_terrain(ahead.xz) {
+        let altitude_ahead = ahead.y - terrain_height(ahead.xz);
+        let urgency = saturate(1.0 - altitude_ahead / min_altitude);
+        let step = heightfield.size / f32(heightfield.resolution);
+        let slope = vec2f(
+            terrain_height(ahead.xz + vec2f(step, 0.0)) - terrain_height(ahead.xz - vec2f(step, 0.0)),
+            terrain_height(ahead.xz + vec2f(0.0, step)) - terrain_height(ahead.xz - vec2f(0.0, step)),
+        );
+        if dot(slope, slope) > EPSILON {
+            let downhill = -normalize(slope);
+            velocity_diff += vec3f(downhill.x, 0.0, downhill.y) * urgency * config.bounds_turn_factor;
+        }
+        velocity_diff.y += urgency * config.bounds_turn_factor;
+    }
+
+    return velocity_diff * heightfield.avoid_factor;
+}
+
+fn seek_waypoint(position: vec3f, velocity: vec3f) -> vec3f {
+    if route.count == 0u || route.weight <= 0.0 {
+        return vec3f();
+    }
+
+    let offset = route.waypoints[route.current].xyz - position;
+    if dot(offset, offset) < EPSILON {
+        return vec3f();
+    }
+    return steer_towards(velocity, offset) * route.weight;
+}
+
 fn limit_speed(velocity: vec3f) -> vec3f {
     let speed_sqrd = velocity.x * velocity.x + velocity.y * velocity.y + velocity.z * velocity.z;
     if speed_sqrd > config.max_speed * config.max_speed {
@@ -128,44 +267,190 @@ fn limit_speed(velocity: vec3f) -> vec3f {
     return velocity;
 }
 
-@compute @workgroup_size(8, 8, 1)
+@compute @workgroup_size(#{WORKGROUP_SIZE}, 1, 1)
 fn init(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
-    let index = vec2f(f32(invocation_id.x), f32(invocation_id.y));
+    if invocation_id.x >= MAX_BOIDS_COUNT {
+        return;
+    }
+
+    let texel = texel_from_index(invocation_id.x);
+    let index = vec2f(f32(texel.x), f32(texel.y));
     let max_init_speed = 1.0;
 
     let location_f32 = vec2f(BOX_SIZE_F32 * (index.x / TEXTURE_SIZE_F32 - 0.5), BOX_SIZE_F32 * (index.y / TEXTURE_SIZE_F32 - 0.5));
 
-    storageBarrier();
-
     let z = sin(f32(index.x * 960.2 + index.y * 2.0)) * HALF_BOX_SIZE_F32;
     let vx = sin(f32(index.x * 672.2 + index.y * 1.0)) * HALF_BOX_SIZE_F32;
     let vy = sin(f32(index.x * 44.2 + index.y * 3.0)) * max_init_speed;
     let vz = sin(f32(index.x * 123.2 + index.y * 4.0)) * max_init_speed;
 
-    textureStore(position_map, invocation_id.xy, vec4f(location_f32, z, 0.0));
-    textureStore(velocity_map, invocation_id.xy, vec4f(vx, vy, vz, 0.0));
+    textureStore(position_map, texel, vec4f(location_f32, z, 0.0));
+    textureStore(velocity_map, texel, vec4f(vx, vy, vz, 0.0));
+}
+
+@compute @workgroup_size(#{WORKGROUP_SIZE}, 1, 1)
+fn update(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    let index = invocation_id.x;
+    // The last workgroup can extend past the boid count, those invocations only take part in the barrier
+    let is_active = index < config.boids_count;
+    let texel = texel_from_index(min(index, MAX_BOIDS_COUNT - 1));
+
+    let position = textureLoad(position_map, texel).xyz;
+    var velocity = textureLoad(velocity_map, texel).xyz;
+    var neighbors = 0u;
+
+    if is_active {
+        let steering = loop_through_neighbors(position, velocity);
+        velocity = steering.velocity;
+        neighbors = steering.neighbors;
+        velocity += keep_boid_within_bounds(position);
+        velocity += seek_waypoint(position, velocity);
+        velocity += avoid_terrain(position, velocity);
+        velocity = limit_speed(velocity);
+    }
+
+    storageBarrier();
+
+    if is_active {
+        var new_position = position + velocity;
+        // never let a boid end up inside the terrain
+        if is_above_terrain(new_position.xz) {
+            new_position.y = max(new_position.y, terrain_height(new_position.xz));
+        }
+        textureStore(position_map, texel, vec4(new_position, 0.0));
+        textureStore(velocity_map, texel, vec4(velocity, f32(neighbors)));
+    }
+}
+
+fn is_in_frustum(position: vec3f) -> bool {
+    for (var i: u32 = 0; i < 6; i++) {
+        let plane = cull_config.frustum[i];
+        if dot(plane.xyz, position) + plane.w + cull_config.boid_radius <= 0.0 {
+            return false;
+        }
+    }
+    return true;
+}
+
+fn lod_from_distance(distance: f32) -> u32 {
+    if distance > cull_config.lod_distances.y {
+        return 2u;
+    }
+    if distance > cull_config.lod_distances.x {
+        return 1u;
+    }
+    return 0u;
+}
+
+@compute @workgroup_size(#{WORKGROUP_SIZE}, 1, 1)
+fn cull(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    let index = invocation_id.x;
+    if index >= config.boids_count {
+        return;
+    }
+
+    let position = textureLoad(position_map, texel_from_index(index)).xyz;
+    if cull_config.frustum_culling != 0u && !is_in_frustum(position) {
+        return;
+    }
+
+    let lod = lod_from_distance(distance(position, cull_config.camera_position));
+    let slot = atomicAdd(&indirect_args[lod].instance_count, 1u);
+    visible_instances[lod * MAX_BOIDS_COUNT + slot] = index;
 }
 
-@compute @workgroup_size(8, 8, 1)
-fn update(@builtin(global_invocation_id) invocation_id: vec3<u32>, @builtin(num_workgroups) num_workgroups: vec3<u32>) {
-    let index = invocation_id.x * TEXTURE_SIZE + invocation_id.y;
-    if index > config.boids_count {
-        storageBarrier();
+@compute @workgroup_size(#{WORKGROUP_SIZE}, 1, 1)
+fn record_trails(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    let index = invocation_id.x;
+    if index >= config.boids_count {
         return;
     }
 
-    var location_i32 = vec2i(i32(invocation_id.x), i32(invocation_id.y));
-    var position = textureLoad(position_map, location_i32).xyz;
-    var velocity = textureLoad(velocity_map, location_i32).xyz;
+    let texel = texel_from_index(index);
+    let position = textureLoad(position_map, texel).xyz;
+    let speed = length(textureLoad(velocity_map, texel).xyz);
+    trail_points[index * trail_config.length + trail_config.head] = vec4f(position, speed);
+}
+
+// Scratch space for the tree reductions, which assume a power of two workgroup size
+var<workgroup> reduction_scratch: array<FlockReduction, #{WORKGROUP_SIZE}>;
 
-    let neighbors_interaction = loop_through_neighbors(position, velocity);
+fn empty_reduction() -> FlockReduction {
+    return FlockReduction(
+        vec3f(0.0), 0u, vec3f(0.0), 3.40282e38, vec3f(3.40282e38), 0.0, vec3f(-3.40282e38)
+    );
+}
 
-    velocity = neighbors_interaction;
-    velocity += keep_boid_within_bounds(position);
-    velocity = limit_speed(velocity);
+fn combine(a: FlockReduction, b: FlockReduction) -> FlockReduction {
+    return FlockReduction(
+        a.position + b.position,
+        a.count + b.count,
+        a.velocity + b.velocity,
+        min(a.speed_min, b.speed_min),
+        min(a.aabb_min, b.aabb_min),
+        max(a.speed_max, b.speed_max),
+        max(a.aabb_max, b.aabb_max),
+    );
+}
 
-    storageBarrier();
+// Combine the values in `reduction_scratch`, leaving the result in the first element
+fn reduce_scratch(local_index: u32) {
+    for (var stride = #{WORKGROUP_SIZE}u / 2u; stride > 0u; stride /= 2u) {
+        workgroupBarrier();
+        if local_index < stride {
+            reduction_scratch[local_index] = combine(
+                reduction_scratch[local_index],
+                reduction_scratch[local_index + stride]
+            );
+        }
+    }
+    workgroupBarrier();
+}
 
-    textureStore(position_map, location_i32, vec4(position + velocity, 0.0));
-    textureStore(velocity_map, location_i32, vec4(velocity, 0.0));
+@compute @workgroup_size(#{WORKGROUP_SIZE}, 1, 1)
+fn reduce(
+    @builtin(global_invocation_id) invocation_id: vec3<u32>,
+    @builtin(local_invocation_index) local_index: u32,
+    @builtin(workgroup_id) workgroup_id: vec3<u32>,
+) {
+    let index = invocation_id.x;
+    var value = empty_reduction();
+    if index < config.boids_count {
+        let texel = texel_from_index(index);
+        let position = textureLoad(position_map, texel).xyz;
+        let velocity = textureLoad(velocity_map, texel).xyz;
+        let speed = length(velocity);
+        value = FlockReduction(position, 1u, velocity, speed, position, speed, position);
+    }
+    reduction_scratch[local_index] = value;
+
+    reduce_scratch(local_index);
+
+    if local_index == 0u {
+        flock_partials[workgroup_id.x] = reduction_scratch[0];
+    }
+}
+
+// Runs as a single workgroup, combining the partial results of `reduce` into the flock summary
+@compute @workgroup_size(#{WORKGROUP_SIZE}, 1, 1)
+fn reduce_final(@builtin(local_invocation_index) local_index: u32) {
+    let partials_count = (config.boids_count + #{WORKGROUP_SIZE}u - 1u) / #{WORKGROUP_SIZE}u;
+    var value = empty_reduction();
+    for (var i = local_index; i < partials_count; i += #{WORKGROUP_SIZE}u) {
+        value = combine(value, flock_partials[i]);
+    }
+    reduction_scratch[local_index] = value;
+
+    reduce_scratch(local_index);
+
+    if local_index == 0u {
+        var summary = reduction_scratch[0];
+        if summary.count > 0u {
+            summary.position /= f32(summary.count);
+            summary.velocity /= f32(summary.count);
+        } else {
+            summary = FlockReduction(vec3f(0.0), 0u, vec3f(0.0), 0.0, vec3f(0.0), 0.0, vec3f(0.0));
+        }
+        flock_summary = summary;
+    }
 }
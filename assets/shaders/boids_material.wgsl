@@ -1,20 +1,9 @@
 #import bevy_pbr::{
-    pbr_fragment::pbr_input_from_standard_material,
-    pbr_functions::alpha_discard,
+    mesh_view_bindings::view,
+    pbr_types::pbr_input_new,
+    pbr_functions::{apply_pbr_lighting, calculate_view, main_pass_post_lighting_processing, prepare_world_normal},
     view_transformations::position_world_to_clip,
 }
-#import bevy_pbr::mesh_functions::{get_world_from_local, mesh_position_local_to_world, mesh_normal_local_to_world}
-#ifdef PREPASS_PIPELINE
-#import bevy_pbr::{
-    prepass_io::{VertexOutput, FragmentOutput},
-    pbr_deferred_functions::deferred_output,
-}
-#else
-#import bevy_pbr::{
-    forward_io::{VertexOutput, FragmentOutput},
-    pbr_functions::{apply_pbr_lighting, main_pass_post_lighting_processing},
-}
-#endif
 
 struct Vertex {
     @builtin(instance_index) instance_index: u32,
@@ -22,35 +11,91 @@ struct Vertex {
     @location(1) normal: vec3<f32>,
 };
 
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) world_position: vec4<f32>,
+    @location(1) world_normal: vec3<f32>,
+    @location(2) @interpolate(flat) boid_index: u32,
+};
+
+struct BoidsColor {
+    solid_color: vec4<f32>,
+    ramp: array<vec4<f32>, 4>,
+    mode: u32,
+    max_speed: f32,
+    density_max_neighbors: f32,
+    species_count: u32,
+};
+
 const TEXTURE_SIZE: u32 = 128;
-const TEXTURE_SIZE_F32: f32 = f32(TEXTURE_SIZE);
+const COLOR_RAMP_SIZE: u32 = 4;
+const PI: f32 = 3.14159265;
+
+// Matching `BoidsColorMode` in color.rs
+const COLOR_MODE_SOLID: u32 = 0;
+const COLOR_MODE_SPEED: u32 = 1;
+const COLOR_MODE_HEADING: u32 = 2;
+const COLOR_MODE_DENSITY: u32 = 3;
+const COLOR_MODE_SPECIES: u32 = 4;
 
 @group(2) @binding(100) var position_texture: texture_2d<f32>;
-@group(2) @binding(101) var position_sampler: sampler;
-@group(2) @binding(102) var velocity_texture: texture_2d<f32>;
-@group(2) @binding(103) var velocity_sampler: sampler;
+@group(2) @binding(101) var velocity_texture: texture_2d<f32>;
+// The bucket of visible boid indices for the LOD being drawn, filled by the `cull` compute pass
+@group(2) @binding(102) var<storage, read> visible_instances: array<u32>;
+@group(2) @binding(103) var<uniform> boids_color: BoidsColor;
 
+fn texel_from_index(index: u32) -> vec2u {
+    return vec2u(index / TEXTURE_SIZE, index % TEXTURE_SIZE);
+}
+
+fn sample_ramp(t: f32) -> vec4<f32> {
+    let scaled = saturate(t) * f32(COLOR_RAMP_SIZE - 1);
+    let index = min(u32(scaled), COLOR_RAMP_SIZE - 2);
+    return mix(boids_color.ramp[index], boids_color.ramp[index + 1], scaled - f32(index));
+}
+
+fn hue_to_rgb(hue: f32) -> vec3<f32> {
+    return saturate(abs(fract(hue + vec3<f32>(0.0, 2.0 / 3.0, 1.0 / 3.0)) * 6.0 - 3.0) - 1.0);
+}
+
+fn boid_color(boid_index: u32) -> vec4<f32> {
+    // xyz is the velocity, w the number of neighbors within the align range
+    let velocity = textureLoad(velocity_texture, texel_from_index(boid_index), 0);
+
+    switch boids_color.mode {
+        case COLOR_MODE_SPEED: {
+            return sample_ramp(length(velocity.xyz) / boids_color.max_speed);
+        }
+        case COLOR_MODE_HEADING: {
+            let azimuth = atan2(velocity.z, velocity.x);
+            return vec4<f32>(hue_to_rgb(azimuth / (2.0 * PI) + 0.5), 1.0);
+        }
+        case COLOR_MODE_DENSITY: {
+            return sample_ramp(velocity.w / boids_color.density_max_neighbors);
+        }
+        case COLOR_MODE_SPECIES: {
+            if boids_color.species_count <= 1u {
+                return boids_color.ramp[0];
+            }
+            let species = boid_index % boids_color.species_count;
+            return sample_ramp(f32(species) / f32(boids_color.species_count - 1u));
+        }
+        default: {
+            return boids_color.solid_color;
+        }
+    }
+}
 
 @vertex
 fn vertex(vertex: Vertex) -> VertexOutput {
-    // Based on: https://github.com/bevyengine/bevy/blob/286bc8cce52add44e6f6f9c8cd778d26eaa1a761/crates/bevy_pbr/src/render/mesh.wgsl
     var out: VertexOutput;
-    let model = get_world_from_local(vertex.instance_index);
-
-    var coord = vec2f(f32(vertex.instance_index / TEXTURE_SIZE) / TEXTURE_SIZE_F32, f32(vertex.instance_index % TEXTURE_SIZE) / TEXTURE_SIZE_F32);
-    coord += 0.5 / TEXTURE_SIZE_F32;
-    let position: vec4f = textureSampleLevel(position_texture, position_sampler, coord, 0.0);
-    // out.uv = vertex.uv;
-    out.instance_index = vertex.instance_index;
-    out.world_position = mesh_position_local_to_world(model, vec4<f32>(vertex.position, 1.0));
-    out.world_position += position;
+    let boid_index = visible_instances[vertex.instance_index];
+    let boid_position = textureLoad(position_texture, texel_from_index(boid_index), 0).xyz;
+
+    out.world_position = vec4<f32>(vertex.position + boid_position, 1.0);
     out.position = position_world_to_clip(out.world_position.xyz);
-    out.world_normal = mesh_normal_local_to_world(
-        vertex.normal,
-        // Use vertex_no_morph.instance_index instead of vertex.instance_index to work around a wgpu dx12 bug.
-        // See https://github.com/gfx-rs/naga/issues/2416
-        vertex.instance_index
-    );
+    out.world_normal = vertex.normal;
+    out.boid_index = boid_index;
     return out;
 }
 
@@ -59,33 +104,23 @@ fn vertex(vertex: Vertex) -> VertexOutput {
 fn fragment(
     in: VertexOutput,
     @builtin(front_facing) is_front: bool,
-) -> FragmentOutput {
-    // generate a PbrInput struct from the StandardMaterial bindings
-    var pbr_input = pbr_input_from_standard_material(in, is_front);
+) -> @location(0) vec4<f32> {
+    var pbr_input = pbr_input_new();
 
-    // we can optionally modify the input before lighting and alpha_discard is applied
-    pbr_input.material.base_color.b = pbr_input.material.base_color.r;
+    pbr_input.material.base_color = boid_color(in.boid_index);
+    pbr_input.frag_coord = in.position;
+    pbr_input.world_position = in.world_position;
+    pbr_input.world_normal = prepare_world_normal(in.world_normal, false, is_front);
+    pbr_input.N = normalize(pbr_input.world_normal);
+    pbr_input.is_orthographic = view.clip_from_view[3].w == 1.0;
+    pbr_input.V = calculate_view(in.world_position, pbr_input.is_orthographic);
 
-    // alpha discard
-    pbr_input.material.base_color = alpha_discard(pbr_input.material, pbr_input.material.base_color);
-
-#ifdef PREPASS_PIPELINE
-    // in deferred mode we can't modify anything after that, as lighting is run in a separate fullscreen shader.
-    let out = deferred_output(in, pbr_input);
-#else
-    var out: FragmentOutput;
     // apply lighting
-    out.color = apply_pbr_lighting(pbr_input);
+    var color = apply_pbr_lighting(pbr_input);
 
     // apply in-shader post processing (fog, alpha-premultiply, and also tonemapping, debanding if the camera is non-hdr)
     // note this does not include fullscreen postprocessing effects like bloom.
-    out.color = main_pass_post_lighting_processing(pbr_input, out.color);
-
-    // we can optionally modify the final result here
-    out.color = out.color * 2.0;
-#endif
-
-    // out.color = textureSampleLevel(position_texture, position_sampler, vec2<f32>(vec2<i32>(in.uv * 128.0)) / 128.0, 0.0) / 128.0;
+    color = main_pass_post_lighting_processing(pbr_input, color);
 
-    return out;
-}
\ No newline at end of file
+    return color * 2.0;
+}
@@ -0,0 +1,77 @@
+#import bevy_render::view::View
+
+struct Trail {
+    length: u32,
+    head: u32,
+    filled: u32,
+    color_by_speed: u32,
+    max_speed: f32,
+    width: f32,
+};
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+};
+
+const SLOW_COLOR: vec3<f32> = vec3<f32>(0.1, 0.3, 1.0);
+const FAST_COLOR: vec3<f32> = vec3<f32>(1.0, 0.2, 0.1);
+const MAX_ALPHA: f32 = 0.6;
+
+@group(0) @binding(0) var<uniform> view: View;
+
+@group(1) @binding(0) var<uniform> trail: Trail;
+// A ring buffer of `trail.length` points per boid, written by `record_trails` in boids_compute.wgsl
+@group(1) @binding(1) var<storage, read> trail_points: array<vec4<f32>>;
+
+// Point `age` frames back in the trail of a boid, 0 being the current position
+fn trail_point(boid_index: u32, age: u32) -> vec4<f32> {
+    let clamped_age = min(age, trail.filled - 1u);
+    let slot = (trail.head + trail.length - clamped_age) % trail.length;
+    return trail_points[boid_index * trail.length + slot];
+}
+
+// Each trail segment is a quad of two triangles spanning the points at `age` and `age + 1`
+@vertex
+fn vertex(
+    @builtin(vertex_index) vertex_index: u32,
+    @builtin(instance_index) boid_index: u32,
+) -> VertexOutput {
+    var corners = array<vec2<u32>, 6>(
+        vec2<u32>(0u, 0u), vec2<u32>(0u, 1u), vec2<u32>(1u, 0u),
+        vec2<u32>(1u, 0u), vec2<u32>(0u, 1u), vec2<u32>(1u, 1u),
+    );
+    let corner = corners[vertex_index % 6u];
+    let age = vertex_index / 6u + corner.x;
+
+    let point = trail_point(boid_index, age);
+    let tangent = trail_point(boid_index, age + 1u).xyz - trail_point(boid_index, max(age, 1u) - 1u).xyz;
+    let to_camera = view.world_position - point.xyz;
+    var side = cross(tangent, to_camera);
+    if dot(side, side) < 0.0001 {
+        side = vec3<f32>(0.0);
+    } else {
+        side = normalize(side);
+    }
+
+    // the ribbon narrows and fades out towards its oldest point
+    let fade = 1.0 - f32(age) / f32(trail.length - 1u);
+    let offset = side * trail.width * fade * (f32(corner.y) * 2.0 - 1.0);
+
+    var color = vec3<f32>(1.0);
+    if trail.color_by_speed != 0u {
+        color = mix(SLOW_COLOR, FAST_COLOR, saturate(point.w / trail.max_speed));
+    }
+
+    var out: VertexOutput;
+    out.position = view.clip_from_world * vec4<f32>(point.xyz + offset, 1.0);
+    // segments beyond the written part of the ring buffer are fully transparent
+    let alpha = select(0.0, fade * MAX_ALPHA, age < trail.filled);
+    out.color = vec4<f32>(color, alpha);
+    return out;
+}
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    return in.color;
+}
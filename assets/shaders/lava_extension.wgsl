@@ -0,0 +1,45 @@
+#import bevy_pbr::{
+    mesh_view_bindings::globals,
+    pbr_fragment::pbr_input_from_standard_material,
+    pbr_functions::alpha_discard,
+}
+#import bevy_experiments::lava::{LavaSettings, PLANE_SIZE, lava}
+
+#ifdef PREPASS_PIPELINE
+#import bevy_pbr::{
+    prepass_io::{VertexOutput, FragmentOutput},
+    pbr_deferred_functions::deferred_output,
+}
+#else
+#import bevy_pbr::{
+    forward_io::{VertexOutput, FragmentOutput},
+    pbr_functions::{apply_pbr_lighting, main_pass_post_lighting_processing},
+}
+#endif
+
+@group(2) @binding(100) var<uniform> settings: LavaSettings;
+
+@fragment
+fn fragment(in: VertexOutput, @builtin(front_facing) is_front: bool) -> FragmentOutput {
+    var pbr_input = pbr_input_from_standard_material(in, is_front);
+
+    // repeat the plane every `tile_size` world units along the ground
+    let tile = fract(in.world_position.xz / settings.tile_size) - 0.5;
+    let glow = lava(tile * PLANE_SIZE, globals.time, settings);
+
+    // thin lava lets the base color through as a cooled down crust
+    let thickness = saturate(glow.a);
+    pbr_input.material.base_color = mix(pbr_input.material.base_color, vec4(glow.rgb, 1.0), thickness);
+    pbr_input.material.emissive = vec4(glow.rgb * thickness, 1.0);
+    pbr_input.material.base_color = alpha_discard(pbr_input.material, pbr_input.material.base_color);
+
+#ifdef PREPASS_PIPELINE
+    let out = deferred_output(in, pbr_input);
+#else
+    var out: FragmentOutput;
+    out.color = apply_pbr_lighting(pbr_input);
+    out.color = main_pass_post_lighting_processing(pbr_input, out.color);
+#endif
+
+    return out;
+}
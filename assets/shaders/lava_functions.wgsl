@@ -0,0 +1,106 @@
+#define_import_path bevy_experiments::lava
+
+#import bevy_experiments::noise::simplex
+
+// Based on https://www.shadertoy.com/view/3sySRK by edankwan
+
+struct LavaSettings {
+    palette_offset: vec3<f32>,
+    speed: f32,
+    palette_amplitude: vec3<f32>,
+    scale: f32,
+    palette_frequency: vec3<f32>,
+    distortion: f32,
+    palette_phase: vec3<f32>,
+    octaves: u32,
+    tile_size: f32,
+}
+
+// the blobs move within a plane this wide at scale 1
+const PLANE_SIZE: f32 = 6.0;
+
+fn smooth_union(d1: f32, d2: f32, k: f32) -> f32 {
+    let h = clamp(0.5 + 0.5 * (d2 - d1) / k, 0.0, 1.0);
+    return mix(d2, d1, h) - k * h * (1.0 - h);
+}
+
+fn sphere(p: vec3<f32>, s: f32) -> f32 {
+    return length(p) - s;
+}
+
+fn map(p: vec3<f32>, time: f32) -> f32 {
+    var d: f32 = 2.;
+
+    for (var i: i32 = 0; i < 16; i = i + 1) {
+        let fi: f32 = f32(i);
+        let t: f32 = time * (fract(fi * 412.531 + 0.513) - 0.5) * 2.;
+        d = smooth_union(sphere(p + sin(t + fi * vec3<f32>(52.5126, 64.62744, 632.25)) * vec3<f32>(2., 2., 0.8), mix(0.5, 1., fract(fi * 412.531 + 0.5124))), d, 0.4);
+    }
+
+    return d;
+}
+
+fn calc_normal(p: vec3<f32>, time: f32) -> vec3<f32> {
+    let h: f32 = 0.00001;
+    let k: vec2<f32> = vec2<f32>(1., -1.);
+    return normalize(k.xyy * map(p + k.xyy * h, time) + k.yyx * map(p + k.yyx * h, time) + k.yxy * map(p + k.yxy * h, time) + k.xxx * map(p + k.xxx * h, time));
+}
+
+// fractal simplex noise, every octave has double the frequency and half the amplitude
+fn fbm(position: vec3<f32>, octaves: u32, seed: u32) -> f32 {
+    var value = 0.0;
+    var amplitude = 0.5;
+    var p = position;
+    for (var i = 0u; i < octaves; i++) {
+        value += amplitude * simplex(p, seed);
+        p *= 2.0;
+        amplitude *= 0.5;
+    }
+    return value;
+}
+
+// cosine palette, see https://iquilezles.org/articles/palettes
+fn palette(x: vec3<f32>, settings: LavaSettings) -> vec3<f32> {
+    return settings.palette_offset
+        + settings.palette_amplitude * cos(settings.palette_frequency * x + settings.palette_phase);
+}
+
+// The lava at `coord`, centered in the plane the blobs move in. The alpha is the thickness of
+// the lava, up to 2m.
+fn lava(coord: vec2<f32>, time: f32, settings: LavaSettings) -> vec4<f32> {
+    let t = time * settings.speed;
+    var plane = coord / settings.scale;
+    if settings.distortion > 0.0 {
+        // warp the plane with slowly changing noise, so the blobs lose their round outlines
+        let sample = vec3(plane * 0.5, t * 0.2);
+        plane += settings.distortion * vec2(
+            fbm(sample, settings.octaves, 0u),
+            fbm(sample, settings.octaves, 1u),
+        );
+    }
+    let uv = plane / PLANE_SIZE + 0.5;
+
+    let ray_origin = vec3(plane, 3.0);
+    let ray_dir = vec3(0.0, 0.0, -1.0);
+
+    var depth = 0.0;
+    var p = vec3<f32>();
+
+    for (var i = 0; i < 64; i++) {
+        p = ray_origin + ray_dir * depth;
+        let dist = map(p, t);
+        depth += dist;
+        if dist < 1e-6 {
+            break;
+        }
+    }
+
+    depth = min(6.0, depth);
+    let n = calc_normal(p, t);
+    let b = max(0.0, dot(n, vec3(0.577)));
+    var col = palette(vec3(b + t * 3.0) + uv.xyx * 2.0, settings) * (0.85 + b * 0.35);
+    col *= exp(-depth * 0.15);
+
+    // maximum thickness is 2m in alpha channel
+    return vec4(col, 1.0 - (depth - 0.5) / 2.0);
+}
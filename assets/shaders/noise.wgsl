@@ -0,0 +1,104 @@
+#define_import_path bevy_experiments::noise
+
+// Seeded 3D simplex and curl noise, matching `math::noise` on the CPU. The `pinned_values` test
+// there lists reference values to compare against.
+
+const CURL_EPSILON: f32 = 1e-2;
+
+// PCG based integer hash, from "Hash Functions for GPU Rendering" by Jarzynski and Olano
+fn pcg_hash(input: u32) -> u32 {
+    let state = input * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+// The edge midpoints of a cube, as in Ken Perlin's improved noise
+fn gradient(cell: vec3<i32>, seed: u32) -> vec3<f32> {
+    let hash = pcg_hash(
+        bitcast<u32>(cell.x) ^ pcg_hash(bitcast<u32>(cell.y) ^ pcg_hash(bitcast<u32>(cell.z) ^ pcg_hash(seed)))
+    );
+    switch hash % 12u {
+        case 0u: { return vec3<f32>(1.0, 1.0, 0.0); }
+        case 1u: { return vec3<f32>(-1.0, 1.0, 0.0); }
+        case 2u: { return vec3<f32>(1.0, -1.0, 0.0); }
+        case 3u: { return vec3<f32>(-1.0, -1.0, 0.0); }
+        case 4u: { return vec3<f32>(1.0, 0.0, 1.0); }
+        case 5u: { return vec3<f32>(-1.0, 0.0, 1.0); }
+        case 6u: { return vec3<f32>(1.0, 0.0, -1.0); }
+        case 7u: { return vec3<f32>(-1.0, 0.0, -1.0); }
+        case 8u: { return vec3<f32>(0.0, 1.0, 1.0); }
+        case 9u: { return vec3<f32>(0.0, -1.0, 1.0); }
+        case 10u: { return vec3<f32>(0.0, 1.0, -1.0); }
+        default: { return vec3<f32>(0.0, -1.0, -1.0); }
+    }
+}
+
+fn corner_contribution(offset: vec3<f32>, cell: vec3<i32>, seed: u32) -> f32 {
+    let falloff = 0.6 - dot(offset, offset);
+    if falloff <= 0.0 {
+        return 0.0;
+    }
+    let falloff2 = falloff * falloff;
+    return falloff2 * falloff2 * dot(gradient(cell, seed), offset);
+}
+
+// 3D simplex noise in roughly [-1, 1], different seeds give unrelated noise
+fn simplex(position: vec3<f32>, seed: u32) -> f32 {
+    let skew = 1.0 / 3.0;
+    let unskew = 1.0 / 6.0;
+
+    // find the simplex the position is in, and the offsets to its corners
+    let cell = floor(position + (position.x + position.y + position.z) * skew);
+    let offset0 = position - (cell - (cell.x + cell.y + cell.z) * unskew);
+    var step1: vec3<i32>;
+    var step2: vec3<i32>;
+    if offset0.x >= offset0.y {
+        if offset0.y >= offset0.z {
+            step1 = vec3<i32>(1, 0, 0);
+            step2 = vec3<i32>(1, 1, 0);
+        } else if offset0.x >= offset0.z {
+            step1 = vec3<i32>(1, 0, 0);
+            step2 = vec3<i32>(1, 0, 1);
+        } else {
+            step1 = vec3<i32>(0, 0, 1);
+            step2 = vec3<i32>(1, 0, 1);
+        }
+    } else if offset0.y < offset0.z {
+        step1 = vec3<i32>(0, 0, 1);
+        step2 = vec3<i32>(0, 1, 1);
+    } else if offset0.x < offset0.z {
+        step1 = vec3<i32>(0, 1, 0);
+        step2 = vec3<i32>(0, 1, 1);
+    } else {
+        step1 = vec3<i32>(0, 1, 0);
+        step2 = vec3<i32>(1, 1, 0);
+    }
+    let offset1 = offset0 - vec3<f32>(step1) + unskew;
+    let offset2 = offset0 - vec3<f32>(step2) + 2.0 * unskew;
+    let offset3 = offset0 - 1.0 + 3.0 * unskew;
+
+    let corner = vec3<i32>(cell);
+    return 32.0 * (corner_contribution(offset0, corner, seed)
+        + corner_contribution(offset1, corner + step1, seed)
+        + corner_contribution(offset2, corner + step2, seed)
+        + corner_contribution(offset3, corner + vec3<i32>(1), seed));
+}
+
+// Central difference of the simplex noise field seeded with `seed + axis`, along `direction`
+fn potential_derivative(position: vec3<f32>, seed: u32, axis: u32, direction: vec3<f32>) -> f32 {
+    let offset = direction * CURL_EPSILON;
+    return (simplex(position + offset, seed + axis) - simplex(position - offset, seed + axis))
+        / (2.0 * CURL_EPSILON);
+}
+
+// Divergence free noise, the curl of three simplex noise fields seeded from `seed`
+fn curl(position: vec3<f32>, seed: u32) -> vec3<f32> {
+    let x = vec3<f32>(1.0, 0.0, 0.0);
+    let y = vec3<f32>(0.0, 1.0, 0.0);
+    let z = vec3<f32>(0.0, 0.0, 1.0);
+    return vec3<f32>(
+        potential_derivative(position, seed, 2u, y) - potential_derivative(position, seed, 1u, z),
+        potential_derivative(position, seed, 0u, z) - potential_derivative(position, seed, 2u, x),
+        potential_derivative(position, seed, 1u, x) - potential_derivative(position, seed, 0u, y),
+    );
+}
@@ -0,0 +1,155 @@
+#import bevy_experiments::noise::{curl, pcg_hash}
+
+const TAU: f32 = 6.28318531;
+
+struct Emitter {
+    // Rotates the y axis onto the center of the velocity cone in world space
+    cone: mat3x3f,
+    origin: vec3f,
+    cos_spread: f32,
+    acceleration: vec3f,
+    drag: f32,
+    speed: f32,
+    speed_variation: f32,
+    lifetime: f32,
+    delta_time: f32,
+    // The particles in [emit_start, emit_start + emit_count) of the ring buffer are emitted this frame
+    emit_start: u32,
+    emit_count: u32,
+    capacity: u32,
+    seed: u32,
+    sort_origin: vec3f,
+    // Capacity rounded up to a power of two
+    sort_count: u32,
+    wind: vec3f,
+    buoyancy: f32,
+    turbulence: f32,
+    turbulence_scale: f32,
+    noise_seed: u32,
+};
+
+struct SortEntry {
+    distance: f32,
+    index: u32,
+};
+
+// A single compare and swap pass of the bitonic sort
+struct SortStep {
+    block: u32,
+    stride: u32,
+};
+
+@group(0) @binding(0) var<uniform> emitter: Emitter;
+
+// xyz is the position, w the remaining lifetime in seconds, particles with w <= 0 are dead
+@group(1) @binding(0) var position_map: texture_storage_2d<rgba32float, read_write>;
+// xyz is the velocity, w the rotation around the axis towards the camera
+@group(1) @binding(1) var velocity_map: texture_storage_2d<rgba32float, read_write>;
+
+// Sorted back to front by `sort_step`, read by the vertex shader in particles_material.wgsl
+@group(2) @binding(0) var<storage, read_write> sort_entries: array<SortEntry>;
+@group(2) @binding(1) var<uniform> sort_pass: SortStep;
+
+// Particle i is stored at texel (i % width, i / width), matching the lookup in particles_material.wgsl
+fn texel_from_index(index: u32) -> vec2u {
+    let width = textureDimensions(position_map).x;
+    return vec2u(index % width, index / width);
+}
+
+// Uniformly distributed in [0, 1), advancing `state`
+fn random(state: ptr<function, u32>) -> f32 {
+    *state = pcg_hash(*state);
+    return f32(*state >> 8u) / 16777216.0;
+}
+
+// Uniform over the spherical cap of the cone, like `ParticleEmitter::initial_velocity`
+fn initial_velocity(state: ptr<function, u32>) -> vec3f {
+    let cos_theta = mix(emitter.cos_spread, 1.0, random(state));
+    let sin_theta = sqrt(max(1.0 - cos_theta * cos_theta, 0.0));
+    let phi = random(state) * TAU;
+    let local = vec3f(sin_theta * cos(phi), cos_theta, sin_theta * sin(phi));
+
+    let speed = emitter.speed * (1.0 + (random(state) * 2.0 - 1.0) * emitter.speed_variation);
+    return emitter.cone * local * speed;
+}
+
+// The new position and velocity, like `ParticleEmitter::integrate_velocity`
+fn integrate(position: vec3f, velocity: vec3f, life: f32, delta_time: f32) -> array<vec3f, 2> {
+    var acceleration = emitter.acceleration + vec3f(0.0, emitter.buoyancy * (1.0 - life), 0.0);
+    if emitter.turbulence != 0.0 {
+        acceleration += curl(position / emitter.turbulence_scale, emitter.noise_seed) * emitter.turbulence;
+    }
+    let accelerated = velocity + acceleration * delta_time;
+    let new_velocity = emitter.wind + (accelerated - emitter.wind) * max(1.0 - emitter.drag * delta_time, 0.0);
+    return array<vec3f, 2>(position + new_velocity * delta_time, new_velocity);
+}
+
+@compute @workgroup_size(#{WORKGROUP_SIZE}, 1, 1)
+fn update(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    let index = invocation_id.x;
+    if index >= emitter.capacity {
+        return;
+    }
+
+    let texel = texel_from_index(index);
+    let offset = (index + emitter.capacity - emitter.emit_start) % emitter.capacity;
+
+    if offset < emitter.emit_count {
+        var state = pcg_hash(index ^ pcg_hash(emitter.seed));
+        let velocity = initial_velocity(&state);
+        let rotation = random(&state) * TAU;
+
+        // spread the particles emitted this frame over the frame, so they don't leave in bursts
+        let age = emitter.delta_time * (f32(offset) + 0.5) / f32(emitter.emit_count);
+        let moved = integrate(emitter.origin, velocity, 0.0, age);
+        textureStore(position_map, texel, vec4f(moved[0], emitter.lifetime - age));
+        textureStore(velocity_map, texel, vec4f(moved[1], rotation));
+        return;
+    }
+
+    let position = textureLoad(position_map, texel);
+    if position.w <= 0.0 {
+        return;
+    }
+
+    let velocity = textureLoad(velocity_map, texel);
+    let life = 1.0 - position.w / emitter.lifetime;
+    let moved = integrate(position.xyz, velocity.xyz, life, emitter.delta_time);
+    textureStore(position_map, texel, vec4f(moved[0], position.w - emitter.delta_time));
+    textureStore(velocity_map, texel, vec4f(moved[1], velocity.w));
+}
+
+@compute @workgroup_size(#{WORKGROUP_SIZE}, 1, 1)
+fn sort_keys(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    let index = invocation_id.x;
+    if index >= emitter.sort_count {
+        return;
+    }
+
+    // dead particles are sorted after the living ones, and the padding after those, so the first
+    // `capacity` entries are exactly the particles in the pool
+    var distance = -2.0;
+    if index < emitter.capacity {
+        let position = textureLoad(position_map, texel_from_index(index));
+        distance = select(-1.0, length(position.xyz - emitter.sort_origin), position.w > 0.0);
+    }
+    sort_entries[index] = SortEntry(distance, index);
+}
+
+@compute @workgroup_size(#{WORKGROUP_SIZE}, 1, 1)
+fn sort_step(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    let index = invocation_id.x;
+    let other = index ^ sort_pass.stride;
+    if index >= emitter.sort_count || other <= index {
+        return;
+    }
+
+    // alternate blocks are sorted in opposite directions, which makes the final block descending
+    let descending = (index & sort_pass.block) == 0u;
+    let a = sort_entries[index];
+    let b = sort_entries[other];
+    if (a.distance < b.distance) == descending {
+        sort_entries[index] = b;
+        sort_entries[other] = a;
+    }
+}
@@ -0,0 +1,124 @@
+#import bevy_pbr::{
+    mesh_view_bindings::view,
+    prepass_utils::prepass_depth,
+    view_transformations::{depth_ndc_to_view_z, position_world_to_clip},
+}
+
+struct Vertex {
+    @builtin(instance_index) instance_index: u32,
+    @location(0) position: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) color: vec4<f32>,
+    @location(2) life: f32,
+};
+
+struct ParticleRender {
+    // The size over life samples, packed four to a vector
+    sizes: array<vec4<f32>, 4>,
+    colors: array<vec4<f32>, 16>,
+    lifetime: f32,
+    soft_distance: f32,
+    // Whether the particles are colored from `color_gradient` instead of `colors`
+    baked_gradient: u32,
+};
+
+struct SortEntry {
+    distance: f32,
+    index: u32,
+};
+
+// Matching `OVER_LIFE_SAMPLES` in render.rs
+const OVER_LIFE_SAMPLES: u32 = 16;
+
+// xyz is the position, w the remaining lifetime in seconds, particles with w <= 0 are dead
+@group(2) @binding(100) var position_texture: texture_2d<f32>;
+// xyz is the velocity, w the rotation around the axis towards the camera
+@group(2) @binding(101) var velocity_texture: texture_2d<f32>;
+@group(2) @binding(102) var<uniform> particle_render: ParticleRender;
+@group(2) @binding(103) var particle_texture: texture_2d<f32>;
+@group(2) @binding(104) var particle_sampler: sampler;
+// The particles sorted back to front by the `sort_step` compute pass
+@group(2) @binding(105) var<storage, read> sorted_particles: array<SortEntry>;
+// The color gradient of the emitter baked over the lifetime, see `BakeTexture` in curve_assets.rs
+@group(2) @binding(106) var color_gradient: texture_1d<f32>;
+@group(2) @binding(107) var color_gradient_sampler: sampler;
+
+// Particle i is stored at texel (i % width, i / width), matching the lookup in particles_compute.wgsl
+fn texel_from_index(index: u32) -> vec2u {
+    let width = textureDimensions(position_texture).x;
+    return vec2u(index % width, index / width);
+}
+
+fn size_sample(index: u32) -> f32 {
+    return particle_render.sizes[index / 4u][index % 4u];
+}
+
+// The neighbouring samples of `life` and the fraction between them
+fn over_life_index(life: f32) -> vec2<f32> {
+    let scaled = saturate(life) * f32(OVER_LIFE_SAMPLES - 1u);
+    let index = min(floor(scaled), f32(OVER_LIFE_SAMPLES - 2u));
+    return vec2<f32>(index, scaled - index);
+}
+
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    var out: VertexOutput;
+    let texel = texel_from_index(sorted_particles[vertex.instance_index].index);
+    let particle = textureLoad(position_texture, texel, 0);
+
+    // collapse dead particles to a point outside of clip space
+    if particle.w <= 0.0 {
+        out.position = vec4<f32>(2.0, 2.0, 2.0, 1.0);
+        return out;
+    }
+
+    let rotation = textureLoad(velocity_texture, texel, 0).w;
+    let life = 1.0 - particle.w / particle_render.lifetime;
+    let over_life = over_life_index(life);
+    let index = u32(over_life.x);
+    let size = mix(size_sample(index), size_sample(index + 1u), over_life.y);
+
+    // the quad lies in the xz plane, turn it to face the camera
+    let corner = vec2<f32>(vertex.position.x, -vertex.position.z) * size;
+    let rotated = vec2<f32>(
+        corner.x * cos(rotation) - corner.y * sin(rotation),
+        corner.x * sin(rotation) + corner.y * cos(rotation),
+    );
+    let right = view.world_from_view[0].xyz;
+    let up = view.world_from_view[1].xyz;
+    let world_position = particle.xyz + right * rotated.x + up * rotated.y;
+
+    out.position = position_world_to_clip(world_position);
+    out.uv = vertex.uv;
+    out.color = mix(
+        particle_render.colors[index],
+        particle_render.colors[index + 1u],
+        over_life.y,
+    );
+    out.life = saturate(life);
+    return out;
+}
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    // sampled outside of the branch, implicit derivatives need uniform control flow
+    let gradient = textureSample(color_gradient, color_gradient_sampler, in.life);
+    let tint = select(in.color, gradient, particle_render.baked_gradient != 0u);
+    var color = textureSample(particle_texture, particle_sampler, in.uv) * tint;
+
+#ifdef DEPTH_PREPASS
+    // fade out where the quad gets close to the scene behind it, instead of cutting through it
+    if particle_render.soft_distance > 0.0 {
+        let scene_z = depth_ndc_to_view_z(prepass_depth(in.position, 0u));
+        let particle_z = depth_ndc_to_view_z(in.position.z);
+        color.a *= saturate((particle_z - scene_z) / particle_render.soft_distance);
+    }
+#endif
+
+    return color;
+}
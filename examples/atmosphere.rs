@@ -4,7 +4,7 @@ use bevy::{
     reflect::TypePath,
     render::render_resource::{AsBindGroup, ShaderRef},
 };
-use bevy_experiments::on_resize_system;
+use bevy_experiments::{atmosphere::AtmosphereShaderPlugin, on_resize_system};
 
 const SHADER_ASSET_PATH: &str = "shaders/atmosphere.wgsl";
 
@@ -12,6 +12,7 @@ fn main() {
     App::new()
         .add_plugins((
             DefaultPlugins,
+            AtmosphereShaderPlugin,
             Material2dPlugin::<CustomMaterial>::default(),
         ))
         .add_systems(Startup, setup)
@@ -1,19 +1,13 @@
-use bevy::sprite::{Material2d, Material2dPlugin};
-use bevy::{
-    prelude::*,
-    reflect::TypePath,
-    render::render_resource::{AsBindGroup, ShaderRef},
+use bevy::prelude::*;
+use bevy_egui::EguiPlugin;
+use bevy_experiments::{
+    lava::{LavaMaterial, LavaPlugin},
+    on_resize_system,
 };
-use bevy_experiments::on_resize_system;
-
-const SHADER_ASSET_PATH: &str = "shaders/lava.wgsl";
 
 fn main() {
     App::new()
-        .add_plugins((
-            DefaultPlugins,
-            Material2dPlugin::<CustomMaterial>::default(),
-        ))
+        .add_plugins((DefaultPlugins, EguiPlugin::default(), LavaPlugin))
         .add_systems(Startup, setup)
         .add_systems(Update, on_resize_system)
         .run();
@@ -22,21 +16,12 @@ fn main() {
 fn setup(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<CustomMaterial>>,
+    mut materials: ResMut<Assets<LavaMaterial>>,
 ) {
     commands.spawn(Camera2d);
     commands.spawn((
         Mesh2d(meshes.add(Rectangle::default())),
-        MeshMaterial2d(materials.add(CustomMaterial {})),
+        MeshMaterial2d(materials.add(LavaMaterial::default())),
         Transform::default().with_scale(Vec3::splat(1.0)),
     ));
 }
-
-impl Material2d for CustomMaterial {
-    fn fragment_shader() -> ShaderRef {
-        SHADER_ASSET_PATH.into()
-    }
-}
-
-#[derive(Asset, TypePath, AsBindGroup, Debug, Clone)]
-struct CustomMaterial {}
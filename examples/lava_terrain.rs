@@ -0,0 +1,63 @@
+use bevy::prelude::*;
+use bevy_egui::EguiPlugin;
+use bevy_experiments::{
+    boids::terrain::{build_terrain_mesh, generate_heights, TerrainConfig},
+    lava::{LavaExtension, LavaMeshMaterial, LavaPlugin, LavaSettings},
+};
+use bevy_panorbit_camera::{PanOrbitCamera, PanOrbitCameraPlugin};
+
+fn main() {
+    App::new()
+        .add_plugins((DefaultPlugins, EguiPlugin::default(), LavaPlugin))
+        .add_plugins(PanOrbitCameraPlugin)
+        .add_systems(Startup, setup)
+        .run();
+}
+
+/// set up the low poly terrain of the boids, covered in lava
+fn setup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<LavaMeshMaterial>>,
+) {
+    // the terrain lies at the bottom of the bounding box of the boids
+    let focus = Vec3::new(0.0, TerrainConfig::BASE_HEIGHT, 0.0);
+    commands.spawn((
+        Camera3d::default(),
+        Transform::from_translation(focus + Vec3::new(-600.0, 400.0, 600.0))
+            .looking_at(focus, Vec3::Y),
+        PanOrbitCamera {
+            focus,
+            button_pan: MouseButton::Middle,
+            button_orbit: MouseButton::Left,
+            ..Default::default()
+        },
+    ));
+
+    commands.spawn((
+        DirectionalLight::default(),
+        Transform::from_xyz(1.0, 2.0, 1.0).looking_at(Vec3::ZERO, Vec3::Y),
+    ));
+
+    let config = TerrainConfig {
+        height_scale: 150.0,
+        ..default()
+    };
+    let heights = generate_heights(&config);
+    commands.spawn((
+        Mesh3d(meshes.add(build_terrain_mesh(&config, &heights))),
+        MeshMaterial3d(materials.add(LavaMeshMaterial {
+            base: StandardMaterial {
+                perceptual_roughness: 1.0,
+                ..default()
+            },
+            extension: LavaExtension {
+                settings: LavaSettings {
+                    tile_size: 250.0,
+                    distortion: 0.5,
+                    ..default()
+                },
+            },
+        })),
+    ));
+}
@@ -0,0 +1,49 @@
+//! Drives the boids remote control API, start the app with `cargo run --example boids -- --remote`
+//! before running this example.
+
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+};
+
+use bevy_experiments::boids::remote::DEFAULT_REMOTE_PORT;
+
+fn request(method: &str, path: &str, body: &str) -> std::io::Result<String> {
+    let mut stream = TcpStream::connect(("127.0.0.1", DEFAULT_REMOTE_PORT))?;
+    write!(
+        stream,
+        "{method} {path} HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    let (status, body) = response.split_once("\r\n\r\n").unwrap_or((&response, ""));
+    println!(
+        "{method} {path} -> {}",
+        status.lines().next().unwrap_or_default()
+    );
+    Ok(body.to_string())
+}
+
+fn main() -> std::io::Result<()> {
+    println!("{}", request("GET", "/config", "")?);
+    println!(
+        "{}",
+        request(
+            "PUT",
+            "/config",
+            r#"{"max_speed": 2.0, "color_mode": "Speed"}"#
+        )?
+    );
+
+    println!("{}", request("POST", "/pause", "")?);
+    println!("{}", request("POST", "/step", r#"{"frames": 10}"#)?);
+
+    let snapshot = request("POST", "/snapshot", "")?;
+    println!("snapshot of {} bytes", snapshot.len());
+
+    println!("{}", request("POST", "/resume", "")?);
+    Ok(())
+}
@@ -0,0 +1,444 @@
+//! Lookup tables the sky shader samples instead of ray marching towards the sun, baked on the
+//! CPU in the background whenever the atmosphere changes, after "A Scalable and Production Ready Sky and
+//! Atmosphere Rendering Technique" by Sébastien Hillaire.
+//!
+//! Both tables are indexed by the cosine of the angle between the up direction and the sun on
+//! the x axis, from -1 to 1, and by the height above the ground on the y axis, from the ground to
+//! the top of the atmosphere.
+
+use std::f32::consts::PI;
+
+use bevy::{
+    prelude::*,
+    render::{
+        render_asset::RenderAssetUsages,
+        render_resource::{Extent3d, TextureDimension, TextureFormat},
+    },
+};
+use bevy_egui::egui::{self, Ui};
+use half::f16;
+
+use super::AtmosphereSettings;
+
+/// Presets for the resolution of the lookup tables and the samples taken to bake them
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AtmosphereQuality {
+    Low,
+    Medium,
+    #[default]
+    High,
+    Ultra,
+}
+
+impl AtmosphereQuality {
+    pub const ALL: [AtmosphereQuality; 4] = [
+        AtmosphereQuality::Low,
+        AtmosphereQuality::Medium,
+        AtmosphereQuality::High,
+        AtmosphereQuality::Ultra,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            AtmosphereQuality::Low => "Low",
+            AtmosphereQuality::Medium => "Medium",
+            AtmosphereQuality::High => "High",
+            AtmosphereQuality::Ultra => "Ultra",
+        }
+    }
+
+    pub fn transmittance_size(self) -> UVec2 {
+        match self {
+            AtmosphereQuality::Low => UVec2::new(64, 16),
+            AtmosphereQuality::Medium => UVec2::new(128, 32),
+            AtmosphereQuality::High => UVec2::new(256, 64),
+            AtmosphereQuality::Ultra => UVec2::new(512, 128),
+        }
+    }
+
+    pub fn multiscattering_size(self) -> UVec2 {
+        match self {
+            AtmosphereQuality::Low => UVec2::splat(16),
+            AtmosphereQuality::Medium | AtmosphereQuality::High => UVec2::splat(32),
+            AtmosphereQuality::Ultra => UVec2::splat(64),
+        }
+    }
+
+    /// Steps along every ray while baking
+    pub fn bake_steps(self) -> u32 {
+        match self {
+            AtmosphereQuality::Low => 16,
+            AtmosphereQuality::Medium => 24,
+            AtmosphereQuality::High => 32,
+            AtmosphereQuality::Ultra => 48,
+        }
+    }
+
+    /// The multiple scattering is gathered from this many squared directions around every texel
+    pub fn multiscattering_directions(self) -> u32 {
+        match self {
+            AtmosphereQuality::Low => 4,
+            AtmosphereQuality::Medium => 6,
+            AtmosphereQuality::High | AtmosphereQuality::Ultra => 8,
+        }
+    }
+}
+
+pub fn atmosphere_quality_ui(quality: &mut AtmosphereQuality, ui: &mut Ui) {
+    ui.label("Sky quality");
+    egui::ComboBox::from_id_salt("atmosphere_quality")
+        .selected_text(quality.label())
+        .show_ui(ui, |ui| {
+            for preset in AtmosphereQuality::ALL {
+                ui.selectable_value(quality, preset, preset.label());
+            }
+        });
+    ui.end_row();
+}
+
+/// Densities of the air, the haze and the ozone at `height` relative to the ground
+pub fn densities(settings: &AtmosphereSettings, height: f32) -> Vec3 {
+    let rayleigh = (-height / settings.rayleigh_height).exp();
+    let mie = (-height / settings.mie_height).exp();
+    // the ozone scales with the air, but peaks in a layer high up
+    let ozone = (settings.absorption_height - height) / settings.absorption_falloff;
+    Vec3::new(rayleigh, mie, rayleigh / (ozone * ozone + 1.0))
+}
+
+/// Light lost per meter at `height`, to scattering and absorption
+pub fn extinction(settings: &AtmosphereSettings, height: f32) -> Vec3 {
+    let density = densities(settings, height);
+    settings.rayleigh_beta * density.x
+        + settings.mie_beta * density.y
+        + settings.absorption_beta * density.z
+}
+
+/// Light scattered per meter at `height`
+pub fn scattering(settings: &AtmosphereSettings, height: f32) -> Vec3 {
+    let density = densities(settings, height);
+    settings.rayleigh_beta * density.x + settings.mie_beta * density.y
+}
+
+/// Distance from a point at `radius` from the planet center to the sphere of `sphere_radius`
+/// along a ray at `mu`, the cosine of the angle with the up direction
+fn distance_to_sphere(radius: f32, mu: f32, sphere_radius: f32) -> Option<f32> {
+    let discriminant = radius * radius * (mu * mu - 1.0) + sphere_radius * sphere_radius;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let far = -radius * mu + discriminant.sqrt();
+    let near = -radius * mu - discriminant.sqrt();
+    if near > 0.0 {
+        Some(near)
+    } else if far > 0.0 {
+        Some(far)
+    } else {
+        None
+    }
+}
+
+/// Whether a ray at `mu` from `radius` hits the ground before leaving the atmosphere
+fn hits_ground(radius: f32, mu: f32, planet_radius: f32) -> bool {
+    mu < 0.0 && radius * radius * (mu * mu - 1.0) + planet_radius * planet_radius >= 0.0
+}
+
+/// A table of colors, sampled with the same coordinates as in the shader
+#[derive(Clone, Debug, PartialEq)]
+pub struct Lut {
+    pub size: UVec2,
+    pub texels: Vec<Vec3>,
+}
+
+impl Lut {
+    /// Evaluates `texel` at the center of every texel, given its `(mu, height)`
+    fn bake(settings: &AtmosphereSettings, size: UVec2, texel: impl Fn(f32, f32) -> Vec3) -> Self {
+        let thickness = settings.atmosphere_radius - settings.planet_radius;
+        let texels = (0..size.y)
+            .flat_map(|y| (0..size.x).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let mu = (x as f32 + 0.5) / size.x as f32 * 2.0 - 1.0;
+                let height = (y as f32 + 0.5) / size.y as f32 * thickness;
+                texel(mu, height)
+            })
+            .collect();
+        Self { size, texels }
+    }
+
+    fn texel(&self, x: u32, y: u32) -> Vec3 {
+        self.texels[(y * self.size.x + x) as usize]
+    }
+
+    /// Bilinear sample, clamped to the edges of the table
+    pub fn sample(&self, settings: &AtmosphereSettings, mu: f32, height: f32) -> Vec3 {
+        let thickness = settings.atmosphere_radius - settings.planet_radius;
+        let uv = Vec2::new(0.5 + 0.5 * mu, height / thickness).clamp(Vec2::ZERO, Vec2::ONE);
+        let max = (self.size - 1).as_vec2();
+        let position = (uv * self.size.as_vec2() - 0.5).clamp(Vec2::ZERO, max);
+        let low = position.floor().as_uvec2();
+        let high = (low + 1).min(self.size - 1);
+        let t = position.fract();
+        let top = self
+            .texel(low.x, low.y)
+            .lerp(self.texel(high.x, low.y), t.x);
+        let bottom = self
+            .texel(low.x, high.y)
+            .lerp(self.texel(high.x, high.y), t.x);
+        top.lerp(bottom, t.y)
+    }
+
+    pub fn to_image(&self) -> Image {
+        let data = self
+            .texels
+            .iter()
+            .flat_map(|texel| texel.extend(1.0).to_array())
+            .flat_map(|value| f16::from_f32(value).to_bits().to_le_bytes())
+            .collect();
+        Image::new(
+            Extent3d {
+                width: self.size.x,
+                height: self.size.y,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            data,
+            TextureFormat::Rgba16Float,
+            RenderAssetUsages::RENDER_WORLD,
+        )
+    }
+}
+
+/// Fraction of sunlight that reaches `height` from the direction at `mu`, zero where the
+/// planet is in the way
+pub fn bake_transmittance(settings: &AtmosphereSettings, size: UVec2, steps: u32) -> Lut {
+    Lut::bake(settings, size, |mu, height| {
+        let radius = settings.planet_radius + height;
+        if hits_ground(radius, mu, settings.planet_radius) {
+            return Vec3::ZERO;
+        }
+        let Some(distance) = distance_to_sphere(radius, mu, settings.atmosphere_radius) else {
+            return Vec3::ONE;
+        };
+        let step = distance / steps as f32;
+        let origin = Vec2::new(0.0, radius);
+        let direction = Vec2::new((1.0 - mu * mu).max(0.0).sqrt(), mu);
+        let optical_depth = (0..steps)
+            .map(|i| {
+                let sample = origin + direction * (i as f32 + 0.5) * step;
+                extinction(settings, sample.length() - settings.planet_radius) * step
+            })
+            .sum::<Vec3>();
+        (-optical_depth).exp()
+    })
+}
+
+/// Light scattered more than once towards a point at `height`, per unit of sunlight and
+/// scattering coefficient, with the sun at `mu`.
+///
+/// Gathers the light scattered once from all directions around the point, and assumes every
+/// further order scatters the same fraction again, which sums to a geometric series.
+pub fn bake_multiscattering(
+    settings: &AtmosphereSettings,
+    transmittance: &Lut,
+    size: UVec2,
+    directions: u32,
+    steps: u32,
+) -> Lut {
+    let isotropic_phase = 1.0 / (4.0 * PI);
+    let sample_directions: Vec<Vec3> = (0..directions)
+        .flat_map(|i| (0..directions).map(move |j| (i, j)))
+        .map(|(i, j)| {
+            let cos_theta = 1.0 - 2.0 * (i as f32 + 0.5) / directions as f32;
+            let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+            let phi = 2.0 * PI * (j as f32 + 0.5) / directions as f32;
+            Vec3::new(sin_theta * phi.cos(), cos_theta, sin_theta * phi.sin())
+        })
+        .collect();
+
+    Lut::bake(settings, size, |mu, height| {
+        let origin = Vec3::new(0.0, settings.planet_radius + height, 0.0);
+        let sun = Vec3::new((1.0 - mu * mu).max(0.0).sqrt(), mu, 0.0);
+
+        let mut second_order = Vec3::ZERO;
+        let mut transfer = Vec3::ZERO;
+        for &direction in &sample_directions {
+            let radius = origin.length();
+            let ray_mu = direction.y;
+            let boundary = if hits_ground(radius, ray_mu, settings.planet_radius) {
+                settings.planet_radius
+            } else {
+                settings.atmosphere_radius
+            };
+            let Some(distance) = distance_to_sphere(radius, ray_mu, boundary) else {
+                continue;
+            };
+            let step = distance / steps as f32;
+
+            let mut view_transmittance = Vec3::ONE;
+            for i in 0..steps {
+                let sample = origin + direction * (i as f32 + 0.5) * step;
+                let sample_radius = sample.length();
+                let sample_height = sample_radius - settings.planet_radius;
+                let sun_mu = (sample / sample_radius).dot(sun);
+
+                let scattered = scattering(settings, sample_height) * step;
+                let sun_transmittance = transmittance.sample(settings, sun_mu, sample_height);
+                second_order +=
+                    view_transmittance * scattered * sun_transmittance * isotropic_phase;
+                transfer += view_transmittance * scattered;
+                view_transmittance *= (-extinction(settings, sample_height) * step).exp();
+            }
+        }
+
+        let count = sample_directions.len() as f32;
+        let second_order = second_order / count;
+        let transfer = (transfer / count).min(Vec3::splat(0.99));
+        second_order / (1.0 - transfer)
+    })
+}
+
+/// The baked tables, kept under the same handles so the sky material picks up new bakes
+#[derive(Resource)]
+pub struct AtmosphereLuts {
+    pub transmittance: Handle<Image>,
+    pub multiscattering: Handle<Image>,
+}
+
+impl FromWorld for AtmosphereLuts {
+    fn from_world(world: &mut World) -> Self {
+        let images = world.resource::<Assets<Image>>();
+        Self {
+            transmittance: images.reserve_handle(),
+            multiscattering: images.reserve_handle(),
+        }
+    }
+}
+
+/// Whether the tables baked for `a` also hold for `b`, the sun and camera don't affect them
+pub fn same_luts(a: &AtmosphereSettings, b: &AtmosphereSettings) -> bool {
+    a.planet_radius == b.planet_radius
+        && a.atmosphere_radius == b.atmosphere_radius
+        && a.rayleigh_beta == b.rayleigh_beta
+        && a.mie_beta == b.mie_beta
+        && a.absorption_beta == b.absorption_beta
+        && a.rayleigh_height == b.rayleigh_height
+        && a.mie_height == b.mie_height
+        && a.absorption_height == b.absorption_height
+        && a.absorption_falloff == b.absorption_falloff
+}
+
+/// The transmittance and multiple scattering tables for `settings`, which takes long enough that
+/// it runs on the [`AsyncComputeTaskPool`](bevy::tasks::AsyncComputeTaskPool)
+pub fn bake_luts(settings: &AtmosphereSettings, quality: AtmosphereQuality) -> (Image, Image) {
+    let transmittance =
+        bake_transmittance(settings, quality.transmittance_size(), quality.bake_steps());
+    let multiscattering = bake_multiscattering(
+        settings,
+        &transmittance,
+        quality.multiscattering_size(),
+        quality.multiscattering_directions(),
+        quality.bake_steps(),
+    );
+    (transmittance.to_image(), multiscattering.to_image())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The `(mu, height)` at the center of every texel, in the order of [`Lut::texels`]
+    fn texel_coordinates(settings: &AtmosphereSettings, size: UVec2) -> Vec<(f32, f32)> {
+        Lut::bake(settings, size, |mu, height| Vec3::new(mu, height, 0.0))
+            .texels
+            .iter()
+            .map(|texel| (texel.x, texel.y))
+            .collect()
+    }
+
+    fn transmittance(quality: AtmosphereQuality) -> Lut {
+        bake_transmittance(
+            &AtmosphereSettings::default(),
+            quality.transmittance_size(),
+            quality.bake_steps(),
+        )
+    }
+
+    #[test]
+    fn transmittance_is_clear_looking_up_from_the_top() {
+        let settings = AtmosphereSettings::default();
+        let thickness = settings.atmosphere_radius - settings.planet_radius;
+        for quality in AtmosphereQuality::ALL {
+            let lut = transmittance(quality);
+            let top = lut.sample(&settings, 1.0, thickness);
+            assert!(top.abs_diff_eq(Vec3::ONE, 1e-3), "{quality:?}: {top}");
+        }
+    }
+
+    #[test]
+    fn transmittance_is_zero_through_the_ground() {
+        let settings = AtmosphereSettings::default();
+        let quality = AtmosphereQuality::High;
+        let lut = transmittance(quality);
+        let coordinates = texel_coordinates(&settings, quality.transmittance_size());
+
+        let mut blocked = 0;
+        for (&texel, &(mu, height)) in lut.texels.iter().zip(&coordinates) {
+            let radius = settings.planet_radius + height;
+            if hits_ground(radius, mu, settings.planet_radius) {
+                assert_eq!(texel, Vec3::ZERO, "mu {mu}, height {height}");
+                blocked += 1;
+            } else {
+                assert!(texel.cmpgt(Vec3::ZERO).all(), "mu {mu}, height {height}");
+            }
+        }
+        // most of the rays looking down from low in the atmosphere hit the ground
+        assert!(blocked >= lut.size.x / 2);
+    }
+
+    #[test]
+    fn transmittance_drops_with_the_sun() {
+        for quality in AtmosphereQuality::ALL {
+            let lut = transmittance(quality);
+            for texel in &lut.texels {
+                assert!(
+                    texel.cmpge(Vec3::ZERO).all() && texel.cmple(Vec3::ONE).all(),
+                    "{quality:?}: {texel}"
+                );
+            }
+            // mu grows along the rows, so the sun sets towards the start of a row
+            for y in 0..lut.size.y {
+                for x in 1..lut.size.x {
+                    let lower = lut.texel(x - 1, y);
+                    let higher = lut.texel(x, y);
+                    assert!(
+                        lower.cmple(higher + 1e-6).all(),
+                        "{quality:?} at ({x}, {y}): {lower} > {higher}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn multiscattering_is_finite_and_positive() {
+        let settings = AtmosphereSettings::default();
+        for quality in AtmosphereQuality::ALL {
+            let lut = bake_multiscattering(
+                &settings,
+                &transmittance(quality),
+                quality.multiscattering_size(),
+                quality.multiscattering_directions(),
+                quality.bake_steps(),
+            );
+            assert_eq!(
+                lut.texels.len() as u32,
+                quality.multiscattering_size().element_product()
+            );
+            for texel in &lut.texels {
+                assert!(
+                    texel.is_finite() && texel.cmpge(Vec3::ZERO).all(),
+                    "{quality:?}: {texel}"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,302 @@
+//! The Rayleigh and Mie scattering of `examples/atmosphere.rs`, drawn as the sky behind 3D
+//! cameras and lit by the [`DirectionalLight`] of the scene.
+//!
+//! The light towards the sun and the light scattered more than once come from lookup tables in
+//! [`lut`], so the sky only ray marches along the view ray.
+
+pub mod lut;
+
+use bevy::{
+    asset::{load_internal_asset, weak_handle},
+    pbr::{MaterialPipeline, MaterialPipelineKey, NotShadowCaster, NotShadowReceiver},
+    prelude::*,
+    render::{
+        mesh::{MeshVertexBufferLayoutRef, PrimitiveTopology},
+        render_asset::RenderAssetUsages,
+        render_resource::{
+            AsBindGroup, RenderPipelineDescriptor, ShaderRef, ShaderType,
+            SpecializedMeshPipelineError,
+        },
+        view::NoFrustumCulling,
+    },
+    tasks::{block_on, poll_once, AsyncComputeTaskPool, Task},
+};
+use bevy_egui::egui::{self, Ui};
+
+use self::lut::{bake_luts, same_luts, AtmosphereLuts, AtmosphereQuality};
+use crate::time_of_day::Moon;
+
+/// The scattering functions, imported as `bevy_experiments::atmosphere`
+pub const ATMOSPHERE_SHADER_HANDLE: Handle<Shader> =
+    weak_handle!("ba979dac-fa1c-487d-84a7-df076ecda0b8");
+
+const SKY_SHADER_ASSET_PATH: &str = "shaders/atmosphere_sky.wgsl";
+
+/// Scattering coefficients are per meter, like all lengths
+#[derive(Resource, ShaderType, Clone, Copy, Debug, PartialEq)]
+pub struct AtmosphereSettings {
+    /// Towards the sun, copied from the first [`DirectionalLight`] that isn't the [`Moon`]
+    pub sun_direction: Vec3,
+    pub sun_intensity: f32,
+    /// Rayleigh scattering, gives the sky its color
+    pub rayleigh_beta: Vec3,
+    pub planet_radius: f32,
+    /// Mie scattering, the haze around the sun
+    pub mie_beta: Vec3,
+    pub atmosphere_radius: f32,
+    /// Light absorbed by the ozone layer
+    pub absorption_beta: Vec3,
+    /// How much Mie scattering prefers the forward direction, the size of the haze
+    pub mie_g: f32,
+    /// Makes the air glow where no sunlight reaches it
+    pub ambient_beta: Vec3,
+    /// Heights over which the density of the air and haze drops by a factor `e`
+    pub rayleigh_height: f32,
+    pub mie_height: f32,
+    /// Height at which the absorption is strongest, and how quickly it drops off around it
+    pub absorption_height: f32,
+    pub absorption_falloff: f32,
+    /// Height of the scene above the planet surface, added to the height of the camera
+    pub altitude: f32,
+    /// Samples along the view ray
+    pub primary_steps: u32,
+}
+
+impl Default for AtmosphereSettings {
+    fn default() -> Self {
+        Self {
+            sun_direction: Vec3::Y,
+            sun_intensity: 40.0,
+            rayleigh_beta: Vec3::new(5.5e-6, 13.0e-6, 22.4e-6),
+            planet_radius: 6371e3,
+            mie_beta: Vec3::splat(21e-6),
+            atmosphere_radius: 6471e3,
+            absorption_beta: Vec3::new(2.04e-5, 4.97e-5, 1.95e-6),
+            mie_g: 0.7,
+            ambient_beta: Vec3::ZERO,
+            rayleigh_height: 8e3,
+            mie_height: 1.2e3,
+            absorption_height: 30e3,
+            absorption_falloff: 4e3,
+            altitude: 0.0,
+            primary_steps: 32,
+        }
+    }
+}
+
+#[derive(Asset, TypePath, AsBindGroup, Clone, Debug, Default)]
+pub struct AtmosphereSkyMaterial {
+    #[uniform(0)]
+    pub settings: AtmosphereSettings,
+    #[texture(1)]
+    #[sampler(2)]
+    pub transmittance: Handle<Image>,
+    #[texture(3)]
+    #[sampler(4)]
+    pub multiscattering: Handle<Image>,
+}
+
+impl Material for AtmosphereSkyMaterial {
+    fn vertex_shader() -> ShaderRef {
+        SKY_SHADER_ASSET_PATH.into()
+    }
+
+    fn fragment_shader() -> ShaderRef {
+        SKY_SHADER_ASSET_PATH.into()
+    }
+
+    fn specialize(
+        _pipeline: &MaterialPipeline<Self>,
+        descriptor: &mut RenderPipelineDescriptor,
+        _layout: &MeshVertexBufferLayoutRef,
+        _key: MaterialPipelineKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        // the sky is on the far plane, so it shouldn't hide anything drawn after it
+        if let Some(depth_stencil) = &mut descriptor.depth_stencil {
+            depth_stencil.depth_write_enabled = false;
+        }
+        descriptor.primitive.cull_mode = None;
+        Ok(())
+    }
+}
+
+/// The entity the sky is drawn with, it covers the screen of every 3D camera
+#[derive(Component)]
+pub struct AtmosphereSky;
+
+/// A single triangle that covers clip space
+fn sky_mesh() -> Mesh {
+    Mesh::new(
+        PrimitiveTopology::TriangleList,
+        RenderAssetUsages::RENDER_WORLD,
+    )
+    .with_inserted_attribute(
+        Mesh::ATTRIBUTE_POSITION,
+        vec![[-1.0, -1.0, 0.0], [3.0, -1.0, 0.0], [-1.0, 3.0, 0.0]],
+    )
+}
+
+fn spawn_sky(
+    mut commands: Commands,
+    settings: Res<AtmosphereSettings>,
+    luts: Res<AtmosphereLuts>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<AtmosphereSkyMaterial>>,
+) {
+    commands.spawn((
+        AtmosphereSky,
+        Mesh3d(meshes.add(sky_mesh())),
+        MeshMaterial3d(materials.add(AtmosphereSkyMaterial {
+            settings: *settings,
+            transmittance: luts.transmittance.clone(),
+            multiscattering: luts.multiscattering.clone(),
+        })),
+        NoFrustumCulling,
+        NotShadowCaster,
+        NotShadowReceiver,
+    ));
+}
+
+fn update_sun_direction(
+    mut settings: ResMut<AtmosphereSettings>,
+    lights: Query<&GlobalTransform, (With<DirectionalLight>, Without<Moon>)>,
+) {
+    if let Some(light) = lights.iter().next() {
+        let sun_direction = -light.forward().as_vec3();
+        if settings.sun_direction != sun_direction {
+            settings.sun_direction = sun_direction;
+        }
+    }
+}
+
+/// Copies the settings into the sky, and bakes the lookup tables again in the background when
+/// the atmosphere or quality changes. The sky keeps the previous tables until the bake is done.
+#[allow(clippy::too_many_arguments)]
+fn update_sky_material(
+    settings: Res<AtmosphereSettings>,
+    quality: Res<AtmosphereQuality>,
+    luts: Res<AtmosphereLuts>,
+    mut baked: Local<Option<(AtmosphereSettings, AtmosphereQuality)>>,
+    mut bake: Local<Option<Task<(Image, Image)>>>,
+    sky: Query<&MeshMaterial3d<AtmosphereSkyMaterial>, With<AtmosphereSky>>,
+    mut materials: ResMut<Assets<AtmosphereSkyMaterial>>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    let mut swapped = false;
+    if let Some(task) = bake.as_mut() {
+        if let Some((transmittance, multiscattering)) = block_on(poll_once(task)) {
+            images.insert(&luts.transmittance, transmittance);
+            images.insert(&luts.multiscattering, multiscattering);
+            *bake = None;
+            swapped = true;
+        }
+    }
+
+    let outdated = match *baked {
+        Some((baked_settings, baked_quality)) => {
+            !same_luts(&baked_settings, &settings) || baked_quality != *quality
+        }
+        None => true,
+    };
+    // a running bake finishes first, instead of restarting on every frame a slider is dragged
+    if outdated && bake.is_none() {
+        let (settings, quality) = (*settings, *quality);
+        *bake =
+            Some(AsyncComputeTaskPool::get().spawn(async move { bake_luts(&settings, quality) }));
+        *baked = Some((settings, quality));
+    }
+    if !settings.is_changed() && !swapped {
+        return;
+    }
+    // touching the material also makes it bind the new tables
+    for material in &sky {
+        if let Some(material) = materials.get_mut(material) {
+            material.settings = *settings;
+        }
+    }
+}
+
+/// Coefficients are shown per 1000 km, so the values are readable
+fn beta_ui(ui: &mut Ui, label: &str, beta: &mut Vec3) {
+    ui.label(label);
+    ui.horizontal(|ui| {
+        let mut scaled = *beta * 1e6;
+        for value in [&mut scaled.x, &mut scaled.y, &mut scaled.z] {
+            ui.add(egui::DragValue::new(value).speed(0.1).range(0.0..=100.0));
+        }
+        if scaled != *beta * 1e6 {
+            *beta = scaled / 1e6;
+        }
+    });
+    ui.end_row();
+}
+
+pub fn atmosphere_ui(settings: &mut AtmosphereSettings, ui: &mut Ui) {
+    ui.label("Sun intensity");
+    ui.add(egui::Slider::new(&mut settings.sun_intensity, 1.0..=100.0));
+    ui.end_row();
+
+    beta_ui(ui, "Rayleigh", &mut settings.rayleigh_beta);
+    beta_ui(ui, "Mie", &mut settings.mie_beta);
+    beta_ui(ui, "Absorption", &mut settings.absorption_beta);
+    beta_ui(ui, "Ambient", &mut settings.ambient_beta);
+
+    ui.label("Mie direction");
+    ui.add(egui::Slider::new(&mut settings.mie_g, 0.0..=0.99));
+    ui.end_row();
+    ui.label("Rayleigh height");
+    ui.add(egui::Slider::new(&mut settings.rayleigh_height, 1e3..=20e3).suffix(" m"));
+    ui.end_row();
+    ui.label("Mie height");
+    ui.add(egui::Slider::new(&mut settings.mie_height, 100.0..=5e3).suffix(" m"));
+    ui.end_row();
+    ui.label("Altitude");
+    ui.add(egui::Slider::new(&mut settings.altitude, 0.0..=50e3).suffix(" m"));
+    ui.end_row();
+    ui.label("Primary steps");
+    ui.add(egui::Slider::new(&mut settings.primary_steps, 1..=64));
+    ui.end_row();
+
+    if ui.button("Reset atmosphere").clicked() {
+        *settings = AtmosphereSettings {
+            sun_direction: settings.sun_direction,
+            ..default()
+        };
+    }
+    ui.end_row();
+}
+
+/// Makes `bevy_experiments::atmosphere` available to shaders, also for the 2D atmosphere
+pub struct AtmosphereShaderPlugin;
+
+impl Plugin for AtmosphereShaderPlugin {
+    fn build(&self, app: &mut App) {
+        load_internal_asset!(
+            app,
+            ATMOSPHERE_SHADER_HANDLE,
+            "../../assets/shaders/atmosphere_functions.wgsl",
+            Shader::from_wgsl
+        );
+    }
+}
+
+pub struct AtmosphereSkyPlugin;
+
+impl Plugin for AtmosphereSkyPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<AtmosphereShaderPlugin>() {
+            app.add_plugins(AtmosphereShaderPlugin);
+        }
+        app.add_plugins(MaterialPlugin::<AtmosphereSkyMaterial> {
+            // the vertex shader ignores the mesh transform, which the prepass would use
+            prepass_enabled: false,
+            shadows_enabled: false,
+            ..default()
+        })
+        .init_resource::<AtmosphereSettings>()
+        .init_resource::<AtmosphereQuality>()
+        .init_resource::<AtmosphereLuts>()
+        .add_systems(Startup, spawn_sky)
+        .add_systems(Update, (update_sun_direction, update_sky_material).chain());
+    }
+}
@@ -1,31 +1,49 @@
-use std::borrow::Cow;
+use std::{borrow::Cow, ops::RangeInclusive};
 
 use bevy::{
     ecs::system::ResMut,
     prelude::*,
     render::{
-        extract_resource::ExtractResourcePlugin,
+        diagnostic::RecordDiagnostics,
+        extract_resource::{ExtractResource, ExtractResourcePlugin},
+        primitives::Frustum,
         render_asset::RenderAssets,
         render_graph::{Node, NodeRunError, RenderGraph, RenderGraphContext, RenderLabel},
         render_resource::{
-            binding_types::uniform_buffer, AsBindGroup, BindGroup, BindGroupEntries,
-            BindGroupLayout, BindGroupLayoutEntries, CachedComputePipelineId, CachedPipelineState,
-            ComputePassDescriptor, ComputePipelineDescriptor, PipelineCache, ShaderStages,
+            binding_types::{storage_buffer_sized, texture_2d, uniform_buffer},
+            AsBindGroup, BindGroup, BindGroupEntries, BindGroupLayout, BindGroupLayoutEntries,
+            CachedComputePipelineId, CachedPipelineState, ComputePassDescriptor,
+            ComputePipelineDescriptor, PipelineCache, ShaderDefVal, ShaderStages,
+            TextureSampleType,
         },
         renderer::{RenderContext, RenderDevice, RenderQueue},
+        storage::GpuShaderStorageBuffer,
         texture::GpuImage,
         Extract, Render, RenderApp, RenderSet,
     },
 };
 
+use crate::capture::{capture_frame, CaptureCamera, FrameCapture};
+
 use super::{
+    color::{BoidsColorMode, DEFAULT_COLOR_RAMP},
+    diagnostics::BOIDS_COMPUTE_SPAN,
     images::IMAGE_SIZE,
-    uniforms::{BoidsImage, BoidsUniform, TerrainUniformBuffer},
+    mesh::BOID_RADIUS,
+    terrain::{TerrainConfig, TerrainHeightmap},
+    trails::{BoidsTrailComputeBindGroup, MAX_TRAIL_LENGTH},
+    uniforms::{
+        BoidsCullUniform, BoidsCullUniformBuffer, BoidsImage, BoidsInstanceBuffers,
+        BoidsTrailUniform, BoidsUniform, FlockReductionBuffers, HeightfieldUniform,
+        HeightfieldUniformBuffer, RouteUniform, RouteUniformBuffer, TerrainUniformBuffer,
+        COLOR_RAMP_SIZE, LOD_COUNT, MAX_WAYPOINTS,
+    },
 };
 
-const WORKGROUP_SIZE: u32 = 8;
+/// Number of invocations per compute workgroup, passed to the shader as the `WORKGROUP_SIZE` shader def
+pub(crate) const WORKGROUP_SIZE: u32 = 64;
 
-#[derive(Resource, Clone, Copy)]
+#[derive(Resource, Reflect, Clone, Copy, PartialEq)]
 pub struct BoidsConfig {
     pub boids_count: u32,
     pub align_range: f32,
@@ -37,6 +55,21 @@ pub struct BoidsConfig {
     pub bounds_margin: f32,
     pub bounds_turn_factor: f32,
     pub max_speed: f32,
+    pub frustum_culling: bool,
+    /// Camera distance beyond which boids are drawn with the medium detail mesh
+    pub lod_medium_distance: f32,
+    /// Camera distance beyond which boids are drawn with the low detail mesh
+    pub lod_low_distance: f32,
+    pub trails_enabled: bool,
+    /// Number of past positions kept per boid for its trail
+    pub trail_length: u32,
+    pub trail_color_by_speed: bool,
+    pub color_mode: BoidsColorMode,
+    pub solid_color: LinearRgba,
+    pub color_ramp: [LinearRgba; COLOR_RAMP_SIZE],
+    /// Neighbor count within the align range at which the density ramp is saturated
+    pub density_max_neighbors: u32,
+    pub species_count: u32,
 }
 
 impl Default for BoidsConfig {
@@ -52,24 +85,120 @@ impl Default for BoidsConfig {
             bounds_margin: 10.0,
             bounds_turn_factor: 0.25,
             max_speed: 1.0,
+            frustum_culling: true,
+            lod_medium_distance: 1500.0,
+            lod_low_distance: 2500.0,
+            trails_enabled: false,
+            trail_length: 16,
+            trail_color_by_speed: true,
+            color_mode: BoidsColorMode::Solid,
+            // css PURPLE in linear space
+            solid_color: LinearRgba::rgb(0.216, 0.0, 0.216),
+            color_ramp: DEFAULT_COLOR_RAMP,
+            density_max_neighbors: 20,
+            species_count: 3,
         }
     }
 }
 
+/// The values the sliders in the boids window offer, the remote API rejects anything outside them
+impl BoidsConfig {
+    pub const BOIDS_COUNT_LIMITS: RangeInclusive<u32> = 1..=IMAGE_SIZE * IMAGE_SIZE;
+    pub const ALIGN_RANGE_LIMITS: RangeInclusive<f32> = 1.0..=100.0;
+    pub const AVOID_RANGE_LIMITS: RangeInclusive<f32> = 0.1..=100.0;
+    pub const CENTERING_RANGE_LIMITS: RangeInclusive<f32> = 0.01..=100.0;
+    pub const FACTOR_LIMITS: RangeInclusive<f32> = 0.0..=10.0;
+    pub const BOUNDS_MARGIN_LIMITS: RangeInclusive<f32> = 0.0..=20.0;
+    pub const BOUNDS_TURN_FACTOR_LIMITS: RangeInclusive<f32> = 0.001..=2.0;
+    pub const MAX_SPEED_LIMITS: RangeInclusive<f32> = 0.1..=20.0;
+    /// Both LOD distances, the medium one can't be further away than the low one
+    pub const LOD_DISTANCE_LIMITS: RangeInclusive<f32> = 0.0..=5000.0;
+    pub const TRAIL_LENGTH_LIMITS: RangeInclusive<u32> = 2..=MAX_TRAIL_LENGTH;
+    pub const DENSITY_MAX_NEIGHBORS_LIMITS: RangeInclusive<u32> = 1..=100;
+    pub const SPECIES_COUNT_LIMITS: RangeInclusive<u32> = 1..=8;
+}
+
+/// Whether the simulation advances, controlled from the UI and the remote API
+#[derive(Resource, Clone, Default, ExtractResource)]
+pub struct BoidsSimulation {
+    pub paused: bool,
+    /// Frames to advance while paused
+    pub pending_steps: u32,
+    /// Incremented to run the init pass again, which scatters the boids like at startup
+    pub restarts: u32,
+    /// Whether the update pass runs this frame, derived from the fields above and whether a
+    /// [`FrameCapture`] is held
+    advances: bool,
+}
+
+impl BoidsSimulation {
+    pub fn restart(&mut self) {
+        self.restarts = self.restarts.wrapping_add(1);
+    }
+
+    pub fn step(&mut self, frames: u32) {
+        self.pending_steps = self.pending_steps.saturating_add(frames);
+    }
+
+    pub fn advances(&self) -> bool {
+        self.advances
+    }
+}
+
+pub(crate) fn advance_simulation(
+    mut simulation: ResMut<BoidsSimulation>,
+    capture: Option<Res<FrameCapture>>,
+) {
+    // the boids step a fixed amount per frame whatever the time does, so they only step on
+    // the frames a capture requests
+    if capture.is_some_and(|capture| capture.is_held()) {
+        simulation.advances = false;
+    } else if simulation.paused {
+        simulation.advances = simulation.pending_steps > 0;
+        simulation.pending_steps = simulation.pending_steps.saturating_sub(1);
+    } else {
+        simulation.advances = true;
+        simulation.pending_steps = 0;
+    }
+}
+
 #[derive(Resource)]
 pub struct BoidsUniformBindGroup(BindGroup);
 
 #[derive(Resource)]
 pub struct BoidsImageBindGroup(BindGroup);
 
+#[derive(Resource)]
+pub struct BoidsCullBindGroup(BindGroup);
+
+#[derive(Resource)]
+pub struct BoidsReductionBindGroup(BindGroup);
+
+/// The camera the boids are culled against, extracted from the main world
+#[derive(Resource, Default)]
+pub struct BoidsCullView(Option<(Vec3, Frustum)>);
+
+#[allow(clippy::too_many_arguments)]
 pub(crate) fn prepare_uniforms_bind_group(
     mut commands: Commands,
     pipeline: Res<BoidsPipeline>,
     render_queue: Res<RenderQueue>,
     mut terrain_uniform_buffer: ResMut<TerrainUniformBuffer>,
+    mut heightfield_uniform_buffer: ResMut<HeightfieldUniformBuffer>,
+    route_uniform_buffer: Res<RouteUniformBuffer>,
     boids_config: Res<BoidsConfig>,
+    terrain_config: Res<TerrainConfig>,
+    heightmap: Res<TerrainHeightmap>,
+    gpu_images: Res<RenderAssets<GpuImage>>,
     render_device: Res<RenderDevice>,
 ) {
+    let (Some(heightmap), Some(route_uniform)) = (
+        gpu_images.get(&heightmap.0),
+        route_uniform_buffer.buffer.binding(),
+    ) else {
+        return;
+    };
+
     let buffer = terrain_uniform_buffer.buffer.get_mut();
 
     buffer.boids_count = boids_config.boids_count;
@@ -87,10 +216,23 @@ pub(crate) fn prepare_uniforms_bind_group(
         .buffer
         .write_buffer(&render_device, &render_queue);
 
+    heightfield_uniform_buffer
+        .buffer
+        .set(HeightfieldUniform::from(terrain_config.as_ref()));
+
+    heightfield_uniform_buffer
+        .buffer
+        .write_buffer(&render_device, &render_queue);
+
     let bind_group_uniforms = render_device.create_bind_group(
         None,
         &pipeline.uniform_bind_group_layout,
-        &BindGroupEntries::single(terrain_uniform_buffer.buffer.binding().unwrap().clone()),
+        &BindGroupEntries::sequential((
+            terrain_uniform_buffer.buffer.binding().unwrap().clone(),
+            heightfield_uniform_buffer.buffer.binding().unwrap().clone(),
+            &heightmap.texture_view,
+            route_uniform,
+        )),
     );
     commands.insert_resource(BoidsUniformBindGroup(bind_group_uniforms));
 }
@@ -116,12 +258,93 @@ pub(crate) fn prepare_textures_bind_group(
     commands.insert_resource(BoidsImageBindGroup(bind_group));
 }
 
+pub(crate) fn prepare_cull_bind_group(
+    mut commands: Commands,
+    pipeline: Res<BoidsPipeline>,
+    render_queue: Res<RenderQueue>,
+    mut cull_uniform_buffer: ResMut<BoidsCullUniformBuffer>,
+    boids_config: Res<BoidsConfig>,
+    cull_view: Res<BoidsCullView>,
+    buffers: Res<BoidsInstanceBuffers>,
+    gpu_buffers: Res<RenderAssets<GpuShaderStorageBuffer>>,
+    render_device: Res<RenderDevice>,
+) {
+    let (Some(visible_instances), Some(indirect_args)) = (
+        gpu_buffers.get(&buffers.visible_instances),
+        gpu_buffers.get(&buffers.indirect_args),
+    ) else {
+        return;
+    };
+
+    let uniform = cull_uniform_buffer.buffer.get_mut();
+    uniform.boid_radius = BOID_RADIUS;
+    uniform.lod_distances = Vec2::new(
+        boids_config.lod_medium_distance,
+        boids_config.lod_low_distance,
+    );
+    match &cull_view.0 {
+        Some((camera_position, frustum)) => {
+            uniform.camera_position = *camera_position;
+            uniform.frustum = frustum.half_spaces.map(|half_space| half_space.normal_d());
+            uniform.frustum_culling = boids_config.frustum_culling.into();
+        }
+        None => uniform.frustum_culling = 0,
+    }
+
+    cull_uniform_buffer
+        .buffer
+        .write_buffer(&render_device, &render_queue);
+
+    let bind_group = render_device.create_bind_group(
+        None,
+        &pipeline.cull_bind_group_layout,
+        &BindGroupEntries::sequential((
+            cull_uniform_buffer.buffer.binding().unwrap().clone(),
+            visible_instances.buffer.as_entire_binding(),
+            indirect_args.buffer.as_entire_binding(),
+        )),
+    );
+    commands.insert_resource(BoidsCullBindGroup(bind_group));
+}
+
+pub(crate) fn prepare_reduction_bind_group(
+    mut commands: Commands,
+    pipeline: Res<BoidsPipeline>,
+    buffers: Res<FlockReductionBuffers>,
+    gpu_buffers: Res<RenderAssets<GpuShaderStorageBuffer>>,
+    render_device: Res<RenderDevice>,
+) {
+    let (Some(partials), Some(summary)) = (
+        gpu_buffers.get(&buffers.partials),
+        gpu_buffers.get(&buffers.summary),
+    ) else {
+        return;
+    };
+
+    let bind_group = render_device.create_bind_group(
+        None,
+        &pipeline.reduction_bind_group_layout,
+        &BindGroupEntries::with_indices((
+            (3, partials.buffer.as_entire_binding()),
+            (4, summary.buffer.as_entire_binding()),
+        )),
+    );
+    commands.insert_resource(BoidsReductionBindGroup(bind_group));
+}
+
 #[derive(Resource)]
 pub struct BoidsPipeline {
     pub texture_bind_group_layout: BindGroupLayout,
     pub uniform_bind_group_layout: BindGroupLayout,
+    pub cull_bind_group_layout: BindGroupLayout,
+    pub trail_bind_group_layout: BindGroupLayout,
+    pub reduction_bind_group_layout: BindGroupLayout,
     init_pipeline: CachedComputePipelineId,
     update_pipeline: CachedComputePipelineId,
+    cull_pipeline: CachedComputePipelineId,
+    record_trails_pipeline: CachedComputePipelineId,
+    reduce_pipeline: CachedComputePipelineId,
+    reduce_final_pipeline: CachedComputePipelineId,
 }
 
 impl FromWorld for BoidsPipeline {
@@ -135,12 +358,55 @@ impl FromWorld for BoidsPipeline {
 
         let entries = BindGroupLayoutEntries::sequential(
             ShaderStages::COMPUTE,
-            (uniform_buffer::<BoidsUniform>(false),),
+            (
+                uniform_buffer::<BoidsUniform>(false),
+                uniform_buffer::<HeightfieldUniform>(false),
+                texture_2d(TextureSampleType::Float { filterable: false }),
+                uniform_buffer::<RouteUniform>(false),
+            ),
         );
 
         let uniform_bind_group_layout =
             render_device.create_bind_group_layout("uniform_bind_group_layout", &entries);
 
+        let cull_entries = BindGroupLayoutEntries::sequential(
+            ShaderStages::COMPUTE,
+            (
+                uniform_buffer::<BoidsCullUniform>(false),
+                storage_buffer_sized(false, None),
+                storage_buffer_sized(false, None),
+            ),
+        );
+        let cull_bind_group_layout =
+            render_device.create_bind_group_layout("boids_cull_bind_group_layout", &cull_entries);
+
+        let trail_entries = BindGroupLayoutEntries::sequential(
+            ShaderStages::COMPUTE,
+            (
+                uniform_buffer::<BoidsTrailUniform>(false),
+                storage_buffer_sized(false, None),
+            ),
+        );
+        let trail_bind_group_layout = render_device
+            .create_bind_group_layout("boids_trail_compute_bind_group_layout", &trail_entries);
+
+        // the reduction buffers share group 2 with the cull buffers, at binding indices that
+        // don't overlap so both can be declared in the same shader
+        let reduction_entries = BindGroupLayoutEntries::with_indices(
+            ShaderStages::COMPUTE,
+            (
+                (3, storage_buffer_sized(false, None)),
+                (4, storage_buffer_sized(false, None)),
+            ),
+        );
+        let reduction_bind_group_layout = render_device
+            .create_bind_group_layout("boids_reduction_bind_group_layout", &reduction_entries);
+        let shader_defs = vec![
+            ShaderDefVal::UInt("WORKGROUP_SIZE".into(), WORKGROUP_SIZE),
+            ShaderDefVal::UInt("LOD_COUNT".into(), LOD_COUNT as u32),
+            ShaderDefVal::UInt("MAX_WAYPOINTS".into(), MAX_WAYPOINTS as u32),
+        ];
+
         let init_pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
             zero_initialize_workgroup_memory: false,
             label: None,
@@ -150,7 +416,7 @@ impl FromWorld for BoidsPipeline {
             ],
             push_constant_ranges: Vec::new(),
             shader: shader.clone(),
-            shader_defs: vec![],
+            shader_defs: shader_defs.clone(),
             entry_point: Cow::from("init"),
         });
         let update_pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
@@ -161,16 +427,78 @@ impl FromWorld for BoidsPipeline {
                 texture_bind_group_layout.clone(),
             ],
             push_constant_ranges: Vec::new(),
-            shader,
-            shader_defs: vec![],
+            shader: shader.clone(),
+            shader_defs: shader_defs.clone(),
             entry_point: Cow::from("update"),
         });
+        let cull_pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+            zero_initialize_workgroup_memory: false,
+            label: None,
+            layout: vec![
+                uniform_bind_group_layout.clone(),
+                texture_bind_group_layout.clone(),
+                cull_bind_group_layout.clone(),
+            ],
+            push_constant_ranges: Vec::new(),
+            shader: shader.clone(),
+            shader_defs: shader_defs.clone(),
+            entry_point: Cow::from("cull"),
+        });
+        let record_trails_pipeline =
+            pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+                zero_initialize_workgroup_memory: false,
+                label: None,
+                layout: vec![
+                    uniform_bind_group_layout.clone(),
+                    texture_bind_group_layout.clone(),
+                    cull_bind_group_layout.clone(),
+                    trail_bind_group_layout.clone(),
+                ],
+                push_constant_ranges: Vec::new(),
+                shader: shader.clone(),
+                shader_defs: shader_defs.clone(),
+                entry_point: Cow::from("record_trails"),
+            });
+        let reduce_pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+            zero_initialize_workgroup_memory: false,
+            label: None,
+            layout: vec![
+                uniform_bind_group_layout.clone(),
+                texture_bind_group_layout.clone(),
+                reduction_bind_group_layout.clone(),
+            ],
+            push_constant_ranges: Vec::new(),
+            shader: shader.clone(),
+            shader_defs: shader_defs.clone(),
+            entry_point: Cow::from("reduce"),
+        });
+        let reduce_final_pipeline =
+            pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+                zero_initialize_workgroup_memory: false,
+                label: None,
+                layout: vec![
+                    uniform_bind_group_layout.clone(),
+                    texture_bind_group_layout.clone(),
+                    reduction_bind_group_layout.clone(),
+                ],
+                push_constant_ranges: Vec::new(),
+                shader,
+                shader_defs,
+                entry_point: Cow::from("reduce_final"),
+            });
 
         BoidsPipeline {
             texture_bind_group_layout,
             uniform_bind_group_layout,
+            cull_bind_group_layout,
+            trail_bind_group_layout,
+            reduction_bind_group_layout,
             init_pipeline,
             update_pipeline,
+            cull_pipeline,
+            record_trails_pipeline,
+            reduce_pipeline,
+            reduce_final_pipeline,
         }
     }
 }
@@ -183,12 +511,15 @@ enum BoidsState {
 
 struct BoidsNode {
     state: BoidsState,
+    /// Value of [`BoidsSimulation::restarts`] the boids were last initialized for
+    restarts: u32,
 }
 
 impl Default for BoidsNode {
     fn default() -> Self {
         Self {
             state: BoidsState::Loading,
+            restarts: 0,
         }
     }
 }
@@ -208,14 +539,33 @@ impl Node for BoidsNode {
                 }
             }
             BoidsState::Init => {
-                if let CachedPipelineState::Ok(_) =
-                    pipeline_cache.get_compute_pipeline_state(pipeline.update_pipeline)
-                {
+                if let (
+                    CachedPipelineState::Ok(_),
+                    CachedPipelineState::Ok(_),
+                    CachedPipelineState::Ok(_),
+                    CachedPipelineState::Ok(_),
+                    CachedPipelineState::Ok(_),
+                ) = (
+                    pipeline_cache.get_compute_pipeline_state(pipeline.update_pipeline),
+                    pipeline_cache.get_compute_pipeline_state(pipeline.cull_pipeline),
+                    pipeline_cache.get_compute_pipeline_state(pipeline.record_trails_pipeline),
+                    pipeline_cache.get_compute_pipeline_state(pipeline.reduce_pipeline),
+                    pipeline_cache.get_compute_pipeline_state(pipeline.reduce_final_pipeline),
+                ) {
                     self.state = BoidsState::Update;
                 }
             }
             BoidsState::Update => {}
         }
+
+        // checked after the transitions, so the init pass runs before returning to updates
+        let restarts = world.resource::<BoidsSimulation>().restarts;
+        if restarts != self.restarts {
+            self.restarts = restarts;
+            if let BoidsState::Update = self.state {
+                self.state = BoidsState::Init;
+            }
+        }
     }
 
     fn run(
@@ -224,14 +574,26 @@ impl Node for BoidsNode {
         render_context: &mut RenderContext,
         world: &World,
     ) -> Result<(), NodeRunError> {
-        let texture_bind_group = &world.resource::<BoidsImageBindGroup>().0;
-        let uniform_bind_group = &world.resource::<BoidsUniformBindGroup>().0;
+        let (
+            Some(BoidsImageBindGroup(texture_bind_group)),
+            Some(BoidsUniformBindGroup(uniform_bind_group)),
+        ) = (
+            world.get_resource::<BoidsImageBindGroup>(),
+            world.get_resource::<BoidsUniformBindGroup>(),
+        )
+        else {
+            return Ok(());
+        };
         let pipeline_cache = world.resource::<PipelineCache>();
         let pipeline = world.resource::<BoidsPipeline>();
+        let config = world.resource::<BoidsConfig>();
+        let advances = world.resource::<BoidsSimulation>().advances();
+        let diagnostics = render_context.diagnostic_recorder();
 
         let mut pass = render_context
             .command_encoder()
             .begin_compute_pass(&ComputePassDescriptor::default());
+        let pass_span = diagnostics.pass_span(&mut pass, BOIDS_COMPUTE_SPAN);
 
         pass.set_bind_group(0, uniform_bind_group, &[]);
         pass.set_bind_group(1, texture_bind_group, &[]);
@@ -243,24 +605,65 @@ impl Node for BoidsNode {
                     .get_compute_pipeline(pipeline.init_pipeline)
                     .unwrap();
                 pass.set_pipeline(init_pipeline);
-                pass.dispatch_workgroups(
-                    IMAGE_SIZE / WORKGROUP_SIZE,
-                    IMAGE_SIZE / WORKGROUP_SIZE,
-                    1,
-                );
+                // initialize the full capacity, so boids are in place when the count is raised later
+                pass.dispatch_workgroups((IMAGE_SIZE * IMAGE_SIZE).div_ceil(WORKGROUP_SIZE), 1, 1);
             }
             BoidsState::Update => {
-                let update_pipeline = pipeline_cache
-                    .get_compute_pipeline(pipeline.update_pipeline)
-                    .unwrap();
-                pass.set_pipeline(update_pipeline);
-                pass.dispatch_workgroups(
-                    IMAGE_SIZE / WORKGROUP_SIZE,
-                    IMAGE_SIZE / WORKGROUP_SIZE,
-                    1,
-                );
+                if advances {
+                    let update_pipeline = pipeline_cache
+                        .get_compute_pipeline(pipeline.update_pipeline)
+                        .unwrap();
+                    pass.set_pipeline(update_pipeline);
+                    pass.dispatch_workgroups(config.boids_count.div_ceil(WORKGROUP_SIZE), 1, 1);
+                }
+
+                // reduce the flock to one partial result per workgroup, then combine those in a
+                // single workgroup into the summary that is read back
+                if let Some(BoidsReductionBindGroup(reduction_bind_group)) =
+                    world.get_resource::<BoidsReductionBindGroup>()
+                {
+                    let reduce_pipeline = pipeline_cache
+                        .get_compute_pipeline(pipeline.reduce_pipeline)
+                        .unwrap();
+                    let reduce_final_pipeline = pipeline_cache
+                        .get_compute_pipeline(pipeline.reduce_final_pipeline)
+                        .unwrap();
+                    pass.set_bind_group(2, reduction_bind_group, &[]);
+                    pass.set_pipeline(reduce_pipeline);
+                    pass.dispatch_workgroups(config.boids_count.div_ceil(WORKGROUP_SIZE), 1, 1);
+                    pass.set_pipeline(reduce_final_pipeline);
+                    pass.dispatch_workgroups(1, 1, 1);
+                }
+
+                // the indirect instance counts are reset by `prepare_indirect_args` every frame,
+                // so culling only has to append the visible boids
+                if let Some(BoidsCullBindGroup(cull_bind_group)) =
+                    world.get_resource::<BoidsCullBindGroup>()
+                {
+                    let cull_pipeline = pipeline_cache
+                        .get_compute_pipeline(pipeline.cull_pipeline)
+                        .unwrap();
+                    pass.set_bind_group(2, cull_bind_group, &[]);
+                    pass.set_pipeline(cull_pipeline);
+                    pass.dispatch_workgroups(config.boids_count.div_ceil(WORKGROUP_SIZE), 1, 1);
+
+                    // only record a trail point when the boids have moved
+                    if let Some(BoidsTrailComputeBindGroup(trail_bind_group)) = world
+                        .get_resource::<BoidsTrailComputeBindGroup>()
+                        .filter(|_| advances)
+                    {
+                        let record_trails_pipeline = pipeline_cache
+                            .get_compute_pipeline(pipeline.record_trails_pipeline)
+                            .unwrap();
+                        pass.set_bind_group(3, trail_bind_group, &[]);
+                        pass.set_pipeline(record_trails_pipeline);
+                        pass.dispatch_workgroups(config.boids_count.div_ceil(WORKGROUP_SIZE), 1, 1);
+                    }
+                }
             }
         }
+
+        pass_span.end(&mut pass);
         Ok(())
     }
 }
@@ -274,6 +677,10 @@ impl Plugin for BoidsComputePlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins(ExtractResourcePlugin::<BoidsImage>::default());
         app.add_plugins(ExtractResourcePlugin::<BoidsUniform>::default());
+        app.add_plugins(ExtractResourcePlugin::<BoidsInstanceBuffers>::default());
+        app.add_plugins(ExtractResourcePlugin::<BoidsSimulation>::default());
+        app.init_resource::<BoidsSimulation>()
+            .add_systems(PostUpdate, advance_simulation.after(capture_frame));
 
         let render_app = app.sub_app_mut(RenderApp);
         render_app.add_systems(
@@ -284,18 +691,28 @@ impl Plugin for BoidsComputePlugin {
             Render,
             prepare_uniforms_bind_group.in_set(RenderSet::PrepareResources),
         );
+        render_app.add_systems(
+            Render,
+            prepare_cull_bind_group.in_set(RenderSet::PrepareResources),
+        );
 
         let mut render_graph = render_app.world_mut().resource_mut::<RenderGraph>();
         render_graph.add_node(BoidsLabel, BoidsNode::default());
         render_graph.add_node_edge(BoidsLabel, bevy::render::graph::CameraDriverLabel);
 
-        render_app.add_systems(ExtractSchedule, (extract_boids_config, extract_time));
+        render_app.add_systems(
+            ExtractSchedule,
+            (extract_boids_config, extract_time, extract_cull_view),
+        );
     }
 
     fn finish(&self, app: &mut App) {
         let render_app = app.sub_app_mut(RenderApp);
         render_app.init_resource::<BoidsPipeline>();
         render_app.init_resource::<TerrainUniformBuffer>();
+        render_app.init_resource::<HeightfieldUniformBuffer>();
+        render_app.init_resource::<BoidsCullUniformBuffer>();
+        render_app.init_resource::<BoidsCullView>();
     }
 }
 
@@ -306,3 +723,15 @@ fn extract_boids_config(mut commands: Commands, config: Extract<Res<BoidsConfig>
 fn extract_time(mut commands: Commands, time: Extract<Res<Time>>) {
     commands.insert_resource(**time);
 }
+
+fn extract_cull_view(
+    mut commands: Commands,
+    cameras: Extract<Query<(&GlobalTransform, &Frustum, Has<CaptureCamera>), With<Camera3d>>>,
+) {
+    // while capturing, cull against the offscreen camera whose frames are written to disk
+    let capture_view = cameras.iter().find(|(_, _, is_capture)| *is_capture);
+    let view = capture_view
+        .or_else(|| cameras.single().ok())
+        .map(|(transform, frustum, _)| (transform.translation(), *frustum));
+    commands.insert_resource(BoidsCullView(view));
+}
@@ -0,0 +1,211 @@
+use bevy::prelude::*;
+use bevy_egui::egui::{self, Color32, Rect, Sense, Ui};
+
+use super::{boids_compute::BoidsConfig, uniforms::COLOR_RAMP_SIZE};
+
+/// What the color of a boid is derived from, mirrored by the `COLOR_MODE_*` constants in
+/// `boids_material.wgsl`
+#[derive(Reflect, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BoidsColorMode {
+    #[default]
+    Solid = 0,
+    Speed = 1,
+    Heading = 2,
+    Density = 3,
+    /// Boids are assigned to a species by their index, the simulation treats all species alike
+    Species = 4,
+}
+
+impl BoidsColorMode {
+    pub const ALL: [BoidsColorMode; 5] = [
+        BoidsColorMode::Solid,
+        BoidsColorMode::Speed,
+        BoidsColorMode::Heading,
+        BoidsColorMode::Density,
+        BoidsColorMode::Species,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            BoidsColorMode::Solid => "Solid",
+            BoidsColorMode::Speed => "Speed",
+            BoidsColorMode::Heading => "Heading",
+            BoidsColorMode::Density => "Local density",
+            BoidsColorMode::Species => "Species",
+        }
+    }
+}
+
+/// Default color ramp, from dark blue over teal and green to yellow
+pub const DEFAULT_COLOR_RAMP: [LinearRgba; COLOR_RAMP_SIZE] = [
+    LinearRgba::rgb(0.03, 0.01, 0.25),
+    LinearRgba::rgb(0.02, 0.3, 0.35),
+    LinearRgba::rgb(0.2, 0.65, 0.1),
+    LinearRgba::rgb(1.0, 0.85, 0.02),
+];
+
+/// Sample the color ramp at `t` in `[0, 1]`, matching `sample_ramp` in `boids_material.wgsl`
+pub fn sample_ramp(ramp: &[LinearRgba; COLOR_RAMP_SIZE], t: f32) -> LinearRgba {
+    let scaled = t.clamp(0.0, 1.0) * (COLOR_RAMP_SIZE - 1) as f32;
+    let index = (scaled as usize).min(COLOR_RAMP_SIZE - 2);
+    ramp[index].mix(&ramp[index + 1], scaled - index as f32)
+}
+
+/// Fully saturated color for a hue in `[0, 1]`, matching `hue_to_rgb` in `boids_material.wgsl`
+pub fn hue_to_rgb(hue: f32) -> LinearRgba {
+    let channel = |offset: f32| (((hue + offset).fract() * 6.0 - 3.0).abs() - 1.0).clamp(0.0, 1.0);
+    LinearRgba::rgb(channel(0.0), channel(2.0 / 3.0), channel(1.0 / 3.0))
+}
+
+/// Color of the given species, spreading the species evenly over the color ramp
+pub fn species_color(
+    ramp: &[LinearRgba; COLOR_RAMP_SIZE],
+    species: u32,
+    species_count: u32,
+) -> LinearRgba {
+    if species_count <= 1 {
+        return ramp[0];
+    }
+    sample_ramp(ramp, species as f32 / (species_count - 1) as f32)
+}
+
+/// Color of boid `index` with the given velocity, matching `boid_color` in `boids_material.wgsl`.
+///
+/// The w component of the velocity is the number of neighbors within the align range.
+pub fn boid_color(config: &BoidsConfig, index: u32, velocity: Vec4) -> LinearRgba {
+    let ramp = &config.color_ramp;
+    match config.color_mode {
+        BoidsColorMode::Solid => config.solid_color,
+        BoidsColorMode::Speed => sample_ramp(ramp, velocity.truncate().length() / config.max_speed),
+        BoidsColorMode::Heading => {
+            let azimuth = velocity.z.atan2(velocity.x);
+            hue_to_rgb(azimuth / std::f32::consts::TAU + 0.5)
+        }
+        BoidsColorMode::Density => {
+            sample_ramp(ramp, velocity.w / config.density_max_neighbors as f32)
+        }
+        BoidsColorMode::Species => species_color(
+            ramp,
+            index % config.species_count.max(1),
+            config.species_count,
+        ),
+    }
+}
+
+fn to_color32(color: LinearRgba) -> Color32 {
+    egui::Rgba::from_rgb(color.red, color.green, color.blue).into()
+}
+
+fn color_edit(ui: &mut Ui, color: &mut LinearRgba) {
+    let mut rgb = [color.red, color.green, color.blue];
+    if ui.color_edit_button_rgb(&mut rgb).changed() {
+        *color = LinearRgba::rgb(rgb[0], rgb[1], rgb[2]);
+    }
+}
+
+/// Paint a horizontal gradient bar with `color_at` evaluated over `[0, 1]`
+pub fn gradient_bar(ui: &mut Ui, color_at: impl Fn(f32) -> LinearRgba) {
+    const STEPS: usize = 64;
+
+    let (response, painter) = ui.allocate_painter(egui::Vec2::new(200.0, 16.0), Sense::hover());
+    let rect = response.rect;
+    let step = rect.width() / STEPS as f32;
+    for i in 0..STEPS {
+        let t = (i as f32 + 0.5) / STEPS as f32;
+        let left = rect.left() + i as f32 * step;
+        painter.rect_filled(
+            Rect::from_min_max(
+                egui::pos2(left, rect.top()),
+                egui::pos2(left + step + 0.5, rect.bottom()),
+            ),
+            0.0,
+            to_color32(color_at(t)),
+        );
+    }
+}
+
+/// Color mode selection and its parameters
+pub fn color_mode_ui(config: &mut BoidsConfig, ui: &mut Ui) {
+    ui.label("Color mode");
+    egui::ComboBox::from_id_salt("boids_color_mode")
+        .selected_text(config.color_mode.label())
+        .show_ui(ui, |ui| {
+            for mode in BoidsColorMode::ALL {
+                ui.selectable_value(&mut config.color_mode, mode, mode.label());
+            }
+        });
+    ui.end_row();
+
+    match config.color_mode {
+        BoidsColorMode::Solid => {
+            ui.label("Color");
+            color_edit(ui, &mut config.solid_color);
+            ui.end_row();
+        }
+        BoidsColorMode::Heading => {}
+        BoidsColorMode::Speed | BoidsColorMode::Density | BoidsColorMode::Species => {
+            ui.label("Color ramp");
+            ui.horizontal(|ui| {
+                for color in &mut config.color_ramp {
+                    color_edit(ui, color);
+                }
+            });
+            ui.end_row();
+        }
+    }
+
+    match config.color_mode {
+        BoidsColorMode::Density => {
+            ui.add(
+                egui::Slider::new(
+                    &mut config.density_max_neighbors,
+                    BoidsConfig::DENSITY_MAX_NEIGHBORS_LIMITS,
+                )
+                .text("Max neighbors"),
+            );
+            ui.end_row();
+        }
+        BoidsColorMode::Species => {
+            ui.add(
+                egui::Slider::new(&mut config.species_count, BoidsConfig::SPECIES_COUNT_LIMITS)
+                    .text("Species"),
+            );
+            ui.end_row();
+        }
+        _ => {}
+    }
+}
+
+/// Legend explaining what the boid colors mean in the current color mode
+pub fn color_legend_ui(config: &BoidsConfig, ui: &mut Ui) {
+    ui.label("Legend");
+    ui.vertical(|ui| match config.color_mode {
+        BoidsColorMode::Solid => {
+            gradient_bar(ui, |_| config.solid_color);
+        }
+        BoidsColorMode::Speed => {
+            gradient_bar(ui, |t| sample_ramp(&config.color_ramp, t));
+            ui.label(format!("0 - {:.1} (max speed)", config.max_speed));
+        }
+        BoidsColorMode::Heading => {
+            gradient_bar(ui, hue_to_rgb);
+            ui.label("-180° - 180° (azimuth of the velocity)");
+        }
+        BoidsColorMode::Density => {
+            gradient_bar(ui, |t| sample_ramp(&config.color_ramp, t));
+            ui.label(format!(
+                "0 - {} neighbors within align range",
+                config.density_max_neighbors
+            ));
+        }
+        BoidsColorMode::Species => {
+            ui.horizontal_wrapped(|ui| {
+                for species in 0..config.species_count {
+                    let color = species_color(&config.color_ramp, species, config.species_count);
+                    ui.colored_label(to_color32(color), format!("■ {}", species + 1));
+                }
+            });
+        }
+    });
+    ui.end_row();
+}
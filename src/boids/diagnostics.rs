@@ -0,0 +1,146 @@
+use bevy::{
+    diagnostic::{Diagnostic, DiagnosticPath, Diagnostics, DiagnosticsStore, RegisterDiagnostic},
+    prelude::*,
+    render::{diagnostic::RenderDiagnosticsPlugin, renderer::RenderDevice, settings::WgpuFeatures},
+};
+use bevy_egui::egui::{Color32, Pos2, Sense, Shape, Stroke, Ui, Vec2};
+
+/// Name of the pass span recorded around the boids compute pass in [`super::boids_compute`]
+pub const BOIDS_COMPUTE_SPAN: &str = "boids_compute";
+/// Name of the pass span recorded around the boids draw pass in [`super::render`]
+pub const BOIDS_DRAW_SPAN: &str = "boids_draw";
+
+pub const BOIDS_COMPUTE_MS: DiagnosticPath = DiagnosticPath::const_new("boids/compute_ms");
+/// GPU time of drawing the boid meshes, the trails are drawn in the transparent pass and aren't
+/// included
+pub const BOIDS_DRAW_MS: DiagnosticPath = DiagnosticPath::const_new("boids/draw_ms");
+
+/// GPU timings recorded by Bevy's [`RenderDiagnosticsPlugin`]
+const RENDER_COMPUTE_GPU: DiagnosticPath =
+    DiagnosticPath::const_new("render/boids_compute/elapsed_gpu");
+const RENDER_DRAW_GPU: DiagnosticPath = DiagnosticPath::const_new("render/boids_draw/elapsed_gpu");
+
+const PLOT_HISTORY_LENGTH: usize = 120;
+
+/// Whether the adapter can write timestamps inside compute and render passes
+#[derive(Resource, Default, Clone, Copy)]
+pub struct BoidsGpuTimings {
+    pub supported: bool,
+}
+
+pub struct BoidsDiagnosticsPlugin;
+
+impl Plugin for BoidsDiagnosticsPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<RenderDiagnosticsPlugin>() {
+            app.add_plugins(RenderDiagnosticsPlugin);
+        }
+
+        app.register_diagnostic(
+            Diagnostic::new(BOIDS_COMPUTE_MS)
+                .with_suffix("ms")
+                .with_max_history_length(PLOT_HISTORY_LENGTH),
+        )
+        .register_diagnostic(
+            Diagnostic::new(BOIDS_DRAW_MS)
+                .with_suffix("ms")
+                .with_max_history_length(PLOT_HISTORY_LENGTH),
+        )
+        .init_resource::<BoidsGpuTimings>()
+        .add_systems(Update, update_boids_diagnostics);
+    }
+
+    fn finish(&self, app: &mut App) {
+        let supported = app
+            .world()
+            .get_resource::<RenderDevice>()
+            .is_some_and(|device| {
+                device.features().contains(
+                    WgpuFeatures::TIMESTAMP_QUERY | WgpuFeatures::TIMESTAMP_QUERY_INSIDE_PASSES,
+                )
+            });
+
+        if !supported {
+            warn!("GPU timestamp queries are not supported by this adapter, boids GPU timings are disabled");
+        }
+
+        app.insert_resource(BoidsGpuTimings { supported });
+    }
+}
+
+/// Copy the render pass timings into the `boids/*` diagnostics.
+fn update_boids_diagnostics(
+    timings: Res<BoidsGpuTimings>,
+    store: Res<DiagnosticsStore>,
+    mut diagnostics: Diagnostics,
+) {
+    if !timings.supported {
+        return;
+    }
+
+    if let Some(compute_ms) = store.get(&RENDER_COMPUTE_GPU).and_then(Diagnostic::value) {
+        diagnostics.add_measurement(&BOIDS_COMPUTE_MS, || compute_ms);
+    }
+    if let Some(draw_ms) = store.get(&RENDER_DRAW_GPU).and_then(Diagnostic::value) {
+        diagnostics.add_measurement(&BOIDS_DRAW_MS, || draw_ms);
+    }
+}
+
+fn sparkline(ui: &mut Ui, values: &[f64], max_value: f64, color: Color32) {
+    let (response, painter) = ui.allocate_painter(Vec2::new(200.0, 40.0), Sense::hover());
+    let rect = response.rect;
+    painter.rect_filled(rect, 2.0, Color32::from_black_alpha(64));
+
+    if values.len() < 2 || max_value <= 0.0 {
+        return;
+    }
+
+    let step = rect.width() / (PLOT_HISTORY_LENGTH - 1) as f32;
+    let points = values
+        .iter()
+        .enumerate()
+        .map(|(i, value)| {
+            Pos2::new(
+                rect.left() + i as f32 * step,
+                rect.bottom() - (*value / max_value) as f32 * rect.height(),
+            )
+        })
+        .collect();
+    painter.add(Shape::line(points, Stroke::new(1.5, color)));
+}
+
+pub fn gpu_timings_ui(timings: &BoidsGpuTimings, store: &DiagnosticsStore, ui: &mut Ui) {
+    if !timings.supported {
+        ui.label("GPU timings");
+        ui.label("Not supported by this adapter");
+        ui.end_row();
+        return;
+    }
+
+    let history = |path: &DiagnosticPath| -> Vec<f64> {
+        store
+            .get(path)
+            .map(|diagnostic| diagnostic.values().copied().collect())
+            .unwrap_or_default()
+    };
+    let compute = history(&BOIDS_COMPUTE_MS);
+    let draw = history(&BOIDS_DRAW_MS);
+    let max_value = compute
+        .iter()
+        .chain(draw.iter())
+        .copied()
+        .fold(0.0, f64::max);
+
+    for (label, path, values, color) in [
+        ("Compute", &BOIDS_COMPUTE_MS, &compute, Color32::LIGHT_BLUE),
+        ("Draw", &BOIDS_DRAW_MS, &draw, Color32::LIGHT_GREEN),
+    ] {
+        let smoothed = store.get(path).and_then(Diagnostic::smoothed);
+        ui.label(match smoothed {
+            Some(ms) => format!("{label}: {ms:.2} ms"),
+            None => format!("{label}: -"),
+        });
+        sparkline(ui, values, max_value, color);
+        ui.end_row();
+    }
+}
@@ -0,0 +1,726 @@
+use std::fmt::Write as _;
+
+use bevy::{
+    prelude::*,
+    render::mesh::{Indices, VertexAttributeValues},
+};
+use bevy_egui::{
+    egui::{self, Pos2},
+    EguiContexts,
+};
+
+use super::{
+    boids_compute::BoidsConfig,
+    color::boid_color,
+    inspector::{BoidsSnapshot, BoidsSnapshotRequest},
+    mesh::BOID_RADIUS,
+};
+
+/// Seconds the simulation runs before a snapshot is exported with the `--export` flag
+const DEFAULT_CLI_EXPORT_DELAY: f32 = 10.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    PlyAscii,
+    PlyBinary,
+    /// Binary glTF with one boid mesh instanced with `EXT_mesh_gpu_instancing`
+    Gltf,
+}
+
+impl ExportFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::PlyAscii | ExportFormat::PlyBinary => "ply",
+            ExportFormat::Gltf => "glb",
+        }
+    }
+
+    /// Format of an export path, binary PLY unless `ascii` is set for `.ply` files
+    pub fn from_path(path: &str, ascii: bool) -> Option<Self> {
+        let extension = path.rsplit_once('.')?.1.to_ascii_lowercase();
+        match extension.as_str() {
+            "ply" if ascii => Some(ExportFormat::PlyAscii),
+            "ply" => Some(ExportFormat::PlyBinary),
+            "glb" => Some(ExportFormat::Gltf),
+            _ => None,
+        }
+    }
+}
+
+/// The state of a single boid as it is exported
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ExportedBoid {
+    pub position: Vec3,
+    pub velocity: Vec3,
+    pub color: LinearRgba,
+}
+
+/// Boids of a snapshot with the colors they are rendered with
+pub fn exported_boids(config: &BoidsConfig, snapshot: &BoidsSnapshot) -> Vec<ExportedBoid> {
+    snapshot
+        .positions
+        .iter()
+        .zip(&snapshot.velocities)
+        .take(config.boids_count as usize)
+        .enumerate()
+        .map(|(index, (position, velocity))| ExportedBoid {
+            position: *position,
+            velocity: velocity.truncate(),
+            color: boid_color(config, index as u32, *velocity),
+        })
+        .collect()
+}
+
+/// A PLY point cloud with position, velocity and color properties per boid
+pub fn write_ply(boids: &[ExportedBoid], binary: bool) -> Vec<u8> {
+    let format = if binary {
+        "binary_little_endian"
+    } else {
+        "ascii"
+    };
+    let mut ply = format!(
+        "ply\nformat {format} 1.0\ncomment boids snapshot\nelement vertex {}\n",
+        boids.len()
+    );
+    for property in ["x", "y", "z", "vx", "vy", "vz"] {
+        let _ = writeln!(ply, "property float {property}");
+    }
+    for property in ["red", "green", "blue"] {
+        let _ = writeln!(ply, "property uchar {property}");
+    }
+    ply.push_str("end_header\n");
+
+    let mut bytes = ply.into_bytes();
+    for boid in boids {
+        let [red, green, blue, _] = Srgba::from(boid.color).to_u8_array();
+        let (p, v) = (boid.position, boid.velocity);
+        if binary {
+            for value in [p.x, p.y, p.z, v.x, v.y, v.z] {
+                bytes.extend(value.to_le_bytes());
+            }
+            bytes.extend([red, green, blue]);
+        } else {
+            let line = format!(
+                "{} {} {} {} {} {} {red} {green} {blue}\n",
+                p.x, p.y, p.z, v.x, v.y, v.z
+            );
+            bytes.extend(line.into_bytes());
+        }
+    }
+    bytes
+}
+
+/// The mesh instanced for every boid in the glTF export, a cone with its tip along +Y so the
+/// heading of the boids is visible
+pub fn export_boid_mesh() -> Mesh {
+    Mesh::from(Cone::new(BOID_RADIUS, BOID_RADIUS * 3.0))
+}
+
+/// Append `data` to the binary chunk and describe it with a buffer view and an accessor
+struct GlbBuilder {
+    binary: Vec<u8>,
+    buffer_views: Vec<String>,
+    accessors: Vec<String>,
+}
+
+impl GlbBuilder {
+    fn push(
+        &mut self,
+        data: &[u8],
+        count: usize,
+        accessor_type: &str,
+        component_type: u32,
+        target: Option<u32>,
+        bounds: Option<(Vec3, Vec3)>,
+    ) -> usize {
+        let offset = self.binary.len();
+        self.binary.extend_from_slice(data);
+        // every accessor has 4 byte components, keep the views aligned to those
+        self.binary.resize(self.binary.len().next_multiple_of(4), 0);
+
+        let target = target.map_or(String::new(), |target| format!(",\"target\":{target}"));
+        self.buffer_views.push(format!(
+            "{{\"buffer\":0,\"byteOffset\":{offset},\"byteLength\":{}{target}}}",
+            data.len()
+        ));
+        let bounds = bounds.map_or(String::new(), |(min, max)| {
+            format!(
+                ",\"min\":[{},{},{}],\"max\":[{},{},{}]",
+                min.x, min.y, min.z, max.x, max.y, max.z
+            )
+        });
+        self.accessors.push(format!(
+            "{{\"bufferView\":{},\"componentType\":{component_type},\"count\":{count},\"type\":\"{accessor_type}\"{bounds}}}",
+            self.buffer_views.len() - 1
+        ));
+        self.accessors.len() - 1
+    }
+}
+
+fn f32_bytes(values: impl IntoIterator<Item = f32>) -> Vec<u8> {
+    values.into_iter().flat_map(f32::to_le_bytes).collect()
+}
+
+/// A binary glTF 2.0 file with `mesh` instanced at every boid with `EXT_mesh_gpu_instancing`,
+/// rotated so the +Y axis of the mesh points along the velocity
+pub fn write_glb(
+    boids: &[ExportedBoid],
+    mesh: &Mesh,
+    color: LinearRgba,
+) -> Result<Vec<u8>, String> {
+    const FLOAT: u32 = 5126;
+    const UNSIGNED_INT: u32 = 5125;
+    const ARRAY_BUFFER: u32 = 34962;
+    const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+
+    let Some(VertexAttributeValues::Float32x3(positions)) =
+        mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+    else {
+        return Err("The boid mesh has no positions".to_string());
+    };
+    let Some(VertexAttributeValues::Float32x3(normals)) = mesh.attribute(Mesh::ATTRIBUTE_NORMAL)
+    else {
+        return Err("The boid mesh has no normals".to_string());
+    };
+    let indices: Vec<u32> = match mesh.indices() {
+        Some(Indices::U16(indices)) => indices.iter().map(|&index| index as u32).collect(),
+        Some(Indices::U32(indices)) => indices.clone(),
+        None => (0..positions.len() as u32).collect(),
+    };
+
+    let mut builder = GlbBuilder {
+        binary: Vec::new(),
+        buffer_views: Vec::new(),
+        accessors: Vec::new(),
+    };
+
+    let (min, max) = positions
+        .iter()
+        .fold((Vec3::MAX, Vec3::MIN), |(min, max), &position| {
+            let position = Vec3::from_array(position);
+            (min.min(position), max.max(position))
+        });
+    let position_accessor = builder.push(
+        &f32_bytes(positions.iter().flatten().copied()),
+        positions.len(),
+        "VEC3",
+        FLOAT,
+        Some(ARRAY_BUFFER),
+        Some((min, max)),
+    );
+    let normal_accessor = builder.push(
+        &f32_bytes(normals.iter().flatten().copied()),
+        normals.len(),
+        "VEC3",
+        FLOAT,
+        Some(ARRAY_BUFFER),
+        None,
+    );
+    let index_accessor = builder.push(
+        &indices
+            .iter()
+            .flat_map(|index| index.to_le_bytes())
+            .collect::<Vec<_>>(),
+        indices.len(),
+        "SCALAR",
+        UNSIGNED_INT,
+        Some(ELEMENT_ARRAY_BUFFER),
+        None,
+    );
+    let translation_accessor = builder.push(
+        &f32_bytes(boids.iter().flat_map(|boid| boid.position.to_array())),
+        boids.len(),
+        "VEC3",
+        FLOAT,
+        None,
+        None,
+    );
+    let rotation_accessor = builder.push(
+        &f32_bytes(boids.iter().flat_map(|boid| {
+            let heading = boid.velocity.try_normalize().unwrap_or(Vec3::Y);
+            Quat::from_rotation_arc(Vec3::Y, heading).to_array()
+        })),
+        boids.len(),
+        "VEC4",
+        FLOAT,
+        None,
+        None,
+    );
+
+    let [red, green, blue, alpha] = color.to_f32_array();
+    let json = format!(
+        concat!(
+            "{{\"asset\":{{\"version\":\"2.0\",\"generator\":\"bevy_experiments boids\"}},",
+            "\"extensionsUsed\":[\"EXT_mesh_gpu_instancing\"],",
+            "\"scene\":0,\"scenes\":[{{\"nodes\":[0]}}],",
+            "\"nodes\":[{{\"name\":\"boids\",\"mesh\":0,\"extensions\":{{\"EXT_mesh_gpu_instancing\":",
+            "{{\"attributes\":{{\"TRANSLATION\":{translation},\"ROTATION\":{rotation}}}}}}}}}],",
+            "\"meshes\":[{{\"name\":\"boid\",\"primitives\":[{{\"attributes\":",
+            "{{\"POSITION\":{position},\"NORMAL\":{normal}}},\"indices\":{indices},\"material\":0}}]}}],",
+            "\"materials\":[{{\"pbrMetallicRoughness\":{{\"baseColorFactor\":",
+            "[{red},{green},{blue},{alpha}],\"metallicFactor\":0.0}}}}],",
+            "\"buffers\":[{{\"byteLength\":{byte_length}}}],",
+            "\"bufferViews\":[{buffer_views}],\"accessors\":[{accessors}]}}"
+        ),
+        translation = translation_accessor,
+        rotation = rotation_accessor,
+        position = position_accessor,
+        normal = normal_accessor,
+        indices = index_accessor,
+        red = red,
+        green = green,
+        blue = blue,
+        alpha = alpha,
+        byte_length = builder.binary.len(),
+        buffer_views = builder.buffer_views.join(","),
+        accessors = builder.accessors.join(","),
+    );
+
+    // the chunks are padded to 4 bytes, the JSON with spaces and the binary data with zeros
+    let mut json = json.into_bytes();
+    json.resize(json.len().next_multiple_of(4), b' ');
+    let binary = builder.binary;
+    let total_length = 12 + 8 + json.len() + 8 + binary.len();
+
+    let mut glb = Vec::with_capacity(total_length);
+    glb.extend(b"glTF");
+    glb.extend(2u32.to_le_bytes());
+    glb.extend((total_length as u32).to_le_bytes());
+    glb.extend((json.len() as u32).to_le_bytes());
+    glb.extend(b"JSON");
+    glb.extend(json);
+    glb.extend((binary.len() as u32).to_le_bytes());
+    glb.extend(b"BIN\0");
+    glb.extend(binary);
+    Ok(glb)
+}
+
+pub fn export_boids(
+    config: &BoidsConfig,
+    snapshot: &BoidsSnapshot,
+    format: ExportFormat,
+    path: &str,
+) -> Result<usize, String> {
+    let boids = exported_boids(config, snapshot);
+    let bytes = match format {
+        ExportFormat::PlyAscii => write_ply(&boids, false),
+        ExportFormat::PlyBinary => write_ply(&boids, true),
+        ExportFormat::Gltf => write_glb(&boids, &export_boid_mesh(), config.solid_color)?,
+    };
+    std::fs::write(path, bytes).map_err(|error| format!("Failed to write {path}: {error}"))?;
+    Ok(boids.len())
+}
+
+/// An export that waits for a snapshot taken after it was requested
+#[derive(Component)]
+#[require(BoidsSnapshotRequest)]
+pub struct BoidsExportRequest {
+    pub format: ExportFormat,
+    pub path: String,
+    /// Exit the app once the export is written, for exports started from the command line
+    pub exit: bool,
+    generation: u32,
+}
+
+/// Export requested with `--export <path>`, optionally with `--ply-ascii` and
+/// `--export-delay <seconds>`
+#[derive(Resource)]
+struct CliExport {
+    format: ExportFormat,
+    path: String,
+    delay: Timer,
+}
+
+#[derive(Resource)]
+pub struct BoidsExportSettings {
+    /// Path of the exported file without the extension
+    pub path: String,
+    status: String,
+}
+
+impl Default for BoidsExportSettings {
+    fn default() -> Self {
+        Self {
+            path: "flock".to_string(),
+            status: String::new(),
+        }
+    }
+}
+
+pub struct BoidsExportPlugin;
+
+impl Plugin for BoidsExportPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<BoidsExportSettings>().add_systems(
+            Update,
+            (start_cli_export, finish_exports, export_ui_system).chain(),
+        );
+
+        match parse_cli_export(std::env::args().skip(1)) {
+            Ok(Some(cli_export)) => {
+                app.insert_resource(cli_export);
+            }
+            Ok(None) => {}
+            Err(error) => error!("{error}"),
+        }
+    }
+}
+
+fn parse_cli_export(mut args: impl Iterator<Item = String>) -> Result<Option<CliExport>, String> {
+    let mut path = None;
+    let mut ascii = false;
+    let mut delay = DEFAULT_CLI_EXPORT_DELAY;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--export" => path = args.next(),
+            "--ply-ascii" => ascii = true,
+            "--export-delay" => {
+                delay = args
+                    .next()
+                    .and_then(|seconds| seconds.parse().ok())
+                    .ok_or("--export-delay needs a number of seconds")?;
+            }
+            _ => {}
+        }
+    }
+
+    let Some(path) = path else {
+        return Ok(None);
+    };
+    let format = ExportFormat::from_path(&path, ascii)
+        .ok_or_else(|| format!("Can't export to {path}, use a .ply or .glb file"))?;
+    Ok(Some(CliExport {
+        format,
+        path,
+        delay: Timer::from_seconds(delay, TimerMode::Once),
+    }))
+}
+
+fn request_export(
+    commands: &mut Commands,
+    snapshot: &BoidsSnapshot,
+    format: ExportFormat,
+    path: String,
+    exit: bool,
+) {
+    commands.spawn(BoidsExportRequest {
+        format,
+        path,
+        exit,
+        generation: snapshot.generation(),
+    });
+}
+
+fn start_cli_export(
+    mut commands: Commands,
+    time: Res<Time>,
+    cli_export: Option<ResMut<CliExport>>,
+    snapshot: Res<BoidsSnapshot>,
+) {
+    let Some(mut cli_export) = cli_export else {
+        return;
+    };
+    if !cli_export.delay.tick(time.delta()).just_finished() {
+        return;
+    }
+    request_export(
+        &mut commands,
+        &snapshot,
+        cli_export.format,
+        cli_export.path.clone(),
+        true,
+    );
+    commands.remove_resource::<CliExport>();
+}
+
+fn finish_exports(
+    mut commands: Commands,
+    requests: Query<(Entity, &BoidsExportRequest)>,
+    snapshot: Res<BoidsSnapshot>,
+    config: Res<BoidsConfig>,
+    mut settings: ResMut<BoidsExportSettings>,
+    mut exit: EventWriter<AppExit>,
+) {
+    let complete =
+        !snapshot.positions.is_empty() && snapshot.velocities.len() >= snapshot.positions.len();
+    for (entity, request) in &requests {
+        if !complete || snapshot.generation() == request.generation {
+            continue;
+        }
+
+        settings.status = match export_boids(&config, &snapshot, request.format, &request.path) {
+            Ok(count) => {
+                info!("Exported {count} boids to {}", request.path);
+                format!("Exported {count} boids to {}", request.path)
+            }
+            Err(error) => {
+                error!("{error}");
+                error
+            }
+        };
+        commands.entity(entity).despawn();
+        if request.exit {
+            exit.write(AppExit::Success);
+        }
+    }
+}
+
+fn export_ui_system(
+    mut commands: Commands,
+    mut settings: ResMut<BoidsExportSettings>,
+    snapshot: Res<BoidsSnapshot>,
+    requests: Query<(), With<BoidsExportRequest>>,
+    mut contexts: EguiContexts,
+) {
+    let Ok(ctx) = contexts.ctx_mut() else {
+        return;
+    };
+    egui::Window::new("Export")
+        .default_pos(Pos2 { x: 830., y: 700. })
+        .default_open(false)
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("File");
+                ui.text_edit_singleline(&mut settings.path);
+            });
+            ui.add_enabled_ui(requests.is_empty(), |ui| {
+                ui.horizontal(|ui| {
+                    for (label, format) in [
+                        ("PLY (ASCII)", ExportFormat::PlyAscii),
+                        ("PLY (binary)", ExportFormat::PlyBinary),
+                        ("glTF (instanced)", ExportFormat::Gltf),
+                    ] {
+                        if ui.button(label).clicked() {
+                            let path = format!("{}.{}", settings.path, format.extension());
+                            request_export(&mut commands, &snapshot, format, path, false);
+                            settings.status = "Waiting for a snapshot".to_string();
+                        }
+                    }
+                });
+            });
+            if !settings.status.is_empty() {
+                ui.label(&settings.status);
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::*;
+
+    fn boids() -> Vec<ExportedBoid> {
+        vec![
+            ExportedBoid {
+                position: Vec3::new(1.5, -2.25, 300.125),
+                velocity: Vec3::new(0.0, 0.0, 4.0),
+                color: LinearRgba::RED,
+            },
+            ExportedBoid {
+                position: Vec3::new(-0.1, 0.2, -0.3),
+                velocity: Vec3::new(1.0, -2.0, 0.5),
+                color: LinearRgba::rgb(0.2, 0.5, 0.9),
+            },
+            // no heading, exported pointing up
+            ExportedBoid {
+                position: Vec3::ZERO,
+                velocity: Vec3::ZERO,
+                color: LinearRgba::WHITE,
+            },
+        ]
+    }
+
+    /// Splits a PLY file into its header lines and the data after `end_header`
+    fn split_ply(ply: &[u8]) -> (Vec<String>, &[u8]) {
+        const END: &[u8] = b"end_header\n";
+        let end = ply
+            .windows(END.len())
+            .position(|window| window == END)
+            .expect("the header ends with end_header");
+        let header = std::str::from_utf8(&ply[..end]).unwrap();
+        (
+            header.lines().map(str::to_string).collect(),
+            &ply[end + END.len()..],
+        )
+    }
+
+    fn check_ply_header(header: &[String], format: &str, count: usize) {
+        let mut expected = vec![
+            "ply".to_string(),
+            format!("format {format} 1.0"),
+            "comment boids snapshot".to_string(),
+            format!("element vertex {count}"),
+        ];
+        for property in ["x", "y", "z", "vx", "vy", "vz"] {
+            expected.push(format!("property float {property}"));
+        }
+        for property in ["red", "green", "blue"] {
+            expected.push(format!("property uchar {property}"));
+        }
+        assert_eq!(header, expected);
+    }
+
+    fn check_vertex(boid: &ExportedBoid, floats: [f32; 6], color: [u8; 3]) {
+        assert_eq!(Vec3::from_slice(&floats[..3]), boid.position);
+        assert_eq!(Vec3::from_slice(&floats[3..]), boid.velocity);
+        let [red, green, blue, _] = Srgba::from(boid.color).to_u8_array();
+        assert_eq!(color, [red, green, blue]);
+    }
+
+    #[test]
+    fn ascii_ply_round_trip() {
+        let boids = boids();
+        let ply = write_ply(&boids, false);
+        let (header, data) = split_ply(&ply);
+        check_ply_header(&header, "ascii", boids.len());
+
+        let lines: Vec<_> = std::str::from_utf8(data).unwrap().lines().collect();
+        assert_eq!(lines.len(), boids.len());
+        for (boid, line) in boids.iter().zip(lines) {
+            let values: Vec<_> = line.split(' ').collect();
+            assert_eq!(values.len(), 9);
+            let floats = std::array::from_fn(|i| values[i].parse().unwrap());
+            let color = std::array::from_fn(|i| values[6 + i].parse().unwrap());
+            check_vertex(boid, floats, color);
+        }
+    }
+
+    #[test]
+    fn binary_ply_round_trip() {
+        let boids = boids();
+        let ply = write_ply(&boids, true);
+        let (header, data) = split_ply(&ply);
+        check_ply_header(&header, "binary_little_endian", boids.len());
+
+        const VERTEX_SIZE: usize = 6 * 4 + 3;
+        assert_eq!(data.len(), boids.len() * VERTEX_SIZE);
+        for (boid, vertex) in boids.iter().zip(data.chunks_exact(VERTEX_SIZE)) {
+            let floats = std::array::from_fn(|i| {
+                f32::from_le_bytes(vertex[i * 4..i * 4 + 4].try_into().unwrap())
+            });
+            check_vertex(boid, floats, [vertex[24], vertex[25], vertex[26]]);
+        }
+    }
+
+    #[test]
+    fn empty_ply_has_no_vertices() {
+        let ply = write_ply(&[], true);
+        let (header, data) = split_ply(&ply);
+        check_ply_header(&header, "binary_little_endian", 0);
+        assert!(data.is_empty());
+    }
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    fn index(json: &Value, key: &str) -> usize {
+        json.get(key).and_then(Value::as_u64).unwrap() as usize
+    }
+
+    fn element<'a>(json: &'a Value, key: &str, index: usize) -> &'a Value {
+        let Some(Value::Array(values)) = json.get(key) else {
+            panic!("{key} is not an array");
+        };
+        &values[index]
+    }
+
+    /// The float components of an accessor, checking its count and type
+    fn read_accessor(
+        json: &Value,
+        binary: &[u8],
+        accessor: usize,
+        count: usize,
+        accessor_type: &str,
+    ) -> Vec<f32> {
+        let accessor = element(json, "accessors", accessor);
+        assert_eq!(index(accessor, "count"), count);
+        assert_eq!(index(accessor, "componentType"), 5126);
+        assert_eq!(
+            accessor.get("type"),
+            Some(&Value::String(accessor_type.to_string()))
+        );
+
+        let view = element(json, "bufferViews", index(accessor, "bufferView"));
+        let offset = index(view, "byteOffset");
+        assert_eq!(offset % 4, 0);
+        binary[offset..offset + index(view, "byteLength")]
+            .chunks_exact(4)
+            .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn glb_round_trip() {
+        let boids = boids();
+        let mesh = export_boid_mesh();
+        let glb = write_glb(&boids, &mesh, LinearRgba::BLUE).unwrap();
+
+        assert_eq!(&glb[0..4], b"glTF");
+        assert_eq!(u32_at(&glb, 4), 2);
+        assert_eq!(u32_at(&glb, 8) as usize, glb.len());
+
+        let json_length = u32_at(&glb, 12) as usize;
+        assert_eq!(json_length % 4, 0);
+        assert_eq!(&glb[16..20], b"JSON");
+        let json = std::str::from_utf8(&glb[20..20 + json_length]).unwrap();
+        let json: Value = serde_json::from_str(json).unwrap();
+
+        let binary_start = 20 + json_length;
+        let binary_length = u32_at(&glb, binary_start) as usize;
+        assert_eq!(binary_length % 4, 0);
+        assert_eq!(&glb[binary_start + 4..binary_start + 8], b"BIN\0");
+        let binary = &glb[binary_start + 8..];
+        assert_eq!(binary.len(), binary_length);
+        assert_eq!(
+            index(element(&json, "buffers", 0), "byteLength"),
+            binary_length
+        );
+
+        let primitive = element(element(&json, "meshes", 0), "primitives", 0);
+        let positions = read_accessor(
+            &json,
+            binary,
+            index(primitive.get("attributes").unwrap(), "POSITION"),
+            mesh.count_vertices(),
+            "VEC3",
+        );
+        assert_eq!(positions.len(), mesh.count_vertices() * 3);
+
+        let instancing = element(&json, "nodes", 0)
+            .get("extensions")
+            .and_then(|extensions| extensions.get("EXT_mesh_gpu_instancing"))
+            .and_then(|instancing| instancing.get("attributes"))
+            .unwrap();
+        let translations = read_accessor(
+            &json,
+            binary,
+            index(instancing, "TRANSLATION"),
+            boids.len(),
+            "VEC3",
+        );
+        let rotations = read_accessor(
+            &json,
+            binary,
+            index(instancing, "ROTATION"),
+            boids.len(),
+            "VEC4",
+        );
+        for ((boid, translation), rotation) in boids
+            .iter()
+            .zip(translations.chunks_exact(3))
+            .zip(rotations.chunks_exact(4))
+        {
+            assert_eq!(Vec3::from_slice(translation), boid.position);
+            // the +Y axis of the mesh follows the velocity
+            let heading = Quat::from_slice(rotation) * Vec3::Y;
+            let expected = boid.velocity.try_normalize().unwrap_or(Vec3::Y);
+            assert!(
+                heading.abs_diff_eq(expected, 1e-5),
+                "{heading} != {expected}"
+            );
+        }
+    }
+}
@@ -0,0 +1,195 @@
+use std::f32::consts::FRAC_PI_4;
+
+use bevy::prelude::*;
+use bevy_egui::egui::{self, Ui};
+use bevy_panorbit_camera::PanOrbitCamera;
+
+use super::{inspector::BoidsInspector, summary::FlockSummary, BOX_SIZE};
+use crate::{flythrough::Flythrough, math::smooth_stop};
+
+/// The camera never moves closer to the flock centroid than this
+const MIN_FRAMING_RADIUS: f32 = 100.0;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CameraFramingMode {
+    /// The camera is only moved by the user
+    #[default]
+    FreeOrbit,
+    /// Keep the flock centered and in frame, the user still controls the orbit angles
+    FollowFlock,
+    /// Slowly circle around the flock while keeping it in frame
+    CinematicOrbit,
+}
+
+impl CameraFramingMode {
+    pub const ALL: [Self; 3] = [Self::FreeOrbit, Self::FollowFlock, Self::CinematicOrbit];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::FreeOrbit => "Free orbit",
+            Self::FollowFlock => "Follow flock",
+            Self::CinematicOrbit => "Cinematic orbit",
+        }
+    }
+}
+
+/// Camera state at the moment the framing mode changed, eased towards the framed state
+#[derive(Clone, Copy, Debug)]
+struct FramingTransition {
+    elapsed: f32,
+    focus: Vec3,
+    radius: f32,
+    pitch: f32,
+}
+
+/// How the [`PanOrbitCamera`] is moved to keep the flock in frame
+#[derive(Resource)]
+pub struct CameraFraming {
+    pub mode: CameraFramingMode,
+    /// Distance of the camera relative to the distance at which the flock just fits in frame
+    pub margin: f32,
+    /// Seconds it takes to ease from the current camera to the framed flock after a mode switch
+    pub transition_duration: f32,
+    /// Yaw speed of the cinematic orbit in radians per second
+    pub orbit_speed: f32,
+    /// Pitch of the cinematic orbit in radians
+    pub orbit_pitch: f32,
+    /// Mode during the previous frame, to detect mode switches
+    previous_mode: CameraFramingMode,
+    transition: Option<FramingTransition>,
+}
+
+impl Default for CameraFraming {
+    fn default() -> Self {
+        Self {
+            mode: CameraFramingMode::FreeOrbit,
+            margin: 1.3,
+            transition_duration: 1.5,
+            orbit_speed: 0.15,
+            orbit_pitch: 0.35,
+            previous_mode: CameraFramingMode::FreeOrbit,
+            transition: None,
+        }
+    }
+}
+
+pub struct CameraFramingPlugin;
+
+impl Plugin for CameraFramingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CameraFraming>()
+            .add_systems(Update, frame_flock);
+    }
+}
+
+/// Distance from the center of a bounding sphere with `radius` at which the sphere fits in a
+/// perspective view with the given vertical field of view and aspect ratio
+pub fn framing_distance(radius: f32, fov: f32, aspect_ratio: f32) -> f32 {
+    let half_fov_y = fov * 0.5;
+    let half_fov_x = (half_fov_y.tan() * aspect_ratio).atan();
+    radius / half_fov_y.min(half_fov_x).sin()
+}
+
+fn frame_flock(
+    time: Res<Time>,
+    mut framing: ResMut<CameraFraming>,
+    summary: Res<FlockSummary>,
+    inspector: Res<BoidsInspector>,
+    flythrough: Option<Res<Flythrough>>,
+    mut cameras: Query<(&mut PanOrbitCamera, &Projection)>,
+) {
+    if framing.mode == CameraFramingMode::FreeOrbit || summary.boids_count == 0 {
+        framing.previous_mode = framing.mode;
+        return;
+    }
+    // following a single boid takes precedence over framing the whole flock
+    if inspector.follow && inspector.selected.is_some() {
+        return;
+    }
+    // as does a camera path that is played back
+    if flythrough.is_some_and(|flythrough| flythrough.is_active()) {
+        return;
+    }
+    let Ok((mut camera, projection)) = cameras.single_mut() else {
+        return;
+    };
+
+    // ease from wherever the camera is when switching modes, instead of jumping to the flock
+    if framing.mode != framing.previous_mode {
+        framing.previous_mode = framing.mode;
+        framing.transition = Some(FramingTransition {
+            elapsed: 0.0,
+            focus: camera.target_focus,
+            radius: camera.target_radius,
+            pitch: camera.target_pitch,
+        });
+    }
+
+    let (fov, aspect_ratio) = match projection {
+        Projection::Perspective(perspective) => (perspective.fov, perspective.aspect_ratio),
+        _ => (FRAC_PI_4, 1.0),
+    };
+    let bounding_radius = summary.size().length() * 0.5;
+    let mut radius = (framing_distance(bounding_radius, fov, aspect_ratio) * framing.margin)
+        .clamp(MIN_FRAMING_RADIUS, BOX_SIZE * 4.0);
+    let mut focus = summary.centroid;
+    let mut pitch = camera.target_pitch;
+
+    if framing.mode == CameraFramingMode::CinematicOrbit {
+        camera.target_yaw += framing.orbit_speed * time.delta_secs();
+        pitch = framing.orbit_pitch;
+    }
+
+    let duration = framing.transition_duration;
+    if let Some(transition) = framing.transition.as_mut() {
+        transition.elapsed += time.delta_secs();
+        let t = if duration > 0.0 {
+            (transition.elapsed / duration).min(1.0)
+        } else {
+            1.0
+        };
+        let eased = smooth_stop(t, 3);
+        focus = transition.focus.lerp(focus, eased);
+        radius = transition.radius.lerp(radius, eased);
+        pitch = transition.pitch.lerp(pitch, eased);
+
+        if t >= 1.0 {
+            framing.transition = None;
+        }
+    }
+
+    camera.target_focus = focus;
+    camera.target_radius = radius;
+    camera.target_pitch = pitch;
+}
+
+pub fn camera_framing_ui(framing: &mut CameraFraming, ui: &mut Ui) {
+    ui.label("Camera");
+    egui::ComboBox::from_id_salt("camera_framing_mode")
+        .selected_text(framing.mode.label())
+        .show_ui(ui, |ui| {
+            for mode in CameraFramingMode::ALL {
+                ui.selectable_value(&mut framing.mode, mode, mode.label());
+            }
+        });
+    ui.end_row();
+
+    if framing.mode == CameraFramingMode::FreeOrbit {
+        return;
+    }
+    ui.label("Framing margin");
+    ui.add(egui::Slider::new(&mut framing.margin, 1.0..=3.0));
+    ui.end_row();
+    ui.label("Transition duration");
+    ui.add(egui::Slider::new(&mut framing.transition_duration, 0.0..=5.0).suffix(" s"));
+    ui.end_row();
+
+    if framing.mode == CameraFramingMode::CinematicOrbit {
+        ui.label("Orbit speed");
+        ui.add(egui::Slider::new(&mut framing.orbit_speed, -1.0..=1.0).suffix(" rad/s"));
+        ui.end_row();
+        ui.label("Orbit pitch");
+        ui.add(egui::Slider::new(&mut framing.orbit_pitch, -1.5..=1.5).suffix(" rad"));
+        ui.end_row();
+    }
+}
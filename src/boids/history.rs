@@ -0,0 +1,398 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+use bevy_egui::{
+    egui::{self, Ui},
+    EguiContexts,
+};
+
+use super::{boids_compute::BoidsConfig, ui::ui_system};
+
+/// Number of undo steps kept for the boids config
+const HISTORY_CAPACITY: usize = 100;
+
+/// Bounded undo/redo history of a config.
+///
+/// Changes are only recorded once they are finished, so dragging a slider over many frames
+/// becomes a single entry.
+#[derive(Resource)]
+pub struct ConfigHistory<T: Send + Sync + 'static> {
+    undo: VecDeque<T>,
+    redo: Vec<T>,
+    capacity: usize,
+    /// The config as of the most recent entry, edits in progress are compared against it
+    committed: Option<T>,
+}
+
+impl<T: Clone + PartialEq + Send + Sync + 'static> ConfigHistory<T> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            capacity,
+            committed: None,
+        }
+    }
+
+    pub fn undo_len(&self) -> usize {
+        self.undo.len()
+    }
+
+    pub fn redo_len(&self) -> usize {
+        self.redo.len()
+    }
+
+    /// Record `config` as a new entry if it changed, unless it is still being edited
+    pub fn record(&mut self, config: &T, editing: bool) {
+        let Some(committed) = self.committed.as_ref() else {
+            self.committed = Some(config.clone());
+            return;
+        };
+        if editing || committed == config {
+            return;
+        }
+
+        if let Some(previous) = self.committed.replace(config.clone()) {
+            self.undo.push_back(previous);
+        }
+        if self.undo.len() > self.capacity {
+            self.undo.pop_front();
+        }
+        self.redo.clear();
+    }
+
+    /// Restore the config before the last entry, returns whether there was anything to undo
+    pub fn undo(&mut self, config: &mut T) -> bool {
+        // an unfinished edit counts as an entry of its own
+        self.record(config, false);
+        let Some(previous) = self.undo.pop_back() else {
+            return false;
+        };
+        self.redo.push(config.clone());
+        *config = previous.clone();
+        self.committed = Some(previous);
+        true
+    }
+
+    /// Restore the config undone last, returns whether there was anything to redo
+    pub fn redo(&mut self, config: &mut T) -> bool {
+        let Some(next) = self.redo.pop() else {
+            return false;
+        };
+        self.undo.push_back(config.clone());
+        *config = next.clone();
+        self.committed = Some(next);
+        true
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ConfigSlot {
+    #[default]
+    A,
+    B,
+}
+
+impl ConfigSlot {
+    pub fn other(self) -> Self {
+        match self {
+            ConfigSlot::A => ConfigSlot::B,
+            ConfigSlot::B => ConfigSlot::A,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ConfigSlot::A => "A",
+            ConfigSlot::B => "B",
+        }
+    }
+}
+
+/// Two configs to compare, edits apply to the active slot
+#[derive(Resource)]
+pub struct ConfigSlots<T: Send + Sync + 'static> {
+    pub active: ConfigSlot,
+    /// The config of the inactive slot, `None` until the first switch
+    inactive: Option<T>,
+}
+
+impl<T: Send + Sync + 'static> Default for ConfigSlots<T> {
+    fn default() -> Self {
+        Self {
+            active: ConfigSlot::A,
+            inactive: None,
+        }
+    }
+}
+
+impl<T: Clone + Send + Sync + 'static> ConfigSlots<T> {
+    /// Make `slot` active, keeping the current config in the slot that is switched away from. A
+    /// slot that is selected for the first time starts as a copy of the current config.
+    pub fn select(&mut self, slot: ConfigSlot, config: &mut T) {
+        if slot == self.active {
+            return;
+        }
+        let previous = config.clone();
+        if let Some(stored) = self.inactive.take() {
+            *config = stored;
+        }
+        self.inactive = Some(previous);
+        self.active = slot;
+    }
+
+    pub fn toggle(&mut self, config: &mut T) {
+        self.select(self.active.other(), config);
+    }
+
+    /// Overwrite the inactive slot with the current config
+    pub fn copy_to_other(&mut self, config: &T) {
+        self.inactive = Some(config.clone());
+    }
+}
+
+pub struct BoidsHistoryPlugin;
+
+impl Plugin for BoidsHistoryPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(ConfigHistory::<BoidsConfig>::new(HISTORY_CAPACITY))
+            .init_resource::<ConfigSlots<BoidsConfig>>()
+            .add_systems(
+                Update,
+                (
+                    config_history_shortcuts.before(ui_system),
+                    record_config_history.after(ui_system),
+                ),
+            );
+    }
+}
+
+/// Record the config once no widget is dragged or typed into anymore
+fn record_config_history(
+    config: Res<BoidsConfig>,
+    mut history: ResMut<ConfigHistory<BoidsConfig>>,
+    mut contexts: EguiContexts,
+) {
+    let editing = contexts
+        .ctx_mut()
+        .is_ok_and(|ctx| ctx.is_using_pointer() || ctx.wants_keyboard_input());
+    history.record(&config, editing);
+}
+
+/// Ctrl+Z undoes, Ctrl+Shift+Z or Ctrl+Y redoes and Ctrl+B switches between slot A and B
+fn config_history_shortcuts(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut config: ResMut<BoidsConfig>,
+    mut history: ResMut<ConfigHistory<BoidsConfig>>,
+    mut slots: ResMut<ConfigSlots<BoidsConfig>>,
+    mut contexts: EguiContexts,
+) {
+    // text fields handle their own undo
+    if contexts
+        .ctx_mut()
+        .is_ok_and(|ctx| ctx.wants_keyboard_input())
+    {
+        return;
+    }
+    let command = keys.any_pressed([
+        KeyCode::ControlLeft,
+        KeyCode::ControlRight,
+        KeyCode::SuperLeft,
+        KeyCode::SuperRight,
+    ]);
+    if !command {
+        return;
+    }
+    let shift = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+
+    if keys.just_pressed(KeyCode::KeyZ) && !shift {
+        history.undo(config.as_mut());
+    } else if keys.just_pressed(KeyCode::KeyY) || (keys.just_pressed(KeyCode::KeyZ) && shift) {
+        history.redo(config.as_mut());
+    } else if keys.just_pressed(KeyCode::KeyB) {
+        slots.toggle(config.as_mut());
+    }
+}
+
+pub fn config_history_ui(
+    history: &mut ConfigHistory<BoidsConfig>,
+    slots: &mut ConfigSlots<BoidsConfig>,
+    config: &mut BoidsConfig,
+    ui: &mut Ui,
+) {
+    ui.horizontal(|ui| {
+        if ui
+            .add_enabled(history.undo_len() > 0, egui::Button::new("Undo"))
+            .on_hover_text("Ctrl+Z")
+            .clicked()
+        {
+            history.undo(config);
+        }
+        if ui
+            .add_enabled(history.redo_len() > 0, egui::Button::new("Redo"))
+            .on_hover_text("Ctrl+Shift+Z")
+            .clicked()
+        {
+            history.redo(config);
+        }
+    });
+    ui.end_row();
+
+    ui.horizontal(|ui| {
+        ui.label("Compare");
+        for slot in [ConfigSlot::A, ConfigSlot::B] {
+            if ui
+                .selectable_label(slots.active == slot, slot.label())
+                .on_hover_text("Ctrl+B")
+                .clicked()
+            {
+                slots.select(slot, config);
+            }
+        }
+        let label = format!("Copy to {}", slots.active.other().label());
+        if ui.button(label).clicked() {
+            slots.copy_to_other(config);
+        }
+    });
+    ui.end_row();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A history with the values 0 to `last` recorded
+    fn history_up_to(last: i32, capacity: usize) -> ConfigHistory<i32> {
+        let mut history = ConfigHistory::new(capacity);
+        for value in 0..=last {
+            history.record(&value, false);
+        }
+        history
+    }
+
+    #[test]
+    fn undo_and_redo_in_order() {
+        let mut history = history_up_to(3, 10);
+        let mut config = 3;
+        assert_eq!(history.undo_len(), 3);
+
+        for expected in [2, 1, 0] {
+            assert!(history.undo(&mut config));
+            assert_eq!(config, expected);
+        }
+        assert!(!history.undo(&mut config));
+        assert_eq!(config, 0);
+
+        for expected in [1, 2, 3] {
+            assert!(history.redo(&mut config));
+            assert_eq!(config, expected);
+        }
+        assert!(!history.redo(&mut config));
+    }
+
+    #[test]
+    fn new_edit_clears_redo() {
+        let mut history = history_up_to(2, 10);
+        let mut config = 2;
+        history.undo(&mut config);
+        assert_eq!(history.redo_len(), 1);
+
+        config = 5;
+        history.record(&config, false);
+        assert_eq!(history.redo_len(), 0);
+        assert!(!history.redo(&mut config));
+
+        history.undo(&mut config);
+        assert_eq!(config, 1);
+    }
+
+    #[test]
+    fn unchanged_config_is_not_recorded() {
+        let mut history = history_up_to(1, 10);
+        history.record(&1, false);
+        history.record(&1, false);
+        assert_eq!(history.undo_len(), 1);
+    }
+
+    #[test]
+    fn oldest_entries_are_evicted() {
+        let mut history = history_up_to(5, 3);
+        let mut config = 5;
+        assert_eq!(history.undo_len(), 3);
+
+        while history.undo(&mut config) {}
+        assert_eq!(config, 2);
+    }
+
+    #[test]
+    fn drag_is_a_single_entry() {
+        let mut history = history_up_to(0, 10);
+        for value in 1..10 {
+            history.record(&value, true);
+        }
+        assert_eq!(history.undo_len(), 0);
+        history.record(&9, false);
+        assert_eq!(history.undo_len(), 1);
+
+        let mut config = 9;
+        history.undo(&mut config);
+        assert_eq!(config, 0);
+    }
+
+    #[test]
+    fn undo_commits_a_pending_edit() {
+        let mut history = history_up_to(1, 10);
+        let mut config = 7;
+        history.record(&config, true);
+
+        // the edit in progress is undone first, and can be redone
+        assert!(history.undo(&mut config));
+        assert_eq!(config, 1);
+        assert!(history.redo(&mut config));
+        assert_eq!(config, 7);
+    }
+
+    #[test]
+    fn first_selection_copies_the_config() {
+        let mut slots = ConfigSlots::default();
+        let mut config = 1;
+        slots.select(ConfigSlot::B, &mut config);
+        assert_eq!(slots.active, ConfigSlot::B);
+        assert_eq!(config, 1);
+
+        // selecting the active slot changes nothing
+        config = 2;
+        slots.select(ConfigSlot::B, &mut config);
+        assert_eq!(config, 2);
+    }
+
+    #[test]
+    fn slots_keep_their_config() {
+        let mut slots = ConfigSlots::default();
+        let mut config = 1;
+        slots.toggle(&mut config);
+        config = 2;
+
+        slots.toggle(&mut config);
+        assert_eq!(slots.active, ConfigSlot::A);
+        assert_eq!(config, 1);
+        slots.toggle(&mut config);
+        assert_eq!(slots.active, ConfigSlot::B);
+        assert_eq!(config, 2);
+    }
+
+    #[test]
+    fn copy_to_other_slot() {
+        let mut slots = ConfigSlots::default();
+        let mut config = 1;
+        slots.toggle(&mut config);
+        config = 2;
+        slots.copy_to_other(&config);
+
+        config = 3;
+        slots.toggle(&mut config);
+        assert_eq!(config, 2);
+        slots.toggle(&mut config);
+        assert_eq!(config, 3);
+    }
+}
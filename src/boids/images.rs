@@ -8,6 +8,7 @@ use bevy::{
 
 pub const IMAGE_SIZE: u32 = 128;
 
+/// Build the position and velocity maps, which are also copied back by `inspector` to pick boids
 pub fn build_images(mut images: ResMut<Assets<Image>>) -> (Handle<Image>, Handle<Image>) {
     let mut positionmap_image = Image::new_fill(
         Extent3d {
@@ -20,8 +21,10 @@ pub fn build_images(mut images: ResMut<Assets<Image>>) -> (Handle<Image>, Handle
         TextureFormat::Rgba32Float,
         RenderAssetUsages::RENDER_WORLD,
     );
-    positionmap_image.texture_descriptor.usage =
-        TextureUsages::COPY_DST | TextureUsages::STORAGE_BINDING | TextureUsages::TEXTURE_BINDING;
+    positionmap_image.texture_descriptor.usage = TextureUsages::COPY_DST
+        | TextureUsages::COPY_SRC
+        | TextureUsages::STORAGE_BINDING
+        | TextureUsages::TEXTURE_BINDING;
 
     let mut velocitymap_image = Image::new_fill(
         Extent3d {
@@ -34,8 +37,10 @@ pub fn build_images(mut images: ResMut<Assets<Image>>) -> (Handle<Image>, Handle
         TextureFormat::Rgba32Float,
         RenderAssetUsages::RENDER_WORLD,
     );
-    velocitymap_image.texture_descriptor.usage =
-        TextureUsages::COPY_DST | TextureUsages::STORAGE_BINDING | TextureUsages::TEXTURE_BINDING;
+    velocitymap_image.texture_descriptor.usage = TextureUsages::COPY_DST
+        | TextureUsages::COPY_SRC
+        | TextureUsages::STORAGE_BINDING
+        | TextureUsages::TEXTURE_BINDING;
 
     (images.add(positionmap_image), images.add(velocitymap_image))
 }
@@ -0,0 +1,619 @@
+use bevy::{
+    color::palettes::css,
+    prelude::*,
+    render::gpu_readback::{Readback, ReadbackComplete},
+    window::PrimaryWindow,
+};
+use bevy_egui::{
+    egui::{self, Pos2, Ui},
+    EguiContexts,
+};
+use bevy_panorbit_camera::PanOrbitCamera;
+
+use crate::capture::CaptureCamera;
+
+use super::{
+    boids_compute::BoidsConfig,
+    images::IMAGE_SIZE,
+    mesh::BOID_RADIUS,
+    route::BoidsRoute,
+    terrain::{TerrainConfig, TerrainHeights},
+    uniforms::{BoidsImage, HeightfieldUniform, RouteUniform},
+    BOX_SIZE,
+};
+
+/// Boids within this distance of the cursor ray can be picked, a bit larger than the boids
+/// themselves so the far away ones are still easy to hit
+const PICK_RADIUS: f32 = BOID_RADIUS * 2.0;
+
+/// Cursor movement in pixels between press and release above which a click is an orbit drag
+const CLICK_MAX_DISTANCE: f32 = 4.0;
+
+/// The boid that is inspected, and how it is shown
+#[derive(Resource)]
+pub struct BoidsInspector {
+    pub selected: Option<u32>,
+    /// Lock the focus of the [`PanOrbitCamera`] onto the selected boid
+    pub follow: bool,
+    /// Draw the perception radii of the selected boid
+    pub show_radii: bool,
+    /// Cursor ray of a click that waits for a snapshot taken after the click
+    pending_pick: Option<(Ray3d, u32)>,
+}
+
+impl Default for BoidsInspector {
+    fn default() -> Self {
+        Self {
+            selected: None,
+            follow: false,
+            show_radii: true,
+            pending_pick: None,
+        }
+    }
+}
+
+/// Copy of the boid textures read back from the GPU, indexed by boid.
+///
+/// The readback only runs while a boid is selected, a click waits to be resolved or a
+/// [`BoidsSnapshotRequest`] exists.
+#[derive(Resource, Default)]
+pub struct BoidsSnapshot {
+    pub positions: Vec<Vec3>,
+    /// xyz is the velocity, w the number of neighbors within the align range
+    pub velocities: Vec<Vec4>,
+    /// Incremented whenever new positions arrive
+    generation: u32,
+}
+
+impl BoidsSnapshot {
+    /// Incremented whenever new positions arrive, to wait for a snapshot taken after a request
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+}
+
+/// Keeps the [`BoidsSnapshot`] up to date for as long as an entity with this component exists
+#[derive(Component, Default)]
+pub struct BoidsSnapshotRequest;
+
+#[derive(Component)]
+struct BoidsSnapshotReadback;
+
+/// Same as `EPSILON` in `boids_compute.wgsl`
+const EPSILON: f32 = 0.0001;
+
+/// Time step the shader integrates the neighbor terms with
+const DELTA_TIME: f32 = 0.033;
+
+/// The terms the velocity of a boid is updated with, mirroring `loop_through_neighbors`,
+/// `keep_boid_within_bounds`, `seek_waypoint` and `avoid_terrain` in `boids_compute.wgsl`
+#[derive(Default, Clone, Copy)]
+pub struct SteeringBreakdown {
+    pub align_neighbors: u32,
+    pub avoid_neighbors: u32,
+    pub centering_neighbors: u32,
+    pub align: Vec3,
+    pub avoid: Vec3,
+    pub centering: Vec3,
+    pub bounds: Vec3,
+    pub waypoint: Vec3,
+    pub terrain: Vec3,
+}
+
+pub struct BoidsInspectorPlugin;
+
+impl Plugin for BoidsInspectorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<BoidsInspector>()
+            .init_resource::<BoidsSnapshot>()
+            .add_systems(
+                Update,
+                (
+                    request_pick,
+                    update_snapshot_readback,
+                    resolve_pick,
+                    follow_selected_boid,
+                    draw_selected_boid,
+                    inspector_ui_system,
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// Texels of a boid texture read back from the GPU, reordered by boid index
+fn texels_by_boid(data: &[u8]) -> Vec<Vec4> {
+    let texels: Vec<Vec4> = data
+        .chunks_exact(16)
+        .map(|texel| {
+            Vec4::from_array(std::array::from_fn(|channel| {
+                f32::from_le_bytes(texel[channel * 4..channel * 4 + 4].try_into().unwrap())
+            }))
+        })
+        .collect();
+
+    // boid i is stored at texel (i / IMAGE_SIZE, i % IMAGE_SIZE) and the rows are laid out along y
+    let size = IMAGE_SIZE as usize;
+    (0..texels.len())
+        .map(|index| texels[(index % size) * size + index / size])
+        .collect()
+}
+
+fn read_positions(trigger: Trigger<ReadbackComplete>, mut snapshot: ResMut<BoidsSnapshot>) {
+    snapshot.positions = texels_by_boid(&trigger.event().0)
+        .into_iter()
+        .map(Vec4::truncate)
+        .collect();
+    snapshot.generation = snapshot.generation.wrapping_add(1);
+}
+
+fn read_velocities(trigger: Trigger<ReadbackComplete>, mut snapshot: ResMut<BoidsSnapshot>) {
+    snapshot.velocities = texels_by_boid(&trigger.event().0);
+}
+
+/// Index of the boid closest to the ray origin that the ray passes within `radius` of
+pub fn pick_boid(ray: Ray3d, positions: &[Vec3], radius: f32) -> Option<u32> {
+    positions
+        .iter()
+        .enumerate()
+        .filter_map(|(index, position)| {
+            let offset = *position - ray.origin;
+            let t = offset.dot(*ray.direction);
+            let distance_squared = offset.length_squared() - t * t;
+            (t > 0.0 && distance_squared <= radius * radius).then_some((index as u32, t))
+        })
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(index, _)| index)
+}
+
+fn steer_towards(config: &BoidsConfig, velocity_self: Vec3, velocity_towards: Vec3) -> Vec3 {
+    let max_steer_force = 0.01;
+    let v = velocity_towards.normalize_or_zero() * config.max_speed - velocity_self;
+    if v.length_squared() > max_steer_force * max_steer_force {
+        return v.normalize() * max_steer_force;
+    }
+    v
+}
+
+fn seek_waypoint(
+    config: &BoidsConfig,
+    route: &RouteUniform,
+    position: Vec3,
+    velocity: Vec3,
+) -> Vec3 {
+    if route.count == 0 || route.weight <= 0.0 {
+        return Vec3::ZERO;
+    }
+
+    let offset = route.waypoints[route.current as usize].truncate() - position;
+    if offset.length_squared() < EPSILON {
+        return Vec3::ZERO;
+    }
+    steer_towards(config, velocity, offset) * route.weight
+}
+
+/// Height of the terrain below `xz`, interpolated between the `heights` of the grid points
+fn terrain_height(heightfield: &HeightfieldUniform, heights: &[f32], xz: Vec2) -> f32 {
+    let resolution = heightfield.resolution as f32;
+    let grid =
+        ((xz - heightfield.origin) / heightfield.size).clamp(Vec2::ZERO, Vec2::ONE) * resolution;
+    let cell = grid
+        .as_uvec2()
+        .min(UVec2::splat(heightfield.resolution - 1));
+    let t = grid - cell.as_vec2();
+
+    let points = heightfield.resolution + 1;
+    let height = |x: u32, z: u32| heights[(z * points + x) as usize];
+    let h00 = height(cell.x, cell.y);
+    let h10 = height(cell.x + 1, cell.y);
+    let h01 = height(cell.x, cell.y + 1);
+    let h11 = height(cell.x + 1, cell.y + 1);
+    heightfield.base_height + h00.lerp(h10, t.x).lerp(h01.lerp(h11, t.x), t.y)
+}
+
+fn is_above_terrain(heightfield: &HeightfieldUniform, xz: Vec2) -> bool {
+    let local = xz - heightfield.origin;
+    local.cmpge(Vec2::ZERO).all() && local.cmple(Vec2::splat(heightfield.size)).all()
+}
+
+fn avoid_terrain(
+    config: &BoidsConfig,
+    heightfield: &HeightfieldUniform,
+    heights: &[f32],
+    position: Vec3,
+    velocity: Vec3,
+) -> Vec3 {
+    const LOOK_AHEAD_FRAMES: f32 = 30.0;
+
+    let points = heightfield.resolution as usize + 1;
+    if heights.len() != points * points {
+        return Vec3::ZERO;
+    }
+
+    let min_altitude = heightfield.min_altitude.max(EPSILON);
+    let height_at = |xz: Vec2| terrain_height(heightfield, heights, xz);
+    let mut velocity_diff = Vec3::ZERO;
+
+    if is_above_terrain(heightfield, position.xz()) {
+        let altitude = position.y - height_at(position.xz());
+        velocity_diff.y +=
+            (1.0 - altitude / min_altitude).clamp(0.0, 1.0) * config.bounds_turn_factor;
+    }
+
+    let ahead = position + velocity * LOOK_AHEAD_FRAMES;
+    if is_above_terrain(heightfield, ahead.xz()) {
+        let altitude_ahead = ahead.y - height_at(ahead.xz());
+        let urgency = (1.0 - altitude_ahead / min_altitude).clamp(0.0, 1.0);
+        let step = heightfield.size / heightfield.resolution as f32;
+        let slope = Vec2::new(
+            height_at(ahead.xz() + Vec2::new(step, 0.0))
+                - height_at(ahead.xz() - Vec2::new(step, 0.0)),
+            height_at(ahead.xz() + Vec2::new(0.0, step))
+                - height_at(ahead.xz() - Vec2::new(0.0, step)),
+        );
+        if slope.length_squared() > EPSILON {
+            let downhill = -slope.normalize();
+            velocity_diff +=
+                Vec3::new(downhill.x, 0.0, downhill.y) * urgency * config.bounds_turn_factor;
+        }
+        velocity_diff.y += urgency * config.bounds_turn_factor;
+    }
+
+    velocity_diff * heightfield.avoid_factor
+}
+
+/// Compute the steering terms of boid `index` from a snapshot, `heights` being the
+/// [`TerrainHeights`] the `heightfield` describes
+pub fn steering_breakdown(
+    config: &BoidsConfig,
+    route: &RouteUniform,
+    heightfield: &HeightfieldUniform,
+    heights: &[f32],
+    positions: &[Vec3],
+    velocities: &[Vec4],
+    index: usize,
+) -> SteeringBreakdown {
+    let position = positions[index];
+    let velocity = velocities[index].truncate();
+    let mut breakdown = SteeringBreakdown::default();
+    let mut avg_velocity = Vec3::ZERO;
+    let mut avoid_velocity = Vec3::ZERO;
+    let mut center = Vec3::ZERO;
+
+    let neighbors = positions
+        .iter()
+        .zip(velocities)
+        .take(config.boids_count as usize);
+    for (other_position, other_velocity) in neighbors {
+        let offset = position - *other_position;
+        let distance_squared = offset.length_squared();
+        if distance_squared < EPSILON * EPSILON {
+            continue;
+        }
+        if distance_squared < config.align_range * config.align_range {
+            avg_velocity += other_velocity.truncate();
+            breakdown.align_neighbors += 1;
+        }
+        if distance_squared < config.avoid_range * config.avoid_range {
+            avoid_velocity += offset;
+            breakdown.avoid_neighbors += 1;
+        }
+        if distance_squared < config.centering_range * config.centering_range {
+            center += *other_position;
+            breakdown.centering_neighbors += 1;
+        }
+    }
+
+    if breakdown.centering_neighbors > 0 {
+        let towards_center = center / breakdown.centering_neighbors as f32 - position;
+        breakdown.centering =
+            steer_towards(config, velocity, towards_center) * config.centering_factor;
+    }
+    if config.avoid_factor.abs() > EPSILON && avoid_velocity.length_squared() > EPSILON {
+        breakdown.avoid = steer_towards(config, velocity, avoid_velocity) * config.avoid_factor;
+    }
+    if breakdown.align_neighbors > 0 && avg_velocity.length_squared() > EPSILON {
+        let average = avg_velocity / breakdown.align_neighbors as f32;
+        breakdown.align = steer_towards(config, velocity, average) * config.align_factor;
+    }
+
+    let half_box_size = BOX_SIZE * 0.5;
+    let margin = config.bounds_margin;
+    for axis in 0..3 {
+        if position[axis] < -half_box_size + margin {
+            breakdown.bounds[axis] += config.bounds_turn_factor;
+        }
+        if position[axis] > half_box_size - margin {
+            breakdown.bounds[axis] -= config.bounds_turn_factor;
+        }
+    }
+
+    // the later terms see the velocity the earlier ones produced, like in the shader
+    let steered_velocity = velocity
+        + (breakdown.centering + breakdown.avoid + breakdown.align) * DELTA_TIME
+        + breakdown.bounds;
+    breakdown.waypoint = seek_waypoint(config, route, position, steered_velocity);
+    breakdown.terrain = avoid_terrain(
+        config,
+        heightfield,
+        heights,
+        position,
+        steered_velocity + breakdown.waypoint,
+    );
+
+    breakdown
+}
+
+/// Turn a left click that didn't orbit the camera into a pick request
+fn request_pick(
+    mut inspector: ResMut<BoidsInspector>,
+    snapshot: Res<BoidsSnapshot>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform), (With<Camera3d>, Without<CaptureCamera>)>,
+    mut pressed_at: Local<Option<Vec2>>,
+    mut contexts: EguiContexts,
+) {
+    let Ok(window) = windows.single() else {
+        return;
+    };
+    let Some(cursor) = window.cursor_position() else {
+        return;
+    };
+    if contexts
+        .ctx_mut()
+        .is_ok_and(|ctx| ctx.wants_pointer_input() || ctx.is_pointer_over_area())
+    {
+        *pressed_at = None;
+        return;
+    }
+
+    if mouse.just_pressed(MouseButton::Left) {
+        *pressed_at = Some(cursor);
+    }
+    if !mouse.just_released(MouseButton::Left) {
+        return;
+    }
+    let Some(pressed_at) = pressed_at.take() else {
+        return;
+    };
+    if pressed_at.distance(cursor) > CLICK_MAX_DISTANCE {
+        return;
+    }
+
+    let Ok((camera, camera_transform)) = cameras.single() else {
+        return;
+    };
+    if let Ok(ray) = camera.viewport_to_world(camera_transform, cursor) {
+        inspector.pending_pick = Some((ray, snapshot.generation));
+    }
+}
+
+/// Read the boid textures back only while the inspector or a [`BoidsSnapshotRequest`] needs them
+fn update_snapshot_readback(
+    mut commands: Commands,
+    inspector: Res<BoidsInspector>,
+    requests: Query<(), With<BoidsSnapshotRequest>>,
+    mut snapshot: ResMut<BoidsSnapshot>,
+    boids_image: Option<Res<BoidsImage>>,
+    readbacks: Query<Entity, With<BoidsSnapshotReadback>>,
+) {
+    let needed =
+        inspector.selected.is_some() || inspector.pending_pick.is_some() || !requests.is_empty();
+
+    if needed && readbacks.is_empty() {
+        let Some(boids_image) = boids_image else {
+            return;
+        };
+        commands
+            .spawn((
+                BoidsSnapshotReadback,
+                Readback::texture(boids_image.position_map.clone()),
+            ))
+            .observe(read_positions);
+        commands
+            .spawn((
+                BoidsSnapshotReadback,
+                Readback::texture(boids_image.velocity_map.clone()),
+            ))
+            .observe(read_velocities);
+    } else if !needed && !readbacks.is_empty() {
+        readbacks
+            .iter()
+            .for_each(|entity| commands.entity(entity).despawn());
+        snapshot.positions.clear();
+        snapshot.velocities.clear();
+    }
+}
+
+/// Select the boid under the cursor once a snapshot taken after the click has arrived
+fn resolve_pick(
+    mut inspector: ResMut<BoidsInspector>,
+    snapshot: Res<BoidsSnapshot>,
+    config: Res<BoidsConfig>,
+) {
+    let Some((ray, generation)) = inspector.pending_pick else {
+        if inspector
+            .selected
+            .is_some_and(|index| index >= config.boids_count)
+        {
+            inspector.selected = None;
+        }
+        return;
+    };
+    if snapshot.generation == generation || snapshot.positions.is_empty() {
+        return;
+    }
+
+    let count = (config.boids_count as usize).min(snapshot.positions.len());
+    inspector.selected = pick_boid(ray, &snapshot.positions[..count], PICK_RADIUS);
+    inspector.pending_pick = None;
+}
+
+fn follow_selected_boid(
+    inspector: Res<BoidsInspector>,
+    snapshot: Res<BoidsSnapshot>,
+    mut cameras: Query<&mut PanOrbitCamera>,
+) {
+    if !inspector.follow {
+        return;
+    }
+    let Some(position) = inspector
+        .selected
+        .and_then(|index| snapshot.positions.get(index as usize))
+    else {
+        return;
+    };
+
+    for mut camera in &mut cameras {
+        camera.target_focus = *position;
+    }
+}
+
+fn draw_selected_boid(
+    inspector: Res<BoidsInspector>,
+    snapshot: Res<BoidsSnapshot>,
+    config: Res<BoidsConfig>,
+    mut gizmos: Gizmos,
+) {
+    let Some(index) = inspector.selected.map(|index| index as usize) else {
+        return;
+    };
+    let (Some(position), Some(velocity)) = (
+        snapshot.positions.get(index),
+        snapshot.velocities.get(index),
+    ) else {
+        return;
+    };
+
+    gizmos.sphere(*position, BOID_RADIUS * 1.5, Color::WHITE);
+    gizmos.arrow(
+        *position,
+        *position + velocity.truncate().normalize_or_zero() * BOID_RADIUS * 6.0,
+        Color::WHITE,
+    );
+
+    if inspector.show_radii {
+        gizmos.sphere(*position, config.align_range, css::LIGHT_BLUE);
+        gizmos.sphere(*position, config.avoid_range, css::TOMATO);
+        gizmos.sphere(*position, config.centering_range, css::LIGHT_GREEN);
+    }
+}
+
+fn vec3_label(value: Vec3) -> String {
+    format!("{:.2}, {:.2}, {:.2}", value.x, value.y, value.z)
+}
+
+fn steering_ui(label: &str, value: Vec3, ui: &mut Ui) {
+    ui.label(label);
+    ui.label(format!("{} ({:.4})", vec3_label(value), value.length()));
+    ui.end_row();
+}
+
+pub fn inspector_ui(
+    inspector: &mut BoidsInspector,
+    snapshot: &BoidsSnapshot,
+    config: &BoidsConfig,
+    route: &BoidsRoute,
+    terrain: &TerrainConfig,
+    terrain_heights: &TerrainHeights,
+    ui: &mut Ui,
+) {
+    let Some(index) = inspector.selected else {
+        ui.label("Click a boid to inspect it");
+        ui.end_row();
+        return;
+    };
+
+    ui.label("Boid");
+    ui.label(index.to_string());
+    ui.end_row();
+
+    let index = index as usize;
+    if index >= snapshot.positions.len() || index >= snapshot.velocities.len() {
+        ui.label("Waiting for readback");
+        ui.end_row();
+        return;
+    }
+
+    let position = snapshot.positions[index];
+    let velocity = snapshot.velocities[index].truncate();
+    let breakdown = steering_breakdown(
+        config,
+        &RouteUniform::from(route),
+        &HeightfieldUniform::from(terrain),
+        &terrain_heights.0,
+        &snapshot.positions,
+        &snapshot.velocities,
+        index,
+    );
+
+    ui.label("Position");
+    ui.label(vec3_label(position));
+    ui.end_row();
+    ui.label("Velocity");
+    ui.label(vec3_label(velocity));
+    ui.end_row();
+    ui.label("Speed");
+    ui.label(format!(
+        "{:.3} / {:.3}",
+        velocity.length(),
+        config.max_speed
+    ));
+    ui.end_row();
+    ui.label("Neighbors (align / avoid / centering)");
+    ui.label(format!(
+        "{} / {} / {}",
+        breakdown.align_neighbors, breakdown.avoid_neighbors, breakdown.centering_neighbors
+    ));
+    ui.end_row();
+    steering_ui("Align", breakdown.align, ui);
+    steering_ui("Avoid", breakdown.avoid, ui);
+    steering_ui("Centering", breakdown.centering, ui);
+    steering_ui("Bounds", breakdown.bounds, ui);
+    steering_ui("Waypoint", breakdown.waypoint, ui);
+    steering_ui("Terrain", breakdown.terrain, ui);
+
+    ui.checkbox(&mut inspector.follow, "Follow");
+    ui.end_row();
+    ui.checkbox(&mut inspector.show_radii, "Show perception radii");
+    ui.end_row();
+    if ui.button("Deselect").clicked() {
+        inspector.selected = None;
+        inspector.follow = false;
+    }
+    ui.end_row();
+}
+
+fn inspector_ui_system(
+    mut inspector: ResMut<BoidsInspector>,
+    snapshot: Res<BoidsSnapshot>,
+    config: Res<BoidsConfig>,
+    route: Res<BoidsRoute>,
+    terrain: Res<TerrainConfig>,
+    terrain_heights: Res<TerrainHeights>,
+    mut contexts: EguiContexts,
+) {
+    let Ok(ctx) = contexts.ctx_mut() else {
+        return;
+    };
+    egui::Window::new("Boid inspector")
+        .default_pos(Pos2 { x: 420., y: 10. })
+        .show(ctx, |ui| {
+            egui::Grid::new("boid_inspector_grid")
+                .num_columns(2)
+                .spacing([40.0, 4.0])
+                .striped(true)
+                .show(ui, |ui| {
+                    inspector_ui(
+                        inspector.as_mut(),
+                        &snapshot,
+                        &config,
+                        &route,
+                        &terrain,
+                        &terrain_heights,
+                        ui,
+                    );
+                });
+        });
+}
@@ -1,13 +1,30 @@
 pub mod mesh;
-use mesh::{spawn_bbox, spawn_boids, update_visibility, BoidsMaterial};
-mod boids_compute;
+use mesh::{spawn_bbox, spawn_boids};
+pub(crate) mod boids_compute;
+pub mod color;
+pub mod diagnostics;
+pub mod export;
+pub mod framing;
+pub mod history;
 mod images;
+pub mod inspector;
+pub mod remote;
+pub mod render;
+pub mod route;
+pub mod summary;
+pub mod terrain;
+pub mod trails;
 mod ui;
 mod uniforms;
 
-use bevy::{pbr::ExtendedMaterial, prelude::*};
+use bevy::prelude::*;
 
-use self::{boids_compute::BoidsComputePlugin, ui::ui_system};
+use self::{
+    boids_compute::BoidsComputePlugin, diagnostics::BoidsDiagnosticsPlugin,
+    export::BoidsExportPlugin, framing::CameraFramingPlugin, history::BoidsHistoryPlugin,
+    inspector::BoidsInspectorPlugin, render::BoidsRenderPlugin, route::BoidsRoutePlugin,
+    summary::FlockSummaryPlugin, terrain::TerrainPlugin, trails::BoidsTrailsPlugin, ui::ui_system,
+};
 
 pub const BOX_SIZE: f32 = 1000.0;
 
@@ -15,13 +32,19 @@ pub struct LowPolyTerrainPlugin;
 
 impl Plugin for LowPolyTerrainPlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugins(MaterialPlugin::<
-            ExtendedMaterial<StandardMaterial, BoidsMaterial>,
-        >::default())
+        app.add_plugins(BoidsRenderPlugin)
             .add_plugins(BoidsComputePlugin)
+            .add_plugins(BoidsDiagnosticsPlugin)
+            .add_plugins(BoidsTrailsPlugin)
+            .add_plugins(BoidsInspectorPlugin)
+            .add_plugins(TerrainPlugin)
+            .add_plugins(BoidsRoutePlugin)
+            .add_plugins(FlockSummaryPlugin)
+            .add_plugins(CameraFramingPlugin)
+            .add_plugins(BoidsExportPlugin)
+            .add_plugins(BoidsHistoryPlugin)
             .add_systems(Startup, spawn_boids)
             .add_systems(Startup, spawn_bbox)
-            .add_systems(Update, ui_system)
-            .add_systems(Update, update_visibility);
+            .add_systems(Update, ui_system);
     }
 }
@@ -0,0 +1,680 @@
+//! Control the flock from scripts over HTTP with JSON bodies, on localhost only.
+//!
+//! - `GET /config` returns the [`BoidsConfig`]
+//! - `PUT /config` updates the fields of the [`BoidsConfig`] that are in the body
+//! - `POST /pause`, `POST /resume` and `POST /restart` control the simulation
+//! - `POST /step` pauses and advances the simulation by `{"frames": n}`, one frame by default
+//! - `POST /snapshot` returns the positions and velocities of all boids
+
+use std::{
+    fmt::Display,
+    io::{BufRead, BufReader, Read, Write},
+    net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream},
+    ops::RangeInclusive,
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use bevy::{
+    prelude::*,
+    reflect::{
+        serde::{TypedReflectDeserializer, TypedReflectSerializer},
+        TypeRegistry,
+    },
+};
+use serde::de::DeserializeSeed;
+use serde_json::{json, Value};
+
+use super::{
+    boids_compute::{BoidsConfig, BoidsSimulation},
+    inspector::{BoidsSnapshot, BoidsSnapshotRequest},
+};
+
+pub const DEFAULT_REMOTE_PORT: u16 = 15702;
+
+/// Requests with a larger body are rejected
+const MAX_BODY_SIZE: usize = 64 * 1024;
+
+/// How long a connection waits for the app to answer, snapshots take a few frames
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Status code and body of a response
+type RemoteResponse = (u16, Value);
+
+struct RemoteRequest {
+    method: String,
+    path: String,
+    body: String,
+    respond: Sender<RemoteResponse>,
+}
+
+/// Requests received by the server thread, answered on the main thread
+#[derive(Resource)]
+struct RemoteRequests(Mutex<Receiver<RemoteRequest>>);
+
+/// A `POST /snapshot` that waits for a snapshot taken after it was requested
+#[derive(Component)]
+#[require(BoidsSnapshotRequest)]
+struct RemoteSnapshotRequest {
+    generation: u32,
+    respond: Sender<RemoteResponse>,
+}
+
+/// The address the remote control API is served on, which has the actual port when the plugin
+/// was added with port 0
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct BoidsRemoteAddress(pub SocketAddr);
+
+/// Serves the remote control API on `127.0.0.1:port`
+pub struct BoidsRemotePlugin {
+    pub port: u16,
+}
+
+impl Default for BoidsRemotePlugin {
+    fn default() -> Self {
+        Self {
+            port: DEFAULT_REMOTE_PORT,
+        }
+    }
+}
+
+impl Plugin for BoidsRemotePlugin {
+    fn build(&self, app: &mut App) {
+        let bound = TcpListener::bind((Ipv4Addr::LOCALHOST, self.port)).and_then(|listener| {
+            let address = listener.local_addr()?;
+            Ok((listener, address))
+        });
+        let (listener, address) = match bound {
+            Ok(bound) => bound,
+            Err(error) => {
+                error!("Failed to listen on port {}: {error}", self.port);
+                return;
+            }
+        };
+        info!("Remote control listening on http://{address}");
+
+        let (sender, receiver) = channel();
+        std::thread::spawn(move || serve(listener, sender));
+
+        app.register_type::<BoidsConfig>()
+            .insert_resource(RemoteRequests(Mutex::new(receiver)))
+            .insert_resource(BoidsRemoteAddress(address))
+            .add_systems(
+                Update,
+                (handle_remote_requests, finish_snapshot_requests).chain(),
+            );
+    }
+}
+
+/// The port passed with `--remote-port <port>`, or the default port if only `--remote` is passed
+pub fn parse_cli_remote(mut args: impl Iterator<Item = String>) -> Result<Option<u16>, String> {
+    let mut port = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--remote" => port = port.or(Some(DEFAULT_REMOTE_PORT)),
+            "--remote-port" => {
+                port = Some(
+                    args.next()
+                        .and_then(|port| port.parse().ok())
+                        .ok_or("--remote-port needs a port number")?,
+                );
+            }
+            _ => {}
+        }
+    }
+    Ok(port)
+}
+
+fn serve(listener: TcpListener, requests: Sender<RemoteRequest>) {
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else {
+            continue;
+        };
+        // a connection waiting for a snapshot shouldn't hold up the others
+        let requests = requests.clone();
+        std::thread::spawn(move || {
+            if let Err(error) = handle_connection(stream, &requests) {
+                warn!("Remote control connection failed: {error}");
+            }
+        });
+    }
+}
+
+fn status_text(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        500 => "Internal Server Error",
+        _ => "Service Unavailable",
+    }
+}
+
+fn error_json(message: impl Into<String>) -> Value {
+    json!({ "error": message.into() })
+}
+
+fn handle_connection(
+    mut stream: TcpStream,
+    requests: &Sender<RemoteRequest>,
+) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let path = parts.next().unwrap_or_default().to_string();
+
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            }
+        }
+    }
+
+    let (status, body) = if content_length > MAX_BODY_SIZE {
+        (413, error_json("The request body is too large"))
+    } else {
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body)?;
+        let (respond, response) = channel();
+        let request = RemoteRequest {
+            method,
+            path,
+            body: String::from_utf8_lossy(&body).into_owned(),
+            respond,
+        };
+        if requests.send(request).is_ok() {
+            response
+                .recv_timeout(RESPONSE_TIMEOUT)
+                .unwrap_or_else(|_| (503, error_json("The app did not respond in time")))
+        } else {
+            (503, error_json("The app is shutting down"))
+        }
+    };
+
+    let body = body.to_string();
+    write!(
+        stream,
+        "HTTP/1.1 {status} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+         Connection: close\r\n\r\n{body}",
+        status_text(status),
+        body.len(),
+    )?;
+    stream.flush()
+}
+
+fn check_limits<T: PartialOrd + Display>(
+    name: &str,
+    value: T,
+    limits: RangeInclusive<T>,
+) -> Result<(), String> {
+    if limits.contains(&value) {
+        Ok(())
+    } else {
+        Err(format!(
+            "{name} must be between {} and {}",
+            limits.start(),
+            limits.end()
+        ))
+    }
+}
+
+/// The same limits as the sliders in the boids window, so a script can't size buffers past what
+/// the simulation allocates or pass values the shaders divide by. NaN is outside every range.
+pub fn validate_config(config: &BoidsConfig) -> Result<(), String> {
+    check_limits(
+        "boids_count",
+        config.boids_count,
+        BoidsConfig::BOIDS_COUNT_LIMITS,
+    )?;
+    check_limits(
+        "align_range",
+        config.align_range,
+        BoidsConfig::ALIGN_RANGE_LIMITS,
+    )?;
+    check_limits(
+        "avoid_range",
+        config.avoid_range,
+        BoidsConfig::AVOID_RANGE_LIMITS,
+    )?;
+    check_limits(
+        "centering_range",
+        config.centering_range,
+        BoidsConfig::CENTERING_RANGE_LIMITS,
+    )?;
+    check_limits(
+        "align_factor",
+        config.align_factor,
+        BoidsConfig::FACTOR_LIMITS,
+    )?;
+    check_limits(
+        "avoid_factor",
+        config.avoid_factor,
+        BoidsConfig::FACTOR_LIMITS,
+    )?;
+    check_limits(
+        "centering_factor",
+        config.centering_factor,
+        BoidsConfig::FACTOR_LIMITS,
+    )?;
+    check_limits(
+        "bounds_margin",
+        config.bounds_margin,
+        BoidsConfig::BOUNDS_MARGIN_LIMITS,
+    )?;
+    check_limits(
+        "bounds_turn_factor",
+        config.bounds_turn_factor,
+        BoidsConfig::BOUNDS_TURN_FACTOR_LIMITS,
+    )?;
+    check_limits("max_speed", config.max_speed, BoidsConfig::MAX_SPEED_LIMITS)?;
+    check_limits(
+        "lod_low_distance",
+        config.lod_low_distance,
+        BoidsConfig::LOD_DISTANCE_LIMITS,
+    )?;
+    check_limits(
+        "lod_medium_distance",
+        config.lod_medium_distance,
+        *BoidsConfig::LOD_DISTANCE_LIMITS.start()..=config.lod_low_distance,
+    )?;
+    check_limits(
+        "trail_length",
+        config.trail_length,
+        BoidsConfig::TRAIL_LENGTH_LIMITS,
+    )?;
+    check_limits(
+        "density_max_neighbors",
+        config.density_max_neighbors,
+        BoidsConfig::DENSITY_MAX_NEIGHBORS_LIMITS,
+    )?;
+    check_limits(
+        "species_count",
+        config.species_count,
+        BoidsConfig::SPECIES_COUNT_LIMITS,
+    )?;
+
+    // the color pickers only offer channels in [0, 1]
+    let channels = std::iter::once(&config.solid_color)
+        .chain(&config.color_ramp)
+        .flat_map(|color| color.to_f32_array());
+    for channel in channels {
+        check_limits("color channels", channel, 0.0..=1.0)?;
+    }
+    Ok(())
+}
+
+fn config_json(config: &BoidsConfig, registry: &TypeRegistry) -> Result<Value, (u16, String)> {
+    serde_json::to_value(TypedReflectSerializer::new(config, registry))
+        .map_err(|error| (500, error.to_string()))
+}
+
+/// Overwrite the members of `target` with those in `patch`, objects may leave out members to keep
+/// their current value
+fn merge_json(target: &mut Value, patch: Value) -> Result<(), String> {
+    match (target, patch) {
+        (Value::Object(target), Value::Object(patch)) => {
+            for (name, value) in patch {
+                let member = target
+                    .get_mut(&name)
+                    .ok_or_else(|| format!("unknown field {name}"))?;
+                merge_json(member, value).map_err(|error| format!("{name}: {error}"))?;
+            }
+            Ok(())
+        }
+        (target, patch) => {
+            *target = patch;
+            Ok(())
+        }
+    }
+}
+
+/// Apply the fields in `body` to a copy of the config, so an invalid body leaves it untouched
+fn put_config(
+    config: &mut BoidsConfig,
+    body: &str,
+    registry: &TypeRegistry,
+) -> Result<Value, (u16, String)> {
+    let patch = serde_json::from_str(body).map_err(|error| (400, error.to_string()))?;
+    let mut json = config_json(config, registry)?;
+    merge_json(&mut json, patch).map_err(|error| (400, error))?;
+    let reflected = TypedReflectDeserializer::of::<BoidsConfig>(registry)
+        .deserialize(json)
+        .map_err(|error| (400, error.to_string()))?;
+    let updated = BoidsConfig::from_reflect(&*reflected)
+        .ok_or_else(|| (400, "the body does not contain a config".to_string()))?;
+    validate_config(&updated).map_err(|error| (400, error))?;
+    *config = updated;
+    config_json(config, registry)
+}
+
+fn step_frames(body: &str) -> Result<u32, (u16, String)> {
+    if body.trim().is_empty() {
+        return Ok(1);
+    }
+    let json: Value = serde_json::from_str(body).map_err(|error| (400, error.to_string()))?;
+    match json.get("frames").and_then(Value::as_f64) {
+        None => Ok(1),
+        Some(frames) if frames >= 1.0 && frames.fract() == 0.0 && frames <= u32::MAX as f64 => {
+            Ok(frames as u32)
+        }
+        Some(frames) => Err((400, format!("Can't step {frames} frames"))),
+    }
+}
+
+fn simulation_json(simulation: &BoidsSimulation) -> Value {
+    json!({
+        "paused": simulation.paused,
+        "pending_steps": simulation.pending_steps,
+        "restarts": simulation.restarts,
+    })
+}
+
+fn handle_remote_requests(
+    mut commands: Commands,
+    requests: Res<RemoteRequests>,
+    mut config: ResMut<BoidsConfig>,
+    mut simulation: ResMut<BoidsSimulation>,
+    snapshot: Res<BoidsSnapshot>,
+    registry: Res<AppTypeRegistry>,
+) {
+    let Ok(receiver) = requests.0.lock() else {
+        return;
+    };
+    let registry = registry.read();
+    for request in receiver.try_iter() {
+        let response = match (request.method.as_str(), request.path.as_str()) {
+            ("GET", "/config") => config_json(&config, &registry),
+            ("PUT", "/config") => put_config(&mut config, &request.body, &registry),
+            ("POST", "/pause") => {
+                simulation.paused = true;
+                Ok(simulation_json(&simulation))
+            }
+            ("POST", "/resume") => {
+                simulation.paused = false;
+                Ok(simulation_json(&simulation))
+            }
+            ("POST", "/restart") => {
+                simulation.restart();
+                Ok(simulation_json(&simulation))
+            }
+            ("POST", "/step") => step_frames(&request.body).map(|frames| {
+                simulation.paused = true;
+                simulation.step(frames);
+                simulation_json(&simulation)
+            }),
+            ("POST", "/snapshot") => {
+                commands.spawn(RemoteSnapshotRequest {
+                    generation: snapshot.generation(),
+                    respond: request.respond,
+                });
+                continue;
+            }
+            (method, "/config" | "/pause" | "/resume" | "/restart" | "/step" | "/snapshot") => {
+                Err((405, format!("{method} is not allowed on {}", request.path)))
+            }
+            (_, path) => Err((404, format!("{path} not found"))),
+        };
+
+        // the connection is gone if it timed out, there is no one left to answer
+        let _ = request.respond.send(match response {
+            Ok(json) => (200, json),
+            Err((status, error)) => (status, error_json(error)),
+        });
+    }
+}
+
+fn finish_snapshot_requests(
+    mut commands: Commands,
+    requests: Query<(Entity, &RemoteSnapshotRequest)>,
+    snapshot: Res<BoidsSnapshot>,
+    config: Res<BoidsConfig>,
+) {
+    let complete =
+        !snapshot.positions.is_empty() && snapshot.velocities.len() >= snapshot.positions.len();
+    for (entity, request) in &requests {
+        if !complete || snapshot.generation() == request.generation {
+            continue;
+        }
+
+        let count = snapshot.positions.len().min(config.boids_count as usize);
+        let positions: Vec<_> = snapshot.positions[..count]
+            .iter()
+            .map(|position| position.to_array())
+            .collect();
+        let velocities: Vec<_> = snapshot.velocities[..count]
+            .iter()
+            .map(|velocity| velocity.truncate().to_array())
+            .collect();
+        let _ = request.respond.send((
+            200,
+            json!({
+                "boids_count": count,
+                "positions": positions,
+                "velocities": velocities,
+            }),
+        ));
+        commands.entity(entity).despawn();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+
+    /// An app with the resources the API works on, serving on a free port
+    fn remote_app() -> (App, SocketAddr) {
+        let mut app = App::new();
+        app.init_resource::<BoidsConfig>()
+            .init_resource::<BoidsSimulation>()
+            .init_resource::<BoidsSnapshot>()
+            .add_plugins(BoidsRemotePlugin { port: 0 });
+        let address = app.world().resource::<BoidsRemoteAddress>().0;
+        (app, address)
+    }
+
+    fn send(address: SocketAddr, method: &str, path: &str, body: &str) -> (u16, Value) {
+        let mut stream = TcpStream::connect(address).unwrap();
+        write!(
+            stream,
+            "{method} {path} HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        let status = head.split_whitespace().nth(1).unwrap().parse().unwrap();
+        (status, serde_json::from_str(body).unwrap())
+    }
+
+    /// Sends a request from another thread and updates the app until it is answered
+    fn request(app: &mut App, method: &str, path: &str, body: &str) -> (u16, Value) {
+        let address = app.world().resource::<BoidsRemoteAddress>().0;
+        let (method, path, body) = (method.to_string(), path.to_string(), body.to_string());
+        let client = std::thread::spawn(move || send(address, &method, &path, &body));
+
+        let start = Instant::now();
+        while !client.is_finished() {
+            assert!(start.elapsed() < RESPONSE_TIMEOUT, "no response");
+            app.update();
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        client.join().unwrap()
+    }
+
+    fn number(json: &Value, key: &str) -> f64 {
+        json.get(key).and_then(Value::as_f64).unwrap()
+    }
+
+    #[test]
+    fn get_config() {
+        let (mut app, _) = remote_app();
+        let (status, json) = request(&mut app, "GET", "/config", "");
+        assert_eq!(status, 200);
+        let registry = app.world().resource::<AppTypeRegistry>().read();
+        let config = app.world().resource::<BoidsConfig>();
+        assert_eq!(json, config_json(config, &registry).unwrap());
+        assert_eq!(json.get("color_mode"), Some(&json!("Solid")));
+    }
+
+    #[test]
+    fn put_config_updates_the_given_fields() {
+        let (mut app, _) = remote_app();
+        let before = *app.world().resource::<BoidsConfig>();
+
+        let (status, json) = request(
+            &mut app,
+            "PUT",
+            "/config",
+            r#"{"boids_count": 100, "max_speed": 3.5}"#,
+        );
+        assert_eq!(status, 200);
+        assert_eq!(number(&json, "boids_count"), 100.0);
+
+        let config = app.world().resource::<BoidsConfig>();
+        assert_eq!(config.boids_count, 100);
+        assert_eq!(config.max_speed, 3.5);
+        assert_eq!(config.centering_factor, before.centering_factor);
+    }
+
+    #[test]
+    fn invalid_put_config_is_rejected() {
+        let (mut app, _) = remote_app();
+        let before = *app.world().resource::<BoidsConfig>();
+
+        for body in [
+            r#"{"boids_count": 0}"#,
+            r#"{"boids_count": 100, "trail_length": 1}"#,
+            r#"{"boids_count": -1}"#,
+            r#"{"max_speed": 0}"#,
+            r#"{"max_speed": -1}"#,
+            r#"{"max_speed": 1e39}"#,
+            r#"{"align_factor": 11}"#,
+            r#"{"density_max_neighbors": 0}"#,
+            r#"{"species_count": 9}"#,
+            r#"{"lod_medium_distance": 3000, "lod_low_distance": 2000}"#,
+            r#"{"solid_color": {"red": 2}}"#,
+            r#"{"no_such_field": 1}"#,
+            "{",
+            // deeper than the parser recurses
+            "[".repeat(MAX_BODY_SIZE - 1).as_str(),
+        ] {
+            let (status, json) = request(&mut app, "PUT", "/config", body);
+            assert_eq!(status, 400, "{body}");
+            assert!(json.get("error").is_some());
+        }
+        assert!(*app.world().resource::<BoidsConfig>() == before);
+    }
+
+    #[test]
+    fn config_limits() {
+        assert_eq!(validate_config(&BoidsConfig::default()), Ok(()));
+
+        let invalid: [fn(&mut BoidsConfig); 6] = [
+            |config| config.max_speed = f32::NAN,
+            |config| config.max_speed = f32::INFINITY,
+            |config| config.bounds_turn_factor = 0.0,
+            |config| config.density_max_neighbors = 0,
+            |config| config.lod_medium_distance = config.lod_low_distance + 1.0,
+            |config| config.color_ramp[1].green = f32::NAN,
+        ];
+        for (index, update) in invalid.into_iter().enumerate() {
+            let mut config = BoidsConfig::default();
+            update(&mut config);
+            assert!(validate_config(&config).is_err(), "{index}");
+        }
+    }
+
+    #[test]
+    fn pause_resume_and_step() {
+        let (mut app, _) = remote_app();
+
+        let (status, json) = request(&mut app, "POST", "/pause", "");
+        assert_eq!(status, 200);
+        assert_eq!(json.get("paused"), Some(&Value::Bool(true)));
+        assert!(app.world().resource::<BoidsSimulation>().paused);
+
+        let (status, json) = request(&mut app, "POST", "/resume", "");
+        assert_eq!(status, 200);
+        assert_eq!(json.get("paused"), Some(&Value::Bool(false)));
+
+        // stepping pauses, without the simulation systems the steps stay pending
+        let (status, json) = request(&mut app, "POST", "/step", r#"{"frames": 3}"#);
+        assert_eq!(status, 200);
+        assert_eq!(json.get("paused"), Some(&Value::Bool(true)));
+        assert_eq!(number(&json, "pending_steps"), 3.0);
+
+        let (status, json) = request(&mut app, "POST", "/step", "");
+        assert_eq!(status, 200);
+        assert_eq!(number(&json, "pending_steps"), 4.0);
+
+        let (status, _) = request(&mut app, "POST", "/step", r#"{"frames": 0.5}"#);
+        assert_eq!(status, 400);
+        assert_eq!(app.world().resource::<BoidsSimulation>().pending_steps, 4);
+
+        let (status, json) = request(&mut app, "POST", "/restart", "");
+        assert_eq!(status, 200);
+        assert_eq!(number(&json, "restarts"), 1.0);
+    }
+
+    #[test]
+    fn unknown_paths_and_methods() {
+        let (mut app, _) = remote_app();
+
+        let (status, json) = request(&mut app, "GET", "/flock", "");
+        assert_eq!(status, 404);
+        assert!(json.get("error").is_some());
+
+        for (method, path) in [("POST", "/config"), ("GET", "/pause"), ("DELETE", "/step")] {
+            let (status, _) = request(&mut app, method, path, "");
+            assert_eq!(status, 405, "{method} {path}");
+        }
+    }
+
+    #[test]
+    fn large_bodies_are_rejected() {
+        let (_, address) = remote_app();
+        // answered by the connection thread without reading the body, so none is sent
+        let mut stream = TcpStream::connect(address).unwrap();
+        write!(
+            stream,
+            "PUT /config HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            MAX_BODY_SIZE + 1
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 413 "), "{response}");
+    }
+
+    #[test]
+    fn cli_remote_port() {
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        assert_eq!(parse_cli_remote(args(&[]).into_iter()), Ok(None));
+        assert_eq!(
+            parse_cli_remote(args(&["--remote"]).into_iter()),
+            Ok(Some(DEFAULT_REMOTE_PORT))
+        );
+        assert_eq!(
+            parse_cli_remote(args(&["--remote", "--remote-port", "8080"]).into_iter()),
+            Ok(Some(8080))
+        );
+        assert!(parse_cli_remote(args(&["--remote-port", "x"]).into_iter()).is_err());
+    }
+}
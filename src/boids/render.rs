@@ -0,0 +1,505 @@
+use std::num::NonZeroU64;
+
+use bevy::{
+    core_pipeline::core_3d::{
+        graph::{Core3d, Node3d},
+        Opaque3d, Opaque3dBatchSetKey, Opaque3dBinKey,
+    },
+    ecs::{
+        query::QueryItem,
+        system::{
+            lifetimeless::{Read, SRes},
+            SystemParamItem,
+        },
+    },
+    pbr::{
+        MeshPipeline, MeshPipelineKey, RenderMeshInstances, SetMeshBindGroup, SetMeshViewBindGroup,
+    },
+    prelude::*,
+    render::{
+        camera::ExtractedCamera,
+        diagnostic::RecordDiagnostics,
+        extract_component::{ExtractComponent, ExtractComponentPlugin},
+        gpu_readback::ReadbackComplete,
+        mesh::{
+            allocator::MeshAllocator, MeshVertexBufferLayoutRef, RenderMesh, RenderMeshBufferInfo,
+        },
+        render_asset::RenderAssets,
+        render_graph::{
+            NodeRunError, RenderGraphApp, RenderGraphContext, RenderLabel, ViewNode, ViewNodeRunner,
+        },
+        render_phase::{
+            AddRenderCommand, DrawFunctions, PhaseItem, PhaseItemExtraIndex, RenderCommand,
+            RenderCommandResult, SetItemPipeline, TrackedRenderPass, ViewBinnedRenderPhases,
+        },
+        render_resource::{
+            binding_types::{storage_buffer_read_only_sized, texture_2d, uniform_buffer},
+            encase, BindGroup, BindGroupEntries, BindGroupLayout, BindGroupLayoutEntries,
+            BufferBinding, PipelineCache, RenderPassDescriptor, RenderPipelineDescriptor,
+            ShaderStages, ShaderType, SpecializedMeshPipeline, SpecializedMeshPipelineError,
+            SpecializedMeshPipelines, StoreOp, TextureSampleType,
+        },
+        renderer::{RenderContext, RenderDevice, RenderQueue},
+        storage::GpuShaderStorageBuffer,
+        sync_world::MainEntity,
+        texture::GpuImage,
+        view::{ExtractedView, Msaa, ViewDepthTexture, ViewTarget},
+        Render, RenderApp, RenderSet,
+    },
+};
+
+use super::{
+    boids_compute::BoidsConfig,
+    diagnostics::BOIDS_DRAW_SPAN,
+    images::IMAGE_SIZE,
+    uniforms::{
+        BoidsColorUniform, BoidsColorUniformBuffer, BoidsImage, BoidsIndirectArgs,
+        BoidsInstanceBuffers, DrawIndexedIndirectArgs, LOD_COUNT,
+    },
+};
+
+const SHADER_PATH: &str = "shaders/boids_material.wgsl";
+
+/// Size in bytes of the visible instance bucket of a single LOD
+const VISIBLE_INSTANCES_BUCKET_SIZE: u64 =
+    (IMAGE_SIZE * IMAGE_SIZE) as u64 * size_of::<u32>() as u64;
+
+/// Marks the entity holding the boid mesh of the given level of detail, 0 being the most detailed
+#[derive(Component, Clone, Copy, ExtractComponent)]
+pub struct BoidsLod(pub u32);
+
+/// Visible and drawn boid counts of the last frame that was read back from the GPU
+#[derive(Resource, Default, Clone, Copy)]
+pub struct BoidsCullStats {
+    pub visible: u32,
+    pub drawn_per_lod: [u32; LOD_COUNT],
+    pub drawn_triangles: u64,
+}
+
+pub fn update_cull_stats(trigger: Trigger<ReadbackComplete>, mut stats: ResMut<BoidsCullStats>) {
+    let args: BoidsIndirectArgs = trigger.event().to_shader_type();
+
+    stats.drawn_per_lod = args.lods.map(|lod| lod.instance_count);
+    stats.visible = stats.drawn_per_lod.iter().sum();
+    stats.drawn_triangles = args
+        .lods
+        .iter()
+        .map(|lod| u64::from(lod.instance_count) * u64::from(lod.index_count / 3))
+        .sum();
+}
+
+/// Draws the boids in a render pass of their own, right after the main opaque pass, so the GPU
+/// time of drawing them can be measured on its own.
+///
+/// The visible instances are written per frame by the culling pass for the main camera only, so
+/// the boids are left out of the prepass, the deferred pass and the shadow maps.
+pub struct BoidsRenderPlugin;
+
+impl Plugin for BoidsRenderPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(ExtractComponentPlugin::<BoidsLod>::default())
+            .init_resource::<BoidsCullStats>();
+
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app
+            .add_render_command::<Opaque3d, DrawBoids>()
+            .init_resource::<SpecializedMeshPipelines<BoidsRenderPipeline>>()
+            .add_systems(
+                Render,
+                (
+                    queue_boids.in_set(RenderSet::QueueMeshes),
+                    prepare_indirect_args.in_set(RenderSet::PrepareResources),
+                    prepare_draw_bind_groups.in_set(RenderSet::PrepareBindGroups),
+                ),
+            )
+            .add_render_graph_node::<ViewNodeRunner<BoidsDrawNode>>(Core3d, BoidsDrawLabel)
+            .add_render_graph_edges(
+                Core3d,
+                (
+                    Node3d::MainOpaquePass,
+                    BoidsDrawLabel,
+                    Node3d::MainTransmissivePass,
+                ),
+            );
+    }
+
+    fn finish(&self, app: &mut App) {
+        app.sub_app_mut(RenderApp)
+            .init_resource::<BoidsRenderPipeline>()
+            .init_resource::<BoidsColorUniformBuffer>();
+    }
+}
+
+#[derive(Resource)]
+struct BoidsRenderPipeline {
+    shader: Handle<Shader>,
+    mesh_pipeline: MeshPipeline,
+    bind_group_layout: BindGroupLayout,
+}
+
+impl FromWorld for BoidsRenderPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+
+        // bindings start at 100 to stay clear of the standard material bindings imported by bevy_pbr
+        let entries = BindGroupLayoutEntries::with_indices(
+            ShaderStages::VERTEX_FRAGMENT,
+            (
+                (
+                    100,
+                    texture_2d(TextureSampleType::Float { filterable: false }),
+                ),
+                (
+                    101,
+                    texture_2d(TextureSampleType::Float { filterable: false }),
+                ),
+                (102, storage_buffer_read_only_sized(false, None)),
+                (103, uniform_buffer::<BoidsColorUniform>(false)),
+            ),
+        );
+        let bind_group_layout =
+            render_device.create_bind_group_layout("boids_render_bind_group_layout", &entries);
+
+        BoidsRenderPipeline {
+            shader: world.resource::<AssetServer>().load(SHADER_PATH),
+            mesh_pipeline: world.resource::<MeshPipeline>().clone(),
+            bind_group_layout,
+        }
+    }
+}
+
+impl SpecializedMeshPipeline for BoidsRenderPipeline {
+    type Key = MeshPipelineKey;
+
+    fn specialize(
+        &self,
+        key: Self::Key,
+        layout: &MeshVertexBufferLayoutRef,
+    ) -> Result<RenderPipelineDescriptor, SpecializedMeshPipelineError> {
+        let mut descriptor = self.mesh_pipeline.specialize(key, layout)?;
+
+        descriptor.label = Some("boids_render_pipeline".into());
+        descriptor.layout.push(self.bind_group_layout.clone());
+        descriptor.vertex.shader = self.shader.clone();
+        descriptor.fragment.as_mut().unwrap().shader = self.shader.clone();
+        Ok(descriptor)
+    }
+}
+
+/// One bind group per LOD, each exposing only that LOD's bucket of visible instances
+#[derive(Resource)]
+struct BoidsDrawBindGroups([BindGroup; LOD_COUNT]);
+
+#[allow(clippy::too_many_arguments)]
+fn prepare_draw_bind_groups(
+    mut commands: Commands,
+    pipeline: Res<BoidsRenderPipeline>,
+    gpu_images: Res<RenderAssets<GpuImage>>,
+    gpu_buffers: Res<RenderAssets<GpuShaderStorageBuffer>>,
+    boids_image: Res<BoidsImage>,
+    buffers: Res<BoidsInstanceBuffers>,
+    mut color_uniform_buffer: ResMut<BoidsColorUniformBuffer>,
+    boids_config: Res<BoidsConfig>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+) {
+    let (Some(position_map), Some(velocity_map), Some(visible_instances)) = (
+        gpu_images.get(&boids_image.position_map),
+        gpu_images.get(&boids_image.velocity_map),
+        gpu_buffers.get(&buffers.visible_instances),
+    ) else {
+        return;
+    };
+
+    let uniform = color_uniform_buffer.buffer.get_mut();
+    uniform.solid_color = boids_config.solid_color.to_vec4();
+    uniform.ramp = boids_config.color_ramp.map(LinearRgba::to_vec4);
+    uniform.mode = boids_config.color_mode as u32;
+    uniform.max_speed = boids_config.max_speed;
+    uniform.density_max_neighbors = boids_config.density_max_neighbors as f32;
+    uniform.species_count = boids_config.species_count;
+
+    color_uniform_buffer
+        .buffer
+        .write_buffer(&render_device, &render_queue);
+    let color_uniform = color_uniform_buffer.buffer.binding().unwrap();
+
+    let bind_groups = std::array::from_fn(|lod| {
+        render_device.create_bind_group(
+            "boids_render_bind_group",
+            &pipeline.bind_group_layout,
+            &BindGroupEntries::with_indices((
+                (100, &position_map.texture_view),
+                (101, &velocity_map.texture_view),
+                (
+                    102,
+                    BufferBinding {
+                        buffer: &visible_instances.buffer,
+                        offset: lod as u64 * VISIBLE_INSTANCES_BUCKET_SIZE,
+                        size: NonZeroU64::new(VISIBLE_INSTANCES_BUCKET_SIZE),
+                    },
+                ),
+                (103, color_uniform.clone()),
+            )),
+        )
+    });
+    commands.insert_resource(BoidsDrawBindGroups(bind_groups));
+}
+
+/// Write the mesh ranges of every LOD into the indirect arguments and reset the instance counts,
+/// which are filled in again by the culling compute pass.
+fn prepare_indirect_args(
+    lods: Query<(&BoidsLod, &MainEntity)>,
+    render_mesh_instances: Res<RenderMeshInstances>,
+    meshes: Res<RenderAssets<RenderMesh>>,
+    mesh_allocator: Res<MeshAllocator>,
+    buffers: Res<BoidsInstanceBuffers>,
+    gpu_buffers: Res<RenderAssets<GpuShaderStorageBuffer>>,
+    render_queue: Res<RenderQueue>,
+) {
+    let Some(indirect_args) = gpu_buffers.get(&buffers.indirect_args) else {
+        return;
+    };
+
+    let mut args = BoidsIndirectArgs::default();
+    for (lod, main_entity) in &lods {
+        let Some(mesh_id) = render_mesh_instances.mesh_asset_id(*main_entity) else {
+            continue;
+        };
+        let (Some(mesh), Some(vertex_slice), Some(index_slice)) = (
+            meshes.get(mesh_id),
+            mesh_allocator.mesh_vertex_slice(&mesh_id),
+            mesh_allocator.mesh_index_slice(&mesh_id),
+        ) else {
+            continue;
+        };
+        let RenderMeshBufferInfo::Indexed { count, .. } = mesh.buffer_info else {
+            continue;
+        };
+
+        args.lods[lod.0 as usize] = DrawIndexedIndirectArgs {
+            index_count: count,
+            instance_count: 0,
+            first_index: index_slice.range.start,
+            base_vertex: vertex_slice.range.start as i32,
+            first_instance: 0,
+        };
+    }
+
+    let mut bytes = encase::StorageBuffer::new(Vec::new());
+    bytes.write(&args).unwrap();
+    render_queue.write_buffer(&indirect_args.buffer, 0, bytes.as_ref());
+}
+
+/// The boid draws of a view, one per LOD, rendered by [`BoidsDrawNode`]
+#[derive(Component, Default)]
+struct BoidsViewDraws(Vec<Opaque3d>);
+
+/// The LOD meshes are drawn as opaque items, but outside of the opaque phase, so they don't get
+/// batched with the other meshes and [`BoidsDrawNode`] can time them.
+#[allow(clippy::too_many_arguments)]
+fn queue_boids(
+    mut commands: Commands,
+    opaque_3d_draw_functions: Res<DrawFunctions<Opaque3d>>,
+    boids_pipeline: Res<BoidsRenderPipeline>,
+    mut pipelines: ResMut<SpecializedMeshPipelines<BoidsRenderPipeline>>,
+    pipeline_cache: Res<PipelineCache>,
+    meshes: Res<RenderAssets<RenderMesh>>,
+    render_mesh_instances: Res<RenderMeshInstances>,
+    lods: Query<(Entity, &MainEntity), With<BoidsLod>>,
+    opaque_render_phases: Res<ViewBinnedRenderPhases<Opaque3d>>,
+    views: Query<(Entity, &ExtractedView, &Msaa)>,
+) {
+    let draw_boids = opaque_3d_draw_functions.read().id::<DrawBoids>();
+
+    for (view_entity, view, msaa) in &views {
+        // only the 3D cameras, not the shadow views
+        if !opaque_render_phases.contains_key(&view.retained_view_entity) {
+            continue;
+        }
+
+        let view_key = MeshPipelineKey::from_msaa_samples(msaa.samples())
+            | MeshPipelineKey::from_hdr(view.hdr);
+        let mut draws = Vec::new();
+        for (entity, main_entity) in &lods {
+            let Some(mesh_instance) = render_mesh_instances.render_mesh_queue_data(*main_entity)
+            else {
+                continue;
+            };
+            let Some(mesh) = meshes.get(mesh_instance.mesh_asset_id) else {
+                continue;
+            };
+            let key =
+                view_key | MeshPipelineKey::from_primitive_topology(mesh.primitive_topology());
+            let Ok(pipeline) =
+                pipelines.specialize(&pipeline_cache, &boids_pipeline, key, &mesh.layout)
+            else {
+                continue;
+            };
+
+            draws.push(Opaque3d {
+                batch_set_key: Opaque3dBatchSetKey {
+                    pipeline,
+                    draw_function: draw_boids,
+                    material_bind_group_index: None,
+                    vertex_slab: default(),
+                    index_slab: None,
+                    lightmap_slab: None,
+                },
+                bin_key: Opaque3dBinKey {
+                    asset_id: mesh_instance.mesh_asset_id.untyped(),
+                },
+                representative_entity: (entity, *main_entity),
+                batch_range: 0..1,
+                extra_index: PhaseItemExtraIndex::None,
+            });
+        }
+        commands.entity(view_entity).insert(BoidsViewDraws(draws));
+    }
+}
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, RenderLabel)]
+struct BoidsDrawLabel;
+
+/// Renders the [`BoidsViewDraws`] on top of the main opaque pass, inside a pass span named
+/// [`BOIDS_DRAW_SPAN`]
+#[derive(Default)]
+struct BoidsDrawNode;
+
+impl ViewNode for BoidsDrawNode {
+    type ViewQuery = (
+        &'static ExtractedCamera,
+        &'static ViewTarget,
+        &'static ViewDepthTexture,
+        &'static BoidsViewDraws,
+    );
+
+    fn run<'w>(
+        &self,
+        graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext<'w>,
+        (camera, target, depth, draws): QueryItem<'w, Self::ViewQuery>,
+        world: &'w World,
+    ) -> Result<(), NodeRunError> {
+        if draws.0.is_empty() {
+            return Ok(());
+        }
+
+        let diagnostics = render_context.diagnostic_recorder();
+        let mut pass = render_context.begin_tracked_render_pass(RenderPassDescriptor {
+            label: Some("boids_draw"),
+            color_attachments: &[Some(target.get_color_attachment())],
+            depth_stencil_attachment: Some(depth.get_attachment(StoreOp::Store)),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        let pass_span = diagnostics.pass_span(&mut pass, BOIDS_DRAW_SPAN);
+        if let Some(viewport) = camera.viewport.as_ref() {
+            pass.set_camera_viewport(viewport);
+        }
+
+        let draw_functions = world.resource::<DrawFunctions<Opaque3d>>();
+        let mut draw_functions = draw_functions.write();
+        draw_functions.prepare(world);
+        for item in &draws.0 {
+            let Some(draw_function) = draw_functions.get_mut(item.draw_function()) else {
+                continue;
+            };
+            if let Err(error) = draw_function.draw(world, &mut pass, graph.view_entity(), item) {
+                error!("Error encountered while drawing the boids {error:?}");
+            }
+        }
+
+        pass_span.end(&mut pass);
+        Ok(())
+    }
+}
+
+type DrawBoids = (
+    SetItemPipeline,
+    SetMeshViewBindGroup<0>,
+    SetMeshBindGroup<1>,
+    SetBoidsBindGroup<2>,
+    DrawBoidsIndirect,
+);
+
+struct SetBoidsBindGroup<const I: usize>;
+
+impl<P: PhaseItem, const I: usize> RenderCommand<P> for SetBoidsBindGroup<I> {
+    type Param = Option<SRes<BoidsDrawBindGroups>>;
+    type ViewQuery = ();
+    type ItemQuery = Read<BoidsLod>;
+
+    fn render<'w>(
+        _item: &P,
+        _view: (),
+        lod: Option<&'w BoidsLod>,
+        bind_groups: SystemParamItem<'w, '_, Self::Param>,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        let (Some(lod), Some(bind_groups)) = (lod, bind_groups) else {
+            return RenderCommandResult::Skip;
+        };
+
+        pass.set_bind_group(I, &bind_groups.into_inner().0[lod.0 as usize], &[]);
+        RenderCommandResult::Success
+    }
+}
+
+struct DrawBoidsIndirect;
+
+impl<P: PhaseItem> RenderCommand<P> for DrawBoidsIndirect {
+    type Param = (
+        SRes<RenderAssets<RenderMesh>>,
+        SRes<RenderMeshInstances>,
+        SRes<MeshAllocator>,
+        Option<SRes<BoidsInstanceBuffers>>,
+        SRes<RenderAssets<GpuShaderStorageBuffer>>,
+    );
+    type ViewQuery = ();
+    type ItemQuery = Read<BoidsLod>;
+
+    fn render<'w>(
+        item: &P,
+        _view: (),
+        lod: Option<&'w BoidsLod>,
+        (meshes, render_mesh_instances, mesh_allocator, buffers, gpu_buffers): SystemParamItem<
+            'w,
+            '_,
+            Self::Param,
+        >,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        let (Some(lod), Some(buffers)) = (lod, buffers) else {
+            return RenderCommandResult::Skip;
+        };
+        let mesh_allocator = mesh_allocator.into_inner();
+
+        let Some(mesh_id) = render_mesh_instances.mesh_asset_id(item.main_entity()) else {
+            return RenderCommandResult::Skip;
+        };
+        let Some(gpu_mesh) = meshes.into_inner().get(mesh_id) else {
+            return RenderCommandResult::Skip;
+        };
+        let Some(indirect_args) = gpu_buffers.into_inner().get(&buffers.indirect_args) else {
+            return RenderCommandResult::Skip;
+        };
+        let (Some(vertex_slice), Some(index_slice)) = (
+            mesh_allocator.mesh_vertex_slice(&mesh_id),
+            mesh_allocator.mesh_index_slice(&mesh_id),
+        ) else {
+            return RenderCommandResult::Skip;
+        };
+        let RenderMeshBufferInfo::Indexed { index_format, .. } = gpu_mesh.buffer_info else {
+            return RenderCommandResult::Skip;
+        };
+
+        pass.set_vertex_buffer(0, vertex_slice.buffer.slice(..));
+        pass.set_index_buffer(index_slice.buffer.slice(..), 0, index_format);
+        pass.draw_indexed_indirect(
+            &indirect_args.buffer,
+            u64::from(lod.0) * DrawIndexedIndirectArgs::min_size().get(),
+        );
+        RenderCommandResult::Success
+    }
+}
@@ -0,0 +1,272 @@
+use bevy::{
+    color::palettes::css,
+    prelude::*,
+    render::{
+        extract_resource::{ExtractResource, ExtractResourcePlugin},
+        renderer::{RenderDevice, RenderQueue},
+        Render, RenderApp, RenderSet,
+    },
+};
+use bevy_egui::{
+    egui::{self, Pos2, Ui},
+    EguiContexts,
+};
+
+use super::{
+    boids_compute::prepare_uniforms_bind_group,
+    summary::FlockSummary,
+    uniforms::{RouteUniform, RouteUniformBuffer, MAX_WAYPOINTS},
+    BOX_SIZE,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Waypoint {
+    pub position: Vec3,
+    /// Distance of the flock centroid to the waypoint at which the waypoint counts as reached
+    pub arrival_radius: f32,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RouteMode {
+    /// Continue with the first waypoint after the last one
+    #[default]
+    Loop,
+    /// Travel the route back and forth
+    PingPong,
+}
+
+/// An ordered list of waypoints the flock migrates along
+#[derive(Resource, Clone, ExtractResource)]
+pub struct BoidsRoute {
+    pub enabled: bool,
+    pub waypoints: Vec<Waypoint>,
+    /// How strongly each boid steers towards the current waypoint
+    pub weight: f32,
+    pub mode: RouteMode,
+    /// Index of the waypoint the flock is heading to
+    pub current: usize,
+    /// Whether a [`RouteMode::PingPong`] route is travelled backwards
+    pub reversed: bool,
+}
+
+impl Default for BoidsRoute {
+    fn default() -> Self {
+        let corner = BOX_SIZE * 0.3;
+        Self {
+            enabled: false,
+            waypoints: [
+                Vec3::new(-corner, 0.0, -corner),
+                Vec3::new(corner, corner * 0.5, -corner),
+                Vec3::new(corner, 0.0, corner),
+                Vec3::new(-corner, corner * 0.5, corner),
+            ]
+            .map(|position| Waypoint {
+                position,
+                arrival_radius: 150.0,
+            })
+            .to_vec(),
+            weight: 2.0,
+            mode: RouteMode::Loop,
+            current: 0,
+            reversed: false,
+        }
+    }
+}
+
+impl BoidsRoute {
+    pub fn is_active(&self) -> bool {
+        self.enabled && !self.waypoints.is_empty()
+    }
+
+    pub fn current_waypoint(&self) -> Option<&Waypoint> {
+        self.waypoints.get(self.current)
+    }
+
+    /// Move on to the next waypoint according to the [`RouteMode`]
+    pub fn advance(&mut self) {
+        let count = self.waypoints.len();
+        if count < 2 {
+            return;
+        }
+
+        self.current = match self.mode {
+            RouteMode::Loop => (self.current + 1) % count,
+            RouteMode::PingPong => {
+                if (self.reversed && self.current == 0)
+                    || (!self.reversed && self.current == count - 1)
+                {
+                    self.reversed = !self.reversed;
+                }
+                if self.reversed {
+                    self.current - 1
+                } else {
+                    self.current + 1
+                }
+            }
+        };
+    }
+}
+
+pub struct BoidsRoutePlugin;
+
+impl Plugin for BoidsRoutePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(ExtractResourcePlugin::<BoidsRoute>::default())
+            .init_resource::<BoidsRoute>()
+            .add_systems(Update, (advance_route, draw_route, route_ui_system).chain());
+
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app.add_systems(
+            Render,
+            prepare_route_uniform
+                .in_set(RenderSet::PrepareResources)
+                .before(prepare_uniforms_bind_group),
+        );
+    }
+
+    fn finish(&self, app: &mut App) {
+        app.sub_app_mut(RenderApp)
+            .init_resource::<RouteUniformBuffer>();
+    }
+}
+
+impl From<&BoidsRoute> for RouteUniform {
+    fn from(route: &BoidsRoute) -> Self {
+        let mut uniform = RouteUniform::default();
+        let count = route.waypoints.len().min(MAX_WAYPOINTS);
+        for (slot, waypoint) in uniform.waypoints.iter_mut().zip(&route.waypoints) {
+            *slot = waypoint.position.extend(waypoint.arrival_radius);
+        }
+        uniform.count = count as u32;
+        uniform.current = route.current.min(count.saturating_sub(1)) as u32;
+        uniform.weight = if route.is_active() { route.weight } else { 0.0 };
+        uniform
+    }
+}
+
+fn prepare_route_uniform(
+    route: Res<BoidsRoute>,
+    mut route_uniform_buffer: ResMut<RouteUniformBuffer>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+) {
+    route_uniform_buffer
+        .buffer
+        .set(RouteUniform::from(route.as_ref()));
+    route_uniform_buffer
+        .buffer
+        .write_buffer(&render_device, &render_queue);
+}
+
+/// Advance the route once the centroid of the flock has reached the current waypoint
+fn advance_route(mut route: ResMut<BoidsRoute>, summary: Res<FlockSummary>) {
+    if !route.is_active() || !summary.is_changed() || summary.boids_count == 0 {
+        return;
+    }
+    let Some(waypoint) = route.current_waypoint() else {
+        return;
+    };
+
+    if summary.centroid.distance(waypoint.position) < waypoint.arrival_radius {
+        route.advance();
+    }
+}
+
+fn draw_route(route: Res<BoidsRoute>, mut gizmos: Gizmos) {
+    if !route.enabled || route.waypoints.is_empty() {
+        return;
+    }
+
+    let positions = route.waypoints.iter().map(|waypoint| waypoint.position);
+    match route.mode {
+        RouteMode::Loop => {
+            gizmos.linestrip(positions.clone().chain(positions.take(1)), css::GOLD);
+        }
+        RouteMode::PingPong => gizmos.linestrip(positions, css::GOLD),
+    }
+
+    for (index, waypoint) in route.waypoints.iter().enumerate() {
+        let color = if index == route.current {
+            css::ORANGE_RED
+        } else {
+            css::GOLD
+        };
+        gizmos.sphere(waypoint.position, waypoint.arrival_radius, color);
+    }
+}
+
+fn drag_vec3(ui: &mut Ui, value: &mut Vec3) {
+    let half_box_size = BOX_SIZE * 0.5;
+    ui.horizontal(|ui| {
+        for (axis, label) in [(0, "x "), (1, "y "), (2, "z ")] {
+            ui.add(
+                egui::DragValue::new(&mut value[axis])
+                    .range(-half_box_size..=half_box_size)
+                    .prefix(label),
+            );
+        }
+    });
+}
+
+pub fn route_ui(route: &mut BoidsRoute, ui: &mut Ui) {
+    ui.checkbox(&mut route.enabled, "Follow route");
+    ui.end_row();
+    ui.add(egui::Slider::new(&mut route.weight, 0.0..=10.0).text("Waypoint weight"));
+    ui.end_row();
+    ui.horizontal(|ui| {
+        ui.radio_value(&mut route.mode, RouteMode::Loop, "Loop");
+        ui.radio_value(&mut route.mode, RouteMode::PingPong, "Ping-pong");
+    });
+    ui.end_row();
+
+    let mut removed = None;
+    for (index, waypoint) in route.waypoints.iter_mut().enumerate() {
+        ui.label(format!("Waypoint {}", index + 1));
+        drag_vec3(ui, &mut waypoint.position);
+        ui.add(
+            egui::DragValue::new(&mut waypoint.arrival_radius)
+                .range(1.0..=BOX_SIZE)
+                .prefix("radius "),
+        );
+        if ui.button("Remove").clicked() {
+            removed = Some(index);
+        }
+        ui.end_row();
+    }
+    if let Some(index) = removed {
+        route.waypoints.remove(index);
+        route.current = route.current.min(route.waypoints.len().saturating_sub(1));
+    }
+
+    ui.add_enabled_ui(route.waypoints.len() < MAX_WAYPOINTS, |ui| {
+        if ui.button("Add waypoint").clicked() {
+            let last = route.waypoints.last().copied().unwrap_or(Waypoint {
+                position: Vec3::ZERO,
+                arrival_radius: 150.0,
+            });
+            route.waypoints.push(last);
+        }
+    });
+    if ui.button("Restart").clicked() {
+        route.current = 0;
+        route.reversed = false;
+    }
+    ui.end_row();
+}
+
+fn route_ui_system(mut route: ResMut<BoidsRoute>, mut contexts: EguiContexts) {
+    let Ok(ctx) = contexts.ctx_mut() else {
+        return;
+    };
+    egui::Window::new("Route")
+        .default_pos(Pos2 { x: 420., y: 320. })
+        .show(ctx, |ui| {
+            egui::Grid::new("route_grid")
+                .num_columns(4)
+                .spacing([20.0, 4.0])
+                .striped(true)
+                .show(ui, |ui| {
+                    route_ui(route.as_mut(), ui);
+                });
+        });
+}
@@ -0,0 +1,517 @@
+use bevy::{
+    prelude::*,
+    render::{
+        extract_resource::ExtractResourcePlugin,
+        gpu_readback::{Readback, ReadbackComplete},
+        render_asset::RenderAssetUsages,
+        render_resource::{BufferUsages, ShaderType},
+        storage::ShaderStorageBuffer,
+        Render, RenderApp, RenderSet,
+    },
+};
+use bevy_egui::egui::Ui;
+
+use super::{
+    boids_compute::{prepare_reduction_bind_group, WORKGROUP_SIZE},
+    images::IMAGE_SIZE,
+    uniforms::{FlockReduction, FlockReductionBuffers},
+};
+
+/// Aggregate state of the flock, computed on the GPU and read back every frame
+#[derive(Resource, Default, Clone, Copy, Debug, PartialEq)]
+pub struct FlockSummary {
+    pub boids_count: u32,
+    pub centroid: Vec3,
+    pub aabb_min: Vec3,
+    pub aabb_max: Vec3,
+    pub mean_velocity: Vec3,
+    pub speed_min: f32,
+    pub speed_max: f32,
+}
+
+impl FlockSummary {
+    /// Compute the summary on the CPU, the reference the GPU reduction is checked against
+    pub fn from_boids(positions: &[Vec3], velocities: &[Vec3]) -> Self {
+        let boids_count = positions.len().min(velocities.len());
+        if boids_count == 0 {
+            return Self::default();
+        }
+        let (positions, velocities) = (&positions[..boids_count], &velocities[..boids_count]);
+
+        let speeds = velocities.iter().map(|velocity| velocity.length());
+        Self {
+            boids_count: boids_count as u32,
+            centroid: positions.iter().sum::<Vec3>() / boids_count as f32,
+            aabb_min: positions.iter().copied().fold(Vec3::MAX, Vec3::min),
+            aabb_max: positions.iter().copied().fold(Vec3::MIN, Vec3::max),
+            mean_velocity: velocities.iter().sum::<Vec3>() / boids_count as f32,
+            speed_min: speeds.clone().fold(f32::MAX, f32::min),
+            speed_max: speeds.fold(0.0, f32::max),
+        }
+    }
+
+    pub fn size(&self) -> Vec3 {
+        (self.aabb_max - self.aabb_min).max(Vec3::ZERO)
+    }
+}
+
+impl From<FlockReduction> for FlockSummary {
+    fn from(reduction: FlockReduction) -> Self {
+        Self {
+            boids_count: reduction.count,
+            centroid: reduction.position,
+            aabb_min: reduction.aabb_min,
+            aabb_max: reduction.aabb_max,
+            mean_velocity: reduction.velocity,
+            speed_min: reduction.speed_min,
+            speed_max: reduction.speed_max,
+        }
+    }
+}
+
+pub struct FlockSummaryPlugin;
+
+impl Plugin for FlockSummaryPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(ExtractResourcePlugin::<FlockReductionBuffers>::default())
+            .init_resource::<FlockSummary>()
+            .add_systems(Startup, spawn_reduction_buffers);
+
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app.add_systems(
+            Render,
+            prepare_reduction_bind_group.in_set(RenderSet::PrepareResources),
+        );
+    }
+}
+
+fn update_flock_summary(trigger: Trigger<ReadbackComplete>, mut summary: ResMut<FlockSummary>) {
+    let reduction: FlockReduction = trigger.event().to_shader_type();
+    summary.set_if_neq(reduction.into());
+}
+
+fn spawn_reduction_buffers(
+    mut commands: Commands,
+    mut buffers: ResMut<Assets<ShaderStorageBuffer>>,
+) {
+    // the `reduce` pass writes one partial result per workgroup
+    let max_workgroups = (IMAGE_SIZE * IMAGE_SIZE).div_ceil(WORKGROUP_SIZE);
+    let partials = ShaderStorageBuffer::with_size(
+        max_workgroups as usize * FlockReduction::min_size().get() as usize,
+        RenderAssetUsages::RENDER_WORLD,
+    );
+
+    let mut summary = ShaderStorageBuffer::from(FlockReduction::default());
+    summary.buffer_description.usage |= BufferUsages::COPY_SRC;
+    let summary = buffers.add(summary);
+
+    commands
+        .spawn(Readback::buffer(summary.clone()))
+        .observe(update_flock_summary);
+
+    commands.insert_resource(FlockReductionBuffers {
+        partials: buffers.add(partials),
+        summary,
+    });
+}
+
+pub fn flock_summary_ui(summary: &FlockSummary, ui: &mut Ui) {
+    let format_vec3 = |v: Vec3| format!("{:.0} / {:.0} / {:.0}", v.x, v.y, v.z);
+    ui.label("Flock centroid");
+    ui.label(format_vec3(summary.centroid));
+    ui.end_row();
+    ui.label("Flock size");
+    ui.label(format_vec3(summary.size()));
+    ui.end_row();
+    ui.label("Mean velocity");
+    ui.label(format_vec3(summary.mean_velocity));
+    ui.end_row();
+    ui.label("Speed min / max");
+    ui.label(format!(
+        "{:.2} / {:.2}",
+        summary.speed_min, summary.speed_max
+    ));
+    ui.end_row();
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{
+        render::render_resource::encase::{StorageBuffer, UniformBuffer},
+        tasks::block_on,
+    };
+    use wgpu::util::{BufferInitDescriptor, DeviceExt, TextureDataOrder};
+
+    use super::*;
+    use crate::boids::uniforms::{BoidsUniform, LOD_COUNT, MAX_WAYPOINTS};
+
+    /// `empty_reduction` in `boids_compute.wgsl`
+    fn empty_reduction() -> FlockReduction {
+        FlockReduction {
+            position: Vec3::ZERO,
+            count: 0,
+            velocity: Vec3::ZERO,
+            speed_min: f32::MAX,
+            aabb_min: Vec3::MAX,
+            speed_max: 0.0,
+            aabb_max: Vec3::MIN,
+        }
+    }
+
+    /// `combine` in `boids_compute.wgsl`
+    fn combine(a: FlockReduction, b: FlockReduction) -> FlockReduction {
+        FlockReduction {
+            position: a.position + b.position,
+            count: a.count + b.count,
+            velocity: a.velocity + b.velocity,
+            speed_min: a.speed_min.min(b.speed_min),
+            aabb_min: a.aabb_min.min(b.aabb_min),
+            speed_max: a.speed_max.max(b.speed_max),
+            aabb_max: a.aabb_max.max(b.aabb_max),
+        }
+    }
+
+    /// Folds `values` pairwise with halving strides, like `reduce_scratch`
+    fn reduce_scratch(mut values: Vec<FlockReduction>) -> FlockReduction {
+        let mut stride = WORKGROUP_SIZE as usize / 2;
+        while stride > 0 {
+            for i in 0..stride {
+                values[i] = combine(values[i].clone(), values[i + stride].clone());
+            }
+            stride /= 2;
+        }
+        values.swap_remove(0)
+    }
+
+    /// The `reduce` and `reduce_final` passes, mirrored on the CPU
+    fn reduce_on_cpu(positions: &[Vec3], velocities: &[Vec3]) -> FlockSummary {
+        let workgroup_size = WORKGROUP_SIZE as usize;
+        let partials: Vec<_> = (0..positions.len().div_ceil(workgroup_size))
+            .map(|workgroup| {
+                let scratch = (0..workgroup_size)
+                    .map(|local_index| {
+                        let index = workgroup * workgroup_size + local_index;
+                        let Some((&position, &velocity)) =
+                            positions.get(index).zip(velocities.get(index))
+                        else {
+                            return empty_reduction();
+                        };
+                        let speed = velocity.length();
+                        FlockReduction {
+                            position,
+                            count: 1,
+                            velocity,
+                            speed_min: speed,
+                            aabb_min: position,
+                            speed_max: speed,
+                            aabb_max: position,
+                        }
+                    })
+                    .collect();
+                reduce_scratch(scratch)
+            })
+            .collect();
+
+        let scratch = (0..workgroup_size)
+            .map(|local_index| {
+                partials
+                    .iter()
+                    .skip(local_index)
+                    .step_by(workgroup_size)
+                    .fold(empty_reduction(), |value, partial| {
+                        combine(value, partial.clone())
+                    })
+            })
+            .collect();
+        let mut summary = reduce_scratch(scratch);
+        if summary.count > 0 {
+            summary.position /= summary.count as f32;
+            summary.velocity /= summary.count as f32;
+        } else {
+            summary = FlockReduction::default();
+        }
+        summary.into()
+    }
+
+    /// A deterministic flock that doesn't fill the last workgroup
+    fn flock(count: usize) -> (Vec<Vec3>, Vec<Vec3>) {
+        (0..count)
+            .map(|i| {
+                let t = i as f32;
+                (
+                    Vec3::new((t * 0.37).sin(), (t * 0.11).cos(), (t * 0.23).sin()) * 400.0,
+                    Vec3::new((t * 0.53).cos(), (t * 0.29).sin(), 1.0 + (t * 0.07).cos()) * 5.0,
+                )
+            })
+            .unzip()
+    }
+
+    fn assert_close(reduced: FlockSummary, expected: FlockSummary) {
+        assert_eq!(reduced.boids_count, expected.boids_count);
+        assert!(reduced.centroid.abs_diff_eq(expected.centroid, 1e-2));
+        assert_eq!(reduced.aabb_min, expected.aabb_min);
+        assert_eq!(reduced.aabb_max, expected.aabb_max);
+        assert!(reduced
+            .mean_velocity
+            .abs_diff_eq(expected.mean_velocity, 1e-4));
+        assert_eq!(reduced.speed_min, expected.speed_min);
+        assert_eq!(reduced.speed_max, expected.speed_max);
+    }
+
+    /// Runs the `reduce` and `reduce_final` passes of `boids_compute.wgsl`, `None` without an
+    /// adapter that can read and write `Rgba32Float` storage textures
+    fn reduce_on_gpu(positions: &[Vec3], velocities: &[Vec3]) -> Option<FlockSummary> {
+        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::default());
+        let adapter = block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default()))?;
+        let storage_features = adapter
+            .get_texture_format_features(wgpu::TextureFormat::Rgba32Float)
+            .flags;
+        if !storage_features.contains(wgpu::TextureFormatFeatureFlags::STORAGE_READ_WRITE) {
+            return None;
+        }
+        let (device, queue) = block_on(adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                required_features: wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES,
+                required_limits: adapter.limits(),
+                ..default()
+            },
+            None,
+        ))
+        .ok()?;
+
+        // the shader defs `BoidsPipeline` passes to the shader
+        let source = include_str!("../../assets/shaders/boids_compute.wgsl")
+            .replace("#{WORKGROUP_SIZE}", &WORKGROUP_SIZE.to_string())
+            .replace("#{LOD_COUNT}", &LOD_COUNT.to_string())
+            .replace("#{MAX_WAYPOINTS}", &MAX_WAYPOINTS.to_string());
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("boids_compute"),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        });
+        let pipeline = |entry_point| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(entry_point),
+                layout: None,
+                module: &module,
+                entry_point: Some(entry_point),
+                compilation_options: default(),
+                cache: None,
+            })
+        };
+        let (reduce, reduce_final) = (pipeline("reduce"), pipeline("reduce_final"));
+
+        let boids_count = positions.len() as u32;
+        let mut config = UniformBuffer::new(Vec::new());
+        config
+            .write(&BoidsUniform {
+                boids_count,
+                ..default()
+            })
+            .unwrap();
+        let config = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("config"),
+            contents: &config.into_inner(),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        // laid out like `texels_by_boid` in the inspector expects
+        let size = IMAGE_SIZE as usize;
+        let boids_texture = |values: &[Vec3]| {
+            let mut texels = vec![Vec4::ZERO; size * size];
+            for (index, value) in values.iter().enumerate() {
+                texels[(index % size) * size + index / size] = value.extend(0.0);
+            }
+            let data: Vec<u8> = texels
+                .iter()
+                .flat_map(|texel| texel.to_array())
+                .flat_map(f32::to_le_bytes)
+                .collect();
+            device
+                .create_texture_with_data(
+                    &queue,
+                    &wgpu::TextureDescriptor {
+                        label: None,
+                        size: wgpu::Extent3d {
+                            width: IMAGE_SIZE,
+                            height: IMAGE_SIZE,
+                            depth_or_array_layers: 1,
+                        },
+                        mip_level_count: 1,
+                        sample_count: 1,
+                        dimension: wgpu::TextureDimension::D2,
+                        format: wgpu::TextureFormat::Rgba32Float,
+                        usage: wgpu::TextureUsages::STORAGE_BINDING,
+                        view_formats: &[],
+                    },
+                    TextureDataOrder::LayerMajor,
+                    &data,
+                )
+                .create_view(&default())
+        };
+        let position_map = boids_texture(positions);
+        let velocity_map = boids_texture(velocities);
+
+        let workgroups = boids_count.div_ceil(WORKGROUP_SIZE);
+        let reduction_size = FlockReduction::min_size().get();
+        let partials = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("partials"),
+            size: workgroups.max(1) as u64 * reduction_size,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        let summary = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("summary"),
+            size: reduction_size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("readback"),
+            size: reduction_size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        // the layouts are derived from the shader, so they only contain the bindings each entry
+        // point uses
+        let bind_groups = |pipeline: &wgpu::ComputePipeline,
+                           groups: [&[wgpu::BindGroupEntry]; 3]| {
+            groups
+                .iter()
+                .enumerate()
+                .map(|(index, entries)| {
+                    device.create_bind_group(&wgpu::BindGroupDescriptor {
+                        label: None,
+                        layout: &pipeline.get_bind_group_layout(index as u32),
+                        entries,
+                    })
+                })
+                .collect::<Vec<_>>()
+        };
+        let entry = |binding, resource| wgpu::BindGroupEntry { binding, resource };
+        let reduce_bind_groups = bind_groups(
+            &reduce,
+            [
+                &[entry(0, config.as_entire_binding())],
+                &[
+                    entry(0, wgpu::BindingResource::TextureView(&position_map)),
+                    entry(1, wgpu::BindingResource::TextureView(&velocity_map)),
+                ],
+                &[entry(3, partials.as_entire_binding())],
+            ],
+        );
+        let reduce_final_bind_groups = bind_groups(
+            &reduce_final,
+            [
+                &[entry(0, config.as_entire_binding())],
+                &[],
+                &[
+                    entry(3, partials.as_entire_binding()),
+                    entry(4, summary.as_entire_binding()),
+                ],
+            ],
+        );
+
+        let mut encoder = device.create_command_encoder(&default());
+        {
+            let mut pass = encoder.begin_compute_pass(&default());
+            for (pipeline, bind_groups, workgroups) in [
+                (&reduce, &reduce_bind_groups, workgroups),
+                (&reduce_final, &reduce_final_bind_groups, 1),
+            ] {
+                pass.set_pipeline(pipeline);
+                for (index, bind_group) in bind_groups.iter().enumerate() {
+                    pass.set_bind_group(index as u32, bind_group, &[]);
+                }
+                pass.dispatch_workgroups(workgroups, 1, 1);
+            }
+        }
+        encoder.copy_buffer_to_buffer(&summary, 0, &readback, 0, reduction_size);
+        queue.submit([encoder.finish()]);
+
+        readback.slice(..).map_async(wgpu::MapMode::Read, |_| {});
+        device.poll(wgpu::Maintain::Wait);
+        let data = readback.slice(..).get_mapped_range().to_vec();
+        let reduction: FlockReduction = StorageBuffer::new(data).create().unwrap();
+        Some(reduction.into())
+    }
+
+    #[test]
+    fn reduction_matches_cpu_summary() {
+        let (positions, velocities) = flock(1000);
+        assert_close(
+            reduce_on_cpu(&positions, &velocities),
+            FlockSummary::from_boids(&positions, &velocities),
+        );
+    }
+
+    #[test]
+    fn reduction_of_a_single_boid() {
+        let (positions, velocities) = flock(1);
+        let summary = reduce_on_cpu(&positions, &velocities);
+        assert_close(summary, FlockSummary::from_boids(&positions, &velocities));
+        assert_eq!(summary.size(), Vec3::ZERO);
+    }
+
+    #[test]
+    fn reduction_layout_matches_shader() {
+        let reduction = FlockReduction {
+            position: Vec3::new(1.0, 2.0, 3.0),
+            count: 4,
+            velocity: Vec3::new(5.0, 6.0, 7.0),
+            speed_min: 8.0,
+            aabb_min: Vec3::new(9.0, 10.0, 11.0),
+            speed_max: 12.0,
+            aabb_max: Vec3::new(13.0, 14.0, 15.0),
+        };
+        let mut buffer = StorageBuffer::new(Vec::new());
+        buffer.write(&reduction).unwrap();
+        let data = buffer.into_inner();
+
+        // the offsets of the fields of `FlockReduction` in `boids_compute.wgsl`
+        assert_eq!(FlockReduction::min_size().get(), 64);
+        assert_eq!(data.len(), 64);
+        let word = |offset: usize| -> [u8; 4] { data[offset..offset + 4].try_into().unwrap() };
+        let vec3 = |offset: usize| {
+            Vec3::from_array(std::array::from_fn(|i| {
+                f32::from_le_bytes(word(offset + i * 4))
+            }))
+        };
+        assert_eq!(vec3(0), reduction.position);
+        assert_eq!(u32::from_le_bytes(word(12)), reduction.count);
+        assert_eq!(vec3(16), reduction.velocity);
+        assert_eq!(f32::from_le_bytes(word(28)), reduction.speed_min);
+        assert_eq!(vec3(32), reduction.aabb_min);
+        assert_eq!(f32::from_le_bytes(word(44)), reduction.speed_max);
+        assert_eq!(vec3(48), reduction.aabb_max);
+    }
+
+    #[test]
+    fn reduction_shader_matches_cpu_summary() {
+        for count in [1000, 1, 0] {
+            let (positions, velocities) = flock(count);
+            let Some(reduced) = reduce_on_gpu(&positions, &velocities) else {
+                eprintln!("no adapter with read-write Rgba32Float storage textures, skipping");
+                return;
+            };
+            let expected = FlockSummary::from_boids(&positions, &velocities);
+
+            // the GPU sums in a different order and may round `length` differently
+            assert_eq!(reduced.boids_count, expected.boids_count);
+            assert!(reduced.centroid.abs_diff_eq(expected.centroid, 1e-2));
+            assert_eq!(reduced.aabb_min, expected.aabb_min);
+            assert_eq!(reduced.aabb_max, expected.aabb_max);
+            assert!(reduced
+                .mean_velocity
+                .abs_diff_eq(expected.mean_velocity, 1e-4));
+            assert!((reduced.speed_min - expected.speed_min).abs() < 1e-4);
+            assert!((reduced.speed_max - expected.speed_max).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn empty_flock_is_default() {
+        assert_eq!(reduce_on_cpu(&[], &[]), FlockSummary::default());
+        assert_eq!(FlockSummary::from_boids(&[], &[]), FlockSummary::default());
+    }
+}
@@ -0,0 +1,278 @@
+use bevy::{
+    prelude::*,
+    render::{
+        extract_resource::{ExtractResource, ExtractResourcePlugin},
+        mesh::PrimitiveTopology,
+        render_asset::RenderAssetUsages,
+        render_resource::{Extent3d, TextureDimension, TextureFormat, TextureUsages},
+    },
+};
+use bevy_egui::egui::{self, Ui};
+
+use crate::math::noise::simplex;
+
+use super::{uniforms::HeightfieldUniform, BOX_SIZE};
+
+/// Number of grid cells along each side of the terrain
+pub const TERRAIN_RESOLUTION: u32 = 64;
+
+/// Number of noise periods of the first octave along each side of the terrain
+const BASE_FREQUENCY: f32 = 3.0;
+
+/// Colors of the terrain from the lowest to the highest triangles
+const GRASS_COLOR: LinearRgba = LinearRgba::rgb(0.05, 0.2, 0.03);
+const ROCK_COLOR: LinearRgba = LinearRgba::rgb(0.2, 0.12, 0.07);
+const SNOW_COLOR: LinearRgba = LinearRgba::rgb(0.9, 0.9, 0.95);
+
+#[derive(Resource, Clone, Copy, PartialEq, ExtractResource)]
+pub struct TerrainConfig {
+    pub seed: u32,
+    /// Length of the sides of the terrain, centered in the bounding box
+    pub size: f32,
+    pub octaves: u32,
+    pub height_scale: f32,
+    /// Height above the ground the boids are pushed back up from
+    pub min_altitude: f32,
+    pub avoid_factor: f32,
+}
+
+impl Default for TerrainConfig {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            size: BOX_SIZE,
+            octaves: 5,
+            height_scale: 300.0,
+            min_altitude: 50.0,
+            avoid_factor: 1.0,
+        }
+    }
+}
+
+impl TerrainConfig {
+    /// Height of the bottom of the terrain, the floor of the bounding box
+    pub const BASE_HEIGHT: f32 = -BOX_SIZE * 0.5;
+
+    /// World space xz coordinates of the corner of the terrain with the lowest coordinates
+    pub fn origin(&self) -> Vec2 {
+        Vec2::splat(-self.size * 0.5)
+    }
+}
+
+/// The heights of the terrain grid points relative to [`TerrainConfig::BASE_HEIGHT`],
+/// sampled by the boids compute shader
+#[derive(Resource, Clone, ExtractResource)]
+pub struct TerrainHeightmap(pub Handle<Image>);
+
+/// The heights of the [`TerrainHeightmap`], kept on the CPU for the boid inspector
+#[derive(Resource, Default)]
+pub struct TerrainHeights(pub Vec<f32>);
+
+impl From<&TerrainConfig> for HeightfieldUniform {
+    fn from(config: &TerrainConfig) -> Self {
+        HeightfieldUniform {
+            origin: config.origin(),
+            size: config.size,
+            base_height: TerrainConfig::BASE_HEIGHT,
+            resolution: TERRAIN_RESOLUTION,
+            min_altitude: config.min_altitude,
+            avoid_factor: config.avoid_factor,
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct Terrain;
+
+pub struct TerrainPlugin;
+
+impl Plugin for TerrainPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(ExtractResourcePlugin::<TerrainConfig>::default())
+            .add_plugins(ExtractResourcePlugin::<TerrainHeightmap>::default())
+            .init_resource::<TerrainConfig>()
+            .init_resource::<TerrainHeights>()
+            .add_systems(Startup, spawn_terrain)
+            .add_systems(Update, regenerate_terrain);
+    }
+}
+
+/// Fractal sum of `octaves` layers of simplex noise in the xz plane, each at double the
+/// frequency and half the amplitude of the previous one, normalized to roughly `[-1, 1]`
+pub fn fractal_noise(seed: u32, point: Vec2, octaves: u32) -> f32 {
+    let mut sum = 0.0;
+    let mut amplitude = 1.0;
+    let mut frequency = 1.0;
+    let mut total_amplitude = 0.0;
+
+    for octave in 0..octaves {
+        let position = Vec3::new(point.x, 0.0, point.y) * frequency;
+        sum += simplex(position, seed.wrapping_add(octave)) * amplitude;
+        total_amplitude += amplitude;
+        amplitude *= 0.5;
+        frequency *= 2.0;
+    }
+
+    if total_amplitude > 0.0 {
+        sum / total_amplitude
+    } else {
+        0.0
+    }
+}
+
+/// Heights of the `(TERRAIN_RESOLUTION + 1)²` grid points, row by row along z
+pub fn generate_heights(config: &TerrainConfig) -> Vec<f32> {
+    let points = TERRAIN_RESOLUTION + 1;
+    (0..points * points)
+        .map(|index| {
+            let grid = Vec2::new((index % points) as f32, (index / points) as f32);
+            let noise = fractal_noise(
+                config.seed,
+                grid / TERRAIN_RESOLUTION as f32 * BASE_FREQUENCY,
+                config.octaves,
+            );
+            (noise * 0.5 + 0.5).clamp(0.0, 1.0) * config.height_scale
+        })
+        .collect()
+}
+
+fn terrain_color(height: f32, height_scale: f32) -> LinearRgba {
+    let t = if height_scale > 0.0 {
+        height / height_scale
+    } else {
+        0.0
+    };
+    if t < 0.5 {
+        GRASS_COLOR.mix(&ROCK_COLOR, t * 2.0)
+    } else {
+        ROCK_COLOR.mix(&SNOW_COLOR, (t - 0.5) * 2.0)
+    }
+}
+
+/// Build a flat shaded low poly mesh from the grid heights, every triangle has its own vertices
+/// so it gets a single normal and color
+pub fn build_terrain_mesh(config: &TerrainConfig, heights: &[f32]) -> Mesh {
+    let points = TERRAIN_RESOLUTION + 1;
+    let cell_size = config.size / TERRAIN_RESOLUTION as f32;
+    let origin = config.origin();
+    let vertex = |x: u32, z: u32| {
+        Vec3::new(
+            origin.x + x as f32 * cell_size,
+            TerrainConfig::BASE_HEIGHT + heights[(z * points + x) as usize],
+            origin.y + z as f32 * cell_size,
+        )
+    };
+
+    let mut positions = Vec::new();
+    let mut colors = Vec::new();
+    for z in 0..TERRAIN_RESOLUTION {
+        for x in 0..TERRAIN_RESOLUTION {
+            let (a, b, c, d) = (
+                vertex(x, z),
+                vertex(x + 1, z),
+                vertex(x, z + 1),
+                vertex(x + 1, z + 1),
+            );
+            // counter clockwise seen from above
+            for triangle in [[a, c, b], [b, c, d]] {
+                let height = triangle.iter().map(|p| p.y).sum::<f32>() / 3.0;
+                let color = terrain_color(height - TerrainConfig::BASE_HEIGHT, config.height_scale);
+                positions.extend(triangle.map(|p| p.to_array()));
+                colors.extend([color.to_f32_array(); 3]);
+            }
+        }
+    }
+
+    Mesh::new(
+        PrimitiveTopology::TriangleList,
+        RenderAssetUsages::RENDER_WORLD,
+    )
+    .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+    .with_inserted_attribute(Mesh::ATTRIBUTE_COLOR, colors)
+    .with_computed_flat_normals()
+}
+
+pub fn build_heightmap(heights: &[f32]) -> Image {
+    let points = TERRAIN_RESOLUTION + 1;
+    let mut image = Image::new(
+        Extent3d {
+            width: points,
+            height: points,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        heights
+            .iter()
+            .flat_map(|height| height.to_le_bytes())
+            .collect(),
+        TextureFormat::R32Float,
+        RenderAssetUsages::RENDER_WORLD,
+    );
+    image.texture_descriptor.usage = TextureUsages::COPY_DST | TextureUsages::TEXTURE_BINDING;
+    image
+}
+
+fn spawn_terrain(
+    mut commands: Commands,
+    config: Res<TerrainConfig>,
+    mut terrain_heights: ResMut<TerrainHeights>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut images: ResMut<Assets<Image>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let heights = generate_heights(&config);
+
+    commands.spawn((
+        Terrain,
+        Mesh3d(meshes.add(build_terrain_mesh(&config, &heights))),
+        MeshMaterial3d(materials.add(StandardMaterial {
+            perceptual_roughness: 1.0,
+            ..Default::default()
+        })),
+        Transform::IDENTITY,
+    ));
+    commands.insert_resource(TerrainHeightmap(images.add(build_heightmap(&heights))));
+    terrain_heights.0 = heights;
+}
+
+fn regenerate_terrain(
+    config: Res<TerrainConfig>,
+    heightmap: Option<Res<TerrainHeightmap>>,
+    mut terrain_heights: ResMut<TerrainHeights>,
+    terrain: Query<&Mesh3d, With<Terrain>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    if !config.is_changed() || config.is_added() {
+        return;
+    }
+
+    let heights = generate_heights(&config);
+    for mesh in &terrain {
+        meshes.insert(&mesh.0, build_terrain_mesh(&config, &heights));
+    }
+    if let Some(heightmap) = heightmap {
+        images.insert(&heightmap.0, build_heightmap(&heights));
+    }
+    terrain_heights.0 = heights;
+}
+
+pub fn terrain_ui(config: &mut TerrainConfig, ui: &mut Ui) {
+    ui.add(egui::DragValue::new(&mut config.seed).prefix("Seed "));
+    ui.end_row();
+    ui.add(egui::Slider::new(&mut config.size, 100.0..=BOX_SIZE).text("Terrain size"));
+    ui.end_row();
+    ui.add(egui::Slider::new(&mut config.octaves, 1..=8).text("Octaves"));
+    ui.end_row();
+    ui.add(egui::Slider::new(&mut config.height_scale, 0.0..=BOX_SIZE * 0.8).text("Height scale"));
+    ui.end_row();
+    ui.add(egui::Slider::new(&mut config.min_altitude, 0.0..=200.0).text("Min altitude"));
+    ui.end_row();
+    ui.add(egui::Slider::new(&mut config.avoid_factor, 0.0..=5.0).text("Terrain avoid factor"));
+    ui.end_row();
+
+    if ui.button("Reset terrain").clicked() {
+        *config = TerrainConfig::default();
+    }
+    ui.end_row();
+}
@@ -0,0 +1,311 @@
+use bevy::{
+    core_pipeline::core_3d::{Transparent3d, CORE_3D_DEPTH_FORMAT},
+    ecs::system::{lifetimeless::SRes, SystemParamItem},
+    image::BevyDefault,
+    pbr::{MeshPipeline, MeshPipelineKey, SetMeshViewBindGroup},
+    prelude::*,
+    render::{
+        extract_component::{ExtractComponent, ExtractComponentPlugin},
+        render_phase::{
+            AddRenderCommand, DrawFunctions, PhaseItem, PhaseItemExtraIndex, RenderCommand,
+            RenderCommandResult, SetItemPipeline, TrackedRenderPass, ViewSortedRenderPhases,
+        },
+        render_resource::{
+            binding_types::{storage_buffer_read_only_sized, uniform_buffer},
+            BindGroup, BindGroupEntries, BindGroupLayout, BindGroupLayoutEntries, BlendState,
+            Buffer, BufferDescriptor, BufferUsages, ColorTargetState, ColorWrites, CompareFunction,
+            DepthBiasState, DepthStencilState, FragmentState, MultisampleState, PipelineCache,
+            PrimitiveState, RenderPipelineDescriptor, ShaderStages, SpecializedRenderPipeline,
+            SpecializedRenderPipelines, StencilState, TextureFormat, UniformBuffer, VertexState,
+        },
+        renderer::{RenderDevice, RenderQueue},
+        sync_world::{MainEntity, SyncToRenderWorld},
+        view::{ExtractedView, Msaa, ViewTarget},
+        Render, RenderApp, RenderSet,
+    },
+};
+
+use super::{
+    boids_compute::{BoidsConfig, BoidsPipeline, BoidsSimulation},
+    mesh::BOID_RADIUS,
+    uniforms::BoidsTrailUniform,
+};
+
+const SHADER_PATH: &str = "shaders/boids_trails.wgsl";
+
+pub const MAX_TRAIL_LENGTH: u32 = 64;
+
+/// Size in bytes of a single trail point, the position and the speed packed in a `vec4<f32>`
+const TRAIL_POINT_SIZE: u64 = 16;
+
+/// Marks the entity the trail ribbons of all boids are drawn for
+#[derive(Component, Clone, Copy, ExtractComponent)]
+#[require(SyncToRenderWorld)]
+pub struct BoidsTrails;
+
+/// Ring buffers with the last positions of every boid.
+///
+/// The buffer is sized for the current number of boids and reallocated whenever that number or
+/// the trail length changes, so disabled or short trails don't reserve memory for every boid.
+#[derive(Resource, Default)]
+pub struct BoidsTrailBuffer {
+    buffer: Option<Buffer>,
+    boids_count: u32,
+    uniform: UniformBuffer<BoidsTrailUniform>,
+}
+
+#[derive(Resource)]
+pub struct BoidsTrailComputeBindGroup(pub BindGroup);
+
+#[derive(Resource)]
+struct BoidsTrailRenderBindGroup(BindGroup);
+
+pub fn spawn_trails(mut commands: Commands) {
+    commands.spawn(BoidsTrails);
+}
+
+pub struct BoidsTrailsPlugin;
+
+impl Plugin for BoidsTrailsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(ExtractComponentPlugin::<BoidsTrails>::default())
+            .add_systems(Startup, spawn_trails);
+
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app
+            .add_render_command::<Transparent3d, DrawBoidTrails>()
+            .init_resource::<SpecializedRenderPipelines<BoidsTrailsPipeline>>()
+            .init_resource::<BoidsTrailBuffer>()
+            .add_systems(
+                Render,
+                (
+                    queue_trails.in_set(RenderSet::Queue),
+                    prepare_trail_buffer.in_set(RenderSet::PrepareResources),
+                ),
+            );
+    }
+
+    fn finish(&self, app: &mut App) {
+        app.sub_app_mut(RenderApp)
+            .init_resource::<BoidsTrailsPipeline>();
+    }
+}
+
+#[derive(Resource)]
+struct BoidsTrailsPipeline {
+    shader: Handle<Shader>,
+    mesh_pipeline: MeshPipeline,
+    bind_group_layout: BindGroupLayout,
+}
+
+impl FromWorld for BoidsTrailsPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+        let bind_group_layout = render_device.create_bind_group_layout(
+            "boids_trail_render_bind_group_layout",
+            &BindGroupLayoutEntries::sequential(
+                ShaderStages::VERTEX,
+                (
+                    uniform_buffer::<BoidsTrailUniform>(false),
+                    storage_buffer_read_only_sized(false, None),
+                ),
+            ),
+        );
+
+        BoidsTrailsPipeline {
+            shader: world.resource::<AssetServer>().load(SHADER_PATH),
+            mesh_pipeline: world.resource::<MeshPipeline>().clone(),
+            bind_group_layout,
+        }
+    }
+}
+
+impl SpecializedRenderPipeline for BoidsTrailsPipeline {
+    type Key = MeshPipelineKey;
+
+    fn specialize(&self, key: Self::Key) -> RenderPipelineDescriptor {
+        let format = if key.contains(MeshPipelineKey::HDR) {
+            ViewTarget::TEXTURE_FORMAT_HDR
+        } else {
+            TextureFormat::bevy_default()
+        };
+
+        RenderPipelineDescriptor {
+            vertex: VertexState {
+                shader: self.shader.clone(),
+                entry_point: "vertex".into(),
+                shader_defs: vec![],
+                buffers: vec![],
+            },
+            fragment: Some(FragmentState {
+                shader: self.shader.clone(),
+                shader_defs: vec![],
+                entry_point: "fragment".into(),
+                targets: vec![Some(ColorTargetState {
+                    format,
+                    blend: Some(BlendState::ALPHA_BLENDING),
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            layout: vec![
+                self.mesh_pipeline.get_view_layout(key.into()).clone(),
+                self.bind_group_layout.clone(),
+            ],
+            primitive: PrimitiveState::default(),
+            // the ribbons are blended on top of the boids, so they don't write depth
+            depth_stencil: Some(DepthStencilState {
+                format: CORE_3D_DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: CompareFunction::Greater,
+                stencil: StencilState::default(),
+                bias: DepthBiasState::default(),
+            }),
+            multisample: MultisampleState {
+                count: key.msaa_samples(),
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            label: Some("boids_trails_pipeline".into()),
+            push_constant_ranges: vec![],
+            zero_initialize_workgroup_memory: false,
+        }
+    }
+}
+
+/// (Re)allocate the ring buffers and advance the ring head by one point every simulated frame.
+fn prepare_trail_buffer(
+    mut commands: Commands,
+    mut trails: ResMut<BoidsTrailBuffer>,
+    compute_pipeline: Res<BoidsPipeline>,
+    pipeline: Res<BoidsTrailsPipeline>,
+    config: Res<BoidsConfig>,
+    simulation: Res<BoidsSimulation>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+) {
+    if !config.trails_enabled {
+        trails.buffer = None;
+        commands.remove_resource::<BoidsTrailComputeBindGroup>();
+        commands.remove_resource::<BoidsTrailRenderBindGroup>();
+        return;
+    }
+
+    let length = config.trail_length.clamp(2, MAX_TRAIL_LENGTH);
+    let trails = trails.as_mut();
+    let uniform = trails.uniform.get_mut();
+    if trails.buffer.is_none()
+        || trails.boids_count != config.boids_count
+        || uniform.length != length
+    {
+        trails.buffer = Some(render_device.create_buffer(&BufferDescriptor {
+            label: Some("boids_trail_buffer"),
+            size: u64::from(config.boids_count * length) * TRAIL_POINT_SIZE,
+            usage: BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        }));
+        trails.boids_count = config.boids_count;
+        uniform.length = length;
+        uniform.head = 0;
+        uniform.filled = 1;
+    } else if simulation.advances() {
+        uniform.head = (uniform.head + 1) % length;
+        uniform.filled = (uniform.filled + 1).min(length);
+    }
+    uniform.color_by_speed = config.trail_color_by_speed.into();
+    uniform.max_speed = config.max_speed;
+    uniform.width = BOID_RADIUS;
+
+    trails.uniform.write_buffer(&render_device, &render_queue);
+
+    let (Some(buffer), Some(uniform_binding)) = (&trails.buffer, trails.uniform.binding()) else {
+        return;
+    };
+    let compute_bind_group = render_device.create_bind_group(
+        None,
+        &compute_pipeline.trail_bind_group_layout,
+        &BindGroupEntries::sequential((uniform_binding.clone(), buffer.as_entire_binding())),
+    );
+    let render_bind_group = render_device.create_bind_group(
+        None,
+        &pipeline.bind_group_layout,
+        &BindGroupEntries::sequential((uniform_binding, buffer.as_entire_binding())),
+    );
+    commands.insert_resource(BoidsTrailComputeBindGroup(compute_bind_group));
+    commands.insert_resource(BoidsTrailRenderBindGroup(render_bind_group));
+}
+
+fn queue_trails(
+    draw_functions: Res<DrawFunctions<Transparent3d>>,
+    pipeline: Res<BoidsTrailsPipeline>,
+    mut pipelines: ResMut<SpecializedRenderPipelines<BoidsTrailsPipeline>>,
+    pipeline_cache: Res<PipelineCache>,
+    config: Res<BoidsConfig>,
+    trails: Query<(Entity, &MainEntity), With<BoidsTrails>>,
+    mut transparent_render_phases: ResMut<ViewSortedRenderPhases<Transparent3d>>,
+    views: Query<(&ExtractedView, &Msaa)>,
+) {
+    if !config.trails_enabled {
+        return;
+    }
+
+    let draw_function = draw_functions.read().id::<DrawBoidTrails>();
+
+    for (view, msaa) in &views {
+        let Some(transparent_phase) = transparent_render_phases.get_mut(&view.retained_view_entity)
+        else {
+            continue;
+        };
+
+        let view_key = MeshPipelineKey::from_msaa_samples(msaa.samples())
+            | MeshPipelineKey::from_hdr(view.hdr);
+        let pipeline = pipelines.specialize(&pipeline_cache, &pipeline, view_key);
+
+        for (entity, main_entity) in &trails {
+            transparent_phase.add(Transparent3d {
+                entity: (entity, *main_entity),
+                draw_function,
+                pipeline,
+                distance: 0.,
+                batch_range: 0..1,
+                extra_index: PhaseItemExtraIndex::None,
+                indexed: false,
+            });
+        }
+    }
+}
+
+type DrawBoidTrails = (
+    SetItemPipeline,
+    SetMeshViewBindGroup<0>,
+    DrawTrailRibbons<1>,
+);
+
+/// Draws one camera-facing quad per trail segment, the vertices are generated in the shader
+struct DrawTrailRibbons<const I: usize>;
+
+impl<P: PhaseItem, const I: usize> RenderCommand<P> for DrawTrailRibbons<I> {
+    type Param = (
+        Option<SRes<BoidsTrailRenderBindGroup>>,
+        SRes<BoidsTrailBuffer>,
+    );
+    type ViewQuery = ();
+    type ItemQuery = ();
+
+    fn render<'w>(
+        _item: &P,
+        _view: (),
+        _entity: Option<()>,
+        (bind_group, trails): SystemParamItem<'w, '_, Self::Param>,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        let Some(bind_group) = bind_group else {
+            return RenderCommandResult::Skip;
+        };
+        let trails = trails.into_inner();
+        let length = trails.uniform.get().length;
+
+        pass.set_bind_group(I, &bind_group.into_inner().0, &[]);
+        pass.draw(0..(length - 1) * 6, 0..trails.boids_count);
+        RenderCommandResult::Success
+    }
+}
@@ -1,37 +1,116 @@
-use bevy::ecs::system::ResMut;
+use bevy::{
+    diagnostic::DiagnosticsStore,
+    ecs::{
+        change_detection::DetectChangesMut,
+        system::{Res, ResMut},
+    },
+};
 use bevy_egui::{
     egui::{self, Pos2, Ui},
     EguiContexts,
 };
 
-use super::{boids_compute::BoidsConfig, images::IMAGE_SIZE};
+use super::{
+    boids_compute::{BoidsConfig, BoidsSimulation},
+    color::{color_legend_ui, color_mode_ui},
+    diagnostics::{gpu_timings_ui, BoidsGpuTimings},
+    history::{config_history_ui, ConfigHistory, ConfigSlots},
+    render::BoidsCullStats,
+    summary::{flock_summary_ui, FlockSummary},
+    terrain::{terrain_ui, TerrainConfig},
+};
 
 pub fn boids_ui(config: &mut BoidsConfig, ui: &mut Ui) {
     ui.add(
-        egui::Slider::new(&mut config.boids_count, 1..=IMAGE_SIZE * IMAGE_SIZE)
+        egui::Slider::new(&mut config.boids_count, BoidsConfig::BOIDS_COUNT_LIMITS)
             .text("Number of boids"),
     );
     ui.end_row();
-    ui.add(egui::Slider::new(&mut config.align_range, 1.0..=100.0).text("Align range"));
+    ui.add(
+        egui::Slider::new(&mut config.align_range, BoidsConfig::ALIGN_RANGE_LIMITS)
+            .text("Align range"),
+    );
+    ui.end_row();
+    ui.add(
+        egui::Slider::new(&mut config.avoid_range, BoidsConfig::AVOID_RANGE_LIMITS)
+            .text("Avoid range"),
+    );
+    ui.end_row();
+    ui.add(
+        egui::Slider::new(
+            &mut config.centering_range,
+            BoidsConfig::CENTERING_RANGE_LIMITS,
+        )
+        .text("Centering range"),
+    );
+    ui.end_row();
+    ui.add(
+        egui::Slider::new(&mut config.align_factor, BoidsConfig::FACTOR_LIMITS)
+            .text("Align factor"),
+    );
+    ui.end_row();
+    ui.add(
+        egui::Slider::new(&mut config.avoid_factor, BoidsConfig::FACTOR_LIMITS)
+            .text("Avoid factor"),
+    );
     ui.end_row();
-    ui.add(egui::Slider::new(&mut config.avoid_range, 0.1..=100.0).text("Avoid range"));
+    ui.add(
+        egui::Slider::new(&mut config.centering_factor, BoidsConfig::FACTOR_LIMITS)
+            .text("Centering factor"),
+    );
     ui.end_row();
-    ui.add(egui::Slider::new(&mut config.centering_range, 0.01..=100.0).text("Centering range"));
+    ui.add(
+        egui::Slider::new(&mut config.bounds_margin, BoidsConfig::BOUNDS_MARGIN_LIMITS)
+            .text("Bounds margin"),
+    );
     ui.end_row();
-    ui.add(egui::Slider::new(&mut config.align_factor, 0.0..=10.0).text("Align factor"));
+    ui.add(
+        egui::Slider::new(
+            &mut config.bounds_turn_factor,
+            BoidsConfig::BOUNDS_TURN_FACTOR_LIMITS,
+        )
+        .text("Bounds turn factor"),
+    );
     ui.end_row();
-    ui.add(egui::Slider::new(&mut config.avoid_factor, 0.0..=10.0).text("Avoid factor"));
+    ui.add(
+        egui::Slider::new(&mut config.max_speed, BoidsConfig::MAX_SPEED_LIMITS).text("Max speed"),
+    );
     ui.end_row();
-    ui.add(egui::Slider::new(&mut config.centering_factor, 0.0..=10.0).text("Centering factor"));
+    ui.checkbox(&mut config.frustum_culling, "Frustum culling");
     ui.end_row();
-    ui.add(egui::Slider::new(&mut config.bounds_margin, 0.0..=20.0).text("Bounds margin"));
+    // the medium LOD has to start before the low LOD
+    let lod_low_distance = config.lod_low_distance;
+    ui.add(
+        egui::Slider::new(
+            &mut config.lod_medium_distance,
+            *BoidsConfig::LOD_DISTANCE_LIMITS.start()..=lod_low_distance,
+        )
+        .text("Medium LOD distance"),
+    );
     ui.end_row();
+    let lod_medium_distance = config.lod_medium_distance;
     ui.add(
-        egui::Slider::new(&mut config.bounds_turn_factor, 0.001..=2.0).text("Bounds turn factor"),
+        egui::Slider::new(
+            &mut config.lod_low_distance,
+            lod_medium_distance..=*BoidsConfig::LOD_DISTANCE_LIMITS.end(),
+        )
+        .text("Low LOD distance"),
+    );
+    ui.end_row();
+    ui.checkbox(&mut config.trails_enabled, "Trails");
+    ui.end_row();
+    ui.add_enabled(
+        config.trails_enabled,
+        egui::Slider::new(&mut config.trail_length, BoidsConfig::TRAIL_LENGTH_LIMITS)
+            .text("Trail length"),
     );
     ui.end_row();
-    ui.add(egui::Slider::new(&mut config.max_speed, 0.1..=20.0).text("Max speed"));
+    ui.add_enabled(
+        config.trails_enabled,
+        egui::Checkbox::new(&mut config.trail_color_by_speed, "Color trails by speed"),
+    );
     ui.end_row();
+    color_mode_ui(config, ui);
 
     if ui.button("Reset to defaults").clicked() {
         let default = BoidsConfig::default();
@@ -44,10 +123,69 @@ pub fn boids_ui(config: &mut BoidsConfig, ui: &mut Ui) {
         config.centering_factor = default.centering_factor;
         config.bounds_margin = default.bounds_margin;
         config.bounds_turn_factor = default.bounds_turn_factor;
+        config.frustum_culling = default.frustum_culling;
+        config.lod_medium_distance = default.lod_medium_distance;
+        config.lod_low_distance = default.lod_low_distance;
+        config.trails_enabled = default.trails_enabled;
+        config.trail_length = default.trail_length;
+        config.trail_color_by_speed = default.trail_color_by_speed;
+        config.color_mode = default.color_mode;
+        config.solid_color = default.solid_color;
+        config.color_ramp = default.color_ramp;
+        config.density_max_neighbors = default.density_max_neighbors;
+        config.species_count = default.species_count;
     };
 }
 
-pub fn ui_system(mut boids_config: ResMut<BoidsConfig>, mut contexts: EguiContexts) {
+pub fn simulation_ui(simulation: &mut BoidsSimulation, ui: &mut Ui) {
+    ui.horizontal(|ui| {
+        let label = if simulation.paused { "Resume" } else { "Pause" };
+        if ui.button(label).clicked() {
+            simulation.paused = !simulation.paused;
+        }
+        if ui
+            .add_enabled(simulation.paused, egui::Button::new("Step"))
+            .clicked()
+        {
+            simulation.step(1);
+        }
+        if ui.button("Restart").clicked() {
+            simulation.restart();
+        }
+    });
+    ui.end_row();
+}
+
+pub fn cull_stats_ui(config: &BoidsConfig, stats: &BoidsCullStats, ui: &mut Ui) {
+    ui.label("Visible boids");
+    ui.label(format!("{} / {}", stats.visible, config.boids_count));
+    ui.end_row();
+    ui.label("Drawn per LOD");
+    ui.label(
+        stats
+            .drawn_per_lod
+            .map(|count| count.to_string())
+            .join(" / "),
+    );
+    ui.end_row();
+    ui.label("Drawn triangles");
+    ui.label(stats.drawn_triangles.to_string());
+    ui.end_row();
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn ui_system(
+    mut boids_config: ResMut<BoidsConfig>,
+    mut simulation: ResMut<BoidsSimulation>,
+    mut history: ResMut<ConfigHistory<BoidsConfig>>,
+    mut slots: ResMut<ConfigSlots<BoidsConfig>>,
+    mut terrain_config: ResMut<TerrainConfig>,
+    cull_stats: Res<BoidsCullStats>,
+    flock_summary: Res<FlockSummary>,
+    gpu_timings: Res<BoidsGpuTimings>,
+    diagnostics: Res<DiagnosticsStore>,
+    mut contexts: EguiContexts,
+) {
     let Ok(ctx) = contexts.ctx_mut() else {
         return;
     };
@@ -59,7 +197,30 @@ pub fn ui_system(mut boids_config: ResMut<BoidsConfig>, mut contexts: EguiContex
                 .spacing([40.0, 4.0])
                 .striped(true)
                 .show(ui, |ui| {
+                    simulation_ui(simulation.as_mut(), ui);
+                    config_history_ui(history.as_mut(), slots.as_mut(), boids_config.as_mut(), ui);
                     boids_ui(boids_config.as_mut(), ui);
                 });
+            ui.separator();
+            egui::Grid::new("terrain_grid")
+                .num_columns(2)
+                .spacing([40.0, 4.0])
+                .striped(true)
+                .show(ui, |ui| {
+                    // edit a copy, so the terrain is only regenerated when a value actually changes
+                    let mut terrain = *terrain_config;
+                    terrain_ui(&mut terrain, ui);
+                    terrain_config.set_if_neq(terrain);
+                });
+            ui.separator();
+            egui::Grid::new("boids_stats_grid")
+                .num_columns(2)
+                .spacing([40.0, 4.0])
+                .show(ui, |ui| {
+                    color_legend_ui(&boids_config, ui);
+                    cull_stats_ui(&boids_config, &cull_stats, ui);
+                    flock_summary_ui(&flock_summary, ui);
+                    gpu_timings_ui(&gpu_timings, &diagnostics, ui);
+                });
         });
 }
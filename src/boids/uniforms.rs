@@ -3,6 +3,7 @@ use bevy::{
     render::{
         extract_resource::ExtractResource,
         render_resource::{AsBindGroup, ShaderType, UniformBuffer},
+        storage::ShaderStorageBuffer,
     },
 };
 
@@ -52,3 +53,143 @@ pub(crate) struct BoidsImage {
     #[storage_texture(1, image_format = Rgba32Float, access = ReadWrite)]
     pub(crate) velocity_map: Handle<Image>,
 }
+
+/// Number of level-of-detail meshes the boids are bucketed into
+pub const LOD_COUNT: usize = 3;
+
+#[derive(Clone, Default, ShaderType)]
+pub struct BoidsCullUniform {
+    /// The camera frustum as six planes, `xyz` being the inward normal and `w` the distance
+    pub frustum: [Vec4; 6],
+    pub camera_position: Vec3,
+    pub boid_radius: f32,
+    /// Camera distances beyond which boids switch to the next (coarser) LOD mesh
+    pub lod_distances: Vec2,
+    pub frustum_culling: u32,
+}
+
+/// The buffer containing the [`BoidsCullUniform`]
+#[derive(Resource, Default)]
+pub struct BoidsCullUniformBuffer {
+    pub buffer: UniformBuffer<BoidsCullUniform>,
+}
+
+/// Matches the layout of `wgpu`'s `DrawIndexedIndirectArgs`
+#[derive(Clone, Copy, Default, Debug, ShaderType)]
+pub struct DrawIndexedIndirectArgs {
+    pub index_count: u32,
+    pub instance_count: u32,
+    pub first_index: u32,
+    pub base_vertex: i32,
+    pub first_instance: u32,
+}
+
+#[derive(Clone, Default, Debug, ShaderType)]
+pub struct BoidsIndirectArgs {
+    pub lods: [DrawIndexedIndirectArgs; LOD_COUNT],
+}
+
+/// Buffers written by the culling compute pass and consumed by the indirect boid draws
+#[derive(Resource, Clone, ExtractResource)]
+pub(crate) struct BoidsInstanceBuffers {
+    /// Visible boid indices, one bucket of `IMAGE_SIZE * IMAGE_SIZE` entries per LOD
+    pub(crate) visible_instances: Handle<ShaderStorageBuffer>,
+
+    /// A [`BoidsIndirectArgs`]
+    pub(crate) indirect_args: Handle<ShaderStorageBuffer>,
+}
+
+#[derive(Clone, Default, ShaderType)]
+pub struct BoidsTrailUniform {
+    /// Number of points in the ring buffer of each boid
+    pub length: u32,
+    /// Slot of the newest point in the ring buffer
+    pub head: u32,
+    /// Number of points written since the ring buffer was allocated, at most `length`
+    pub filled: u32,
+    pub color_by_speed: u32,
+    pub max_speed: f32,
+    pub width: f32,
+}
+
+/// Number of stops in the color ramp the boids are colored with
+pub const COLOR_RAMP_SIZE: usize = 4;
+
+#[derive(Clone, Default, ShaderType)]
+pub struct BoidsColorUniform {
+    pub solid_color: Vec4,
+    pub ramp: [Vec4; COLOR_RAMP_SIZE],
+    /// A [`super::color::BoidsColorMode`]
+    pub mode: u32,
+    pub max_speed: f32,
+    pub density_max_neighbors: f32,
+    pub species_count: u32,
+}
+
+/// The buffer containing the [`BoidsColorUniform`]
+#[derive(Resource, Default)]
+pub struct BoidsColorUniformBuffer {
+    pub buffer: UniformBuffer<BoidsColorUniform>,
+}
+
+/// The terrain the boids keep their distance from, see [`super::terrain::TerrainConfig`]
+#[derive(Clone, Default, ShaderType)]
+pub struct HeightfieldUniform {
+    pub origin: Vec2,
+    pub size: f32,
+    pub base_height: f32,
+    pub resolution: u32,
+    pub min_altitude: f32,
+    pub avoid_factor: f32,
+}
+
+/// The buffer containing the [`HeightfieldUniform`]
+#[derive(Resource, Default)]
+pub struct HeightfieldUniformBuffer {
+    pub buffer: UniformBuffer<HeightfieldUniform>,
+}
+
+/// Maximum number of waypoints of a [`super::route::BoidsRoute`] that are uploaded to the shader
+pub const MAX_WAYPOINTS: usize = 16;
+
+#[derive(Clone, Default, ShaderType)]
+pub struct RouteUniform {
+    /// xyz is the position of the waypoint, w its arrival radius
+    pub waypoints: [Vec4; MAX_WAYPOINTS],
+    pub count: u32,
+    pub current: u32,
+    /// Steering weight towards the current waypoint, 0 while the route is disabled
+    pub weight: f32,
+}
+
+/// The buffer containing the [`RouteUniform`]
+#[derive(Resource, Default)]
+pub struct RouteUniformBuffer {
+    pub buffer: UniformBuffer<RouteUniform>,
+}
+
+/// Aggregate of a group of boids written by the `reduce` compute passes, both per workgroup and
+/// for the whole flock
+#[derive(Clone, Default, Debug, ShaderType)]
+pub struct FlockReduction {
+    /// Sum of the positions per workgroup, the centroid for the whole flock
+    pub position: Vec3,
+    pub count: u32,
+    /// Sum of the velocities per workgroup, the mean velocity for the whole flock
+    pub velocity: Vec3,
+    pub speed_min: f32,
+    pub aabb_min: Vec3,
+    pub speed_max: f32,
+    pub aabb_max: Vec3,
+}
+
+/// Buffers written by the reduction compute passes
+#[derive(Resource, Clone, ExtractResource)]
+pub(crate) struct FlockReductionBuffers {
+    /// One [`FlockReduction`] per workgroup of the `reduce` pass
+    pub(crate) partials: Handle<ShaderStorageBuffer>,
+
+    /// A single [`FlockReduction`] for the whole flock, read back into a
+    /// [`super::summary::FlockSummary`]
+    pub(crate) summary: Handle<ShaderStorageBuffer>,
+}
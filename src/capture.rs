@@ -0,0 +1,480 @@
+use std::{
+    collections::BTreeMap,
+    io::Write,
+    process::{Command, Stdio},
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        mpsc::{sync_channel, SyncSender},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use bevy::{
+    prelude::*,
+    render::{
+        camera::RenderTarget,
+        render_asset::RenderAssetUsages,
+        render_resource::{Extent3d, TextureDimension, TextureFormat, TextureUsages},
+        view::screenshot::{Screenshot, ScreenshotCaptured},
+    },
+    time::TimeUpdateStrategy,
+    transform::TransformSystem,
+};
+use bevy_egui::{
+    egui::{self, Pos2, Ui},
+    EguiContexts,
+};
+
+/// Frames that may be requested but not written yet, about 8 MB each at 1080p.
+///
+/// The simulation is held while the writer is this far behind, so the memory stays bounded and
+/// every frame is still captured.
+const MAX_QUEUED_FRAMES: u32 = 8;
+
+/// Camera that renders the captured frames offscreen, following the main camera
+#[derive(Component)]
+pub struct CaptureCamera;
+
+#[derive(Resource, Clone)]
+pub struct CaptureSettings {
+    pub width: u32,
+    pub height: u32,
+    /// Frames per second of the capture, the simulation advances `1 / fps` seconds per frame
+    pub fps: u32,
+    pub seconds: f32,
+    /// Directory the numbered PNG frames are written to
+    pub output_dir: String,
+    pub write_png: bool,
+    /// Pipe the raw RGBA frames to the stdin of `encoder_command`
+    pub use_encoder: bool,
+    /// `{width}`, `{height}`, `{fps}` and `{output_dir}` are replaced by the capture settings
+    pub encoder_command: String,
+}
+
+impl Default for CaptureSettings {
+    fn default() -> Self {
+        Self {
+            width: 1920,
+            height: 1080,
+            fps: 60,
+            seconds: 10.0,
+            output_dir: "capture".to_string(),
+            write_png: true,
+            use_encoder: false,
+            encoder_command: "ffmpeg -y -f rawvideo -pix_fmt rgba -s {width}x{height} -r {fps} \
+                -i - -pix_fmt yuv420p {output_dir}/capture.mp4"
+                .to_string(),
+        }
+    }
+}
+
+impl CaptureSettings {
+    pub fn total_frames(&self) -> u32 {
+        (self.seconds * self.fps as f32).round() as u32
+    }
+
+    fn encoder_args(&self) -> Vec<String> {
+        self.encoder_command
+            .split_whitespace()
+            .map(|arg| {
+                arg.replace("{width}", &self.width.to_string())
+                    .replace("{height}", &self.height.to_string())
+                    .replace("{fps}", &self.fps.to_string())
+                    .replace("{output_dir}", &self.output_dir)
+            })
+            .collect()
+    }
+}
+
+struct CapturedFrame {
+    index: u32,
+    image: Image,
+}
+
+struct ActiveCapture {
+    target: Handle<Image>,
+    camera: Entity,
+    /// Index of the next frame to request
+    frame: u32,
+    total_frames: u32,
+    /// Time the simulation advances per captured frame
+    frame_duration: Duration,
+    /// Whether time is stopped until the writer catches up
+    held: bool,
+    /// Dropped once every frame has been requested, which ends the writer thread
+    sender: Option<SyncSender<CapturedFrame>>,
+    written: Arc<AtomicU32>,
+    writer: JoinHandle<()>,
+}
+
+/// A running capture and the outcome of the last one
+#[derive(Resource, Default)]
+pub struct FrameCapture {
+    active: Option<ActiveCapture>,
+    status: String,
+}
+
+impl FrameCapture {
+    pub fn is_capturing(&self) -> bool {
+        self.active.is_some()
+    }
+
+    /// Whether the simulation is held until the writer catches up, no frame is requested then
+    pub fn is_held(&self) -> bool {
+        self.active.as_ref().is_some_and(|active| active.held)
+    }
+}
+
+pub struct FrameCapturePlugin;
+
+impl Plugin for FrameCapturePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CaptureSettings>()
+            .init_resource::<FrameCapture>()
+            .add_systems(Update, capture_ui_system)
+            .add_systems(
+                PostUpdate,
+                (sync_capture_camera, capture_frame)
+                    .chain()
+                    .before(TransformSystem::TransformPropagate),
+            );
+    }
+}
+
+/// Write the frames on a separate thread, so encoding doesn't stall the app.
+///
+/// Frames are written in order, even when their readbacks complete out of order.
+fn spawn_writer(
+    settings: &CaptureSettings,
+) -> Result<(SyncSender<CapturedFrame>, Arc<AtomicU32>, JoinHandle<()>), String> {
+    std::fs::create_dir_all(&settings.output_dir).map_err(|error| error.to_string())?;
+
+    let mut encoder = if settings.use_encoder {
+        let args = settings.encoder_args();
+        let (program, args) = args.split_first().ok_or("The encoder command is empty")?;
+        let child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|error| format!("Failed to start {program}: {error}"))?;
+        Some(child)
+    } else {
+        None
+    };
+
+    // `capture_frame` never has more than this many frames in flight, so sending doesn't block
+    let (sender, receiver) = sync_channel::<CapturedFrame>(MAX_QUEUED_FRAMES as usize);
+    let written = Arc::new(AtomicU32::new(0));
+    let thread_written = written.clone();
+    let output_dir = settings.output_dir.clone();
+    let write_png = settings.write_png;
+
+    let writer = std::thread::spawn(move || {
+        let mut pending = BTreeMap::new();
+        let mut next_index = 0;
+        let mut stdin = encoder.as_mut().and_then(|child| child.stdin.take());
+
+        for frame in receiver {
+            pending.insert(frame.index, frame.image);
+            while let Some(image) = pending.remove(&next_index) {
+                let rgba = match image.try_into_dynamic() {
+                    Ok(image) => image.to_rgba8(),
+                    Err(error) => {
+                        warn!("Failed to convert captured frame {next_index}: {error}");
+                        // still counted, so the capture isn't held waiting for it
+                        next_index += 1;
+                        thread_written.fetch_add(1, Ordering::Relaxed);
+                        continue;
+                    }
+                };
+                if write_png {
+                    let path = format!("{output_dir}/frame_{next_index:05}.png");
+                    if let Err(error) = rgba.save(&path) {
+                        warn!("Failed to write {path}: {error}");
+                    }
+                }
+                if let Some(pipe) = stdin.as_mut() {
+                    if let Err(error) = pipe.write_all(rgba.as_raw()) {
+                        warn!("Failed to pipe frame {next_index} to the encoder: {error}");
+                        stdin = None;
+                    }
+                }
+                next_index += 1;
+                thread_written.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        // closing stdin tells the encoder the stream has ended
+        drop(stdin);
+        if let Some(mut child) = encoder {
+            if let Err(error) = child.wait() {
+                warn!("The encoder failed: {error}");
+            }
+        }
+    });
+
+    Ok((sender, written, writer))
+}
+
+fn start_capture(
+    commands: &mut Commands,
+    settings: &CaptureSettings,
+    images: &mut Assets<Image>,
+    main_camera: (&Transform, &Projection),
+) -> Result<ActiveCapture, String> {
+    let (sender, written, writer) = spawn_writer(settings)?;
+
+    let mut target = Image::new_fill(
+        Extent3d {
+            width: settings.width,
+            height: settings.height,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &[0, 0, 0, 255],
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::default(),
+    );
+    target.texture_descriptor.usage |=
+        TextureUsages::COPY_SRC | TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING;
+    let target = images.add(target);
+
+    let (transform, projection) = main_camera;
+    let camera = commands
+        .spawn((
+            CaptureCamera,
+            Camera3d::default(),
+            Camera {
+                hdr: true,
+                target: RenderTarget::Image(target.clone().into()),
+                ..default()
+            },
+            *transform,
+            projection.clone(),
+        ))
+        .id();
+
+    // advance the same amount of time every frame, however long rendering and encoding takes
+    let frame_duration = Duration::from_secs_f64(1.0 / settings.fps as f64);
+    commands.insert_resource(TimeUpdateStrategy::ManualDuration(frame_duration));
+
+    Ok(ActiveCapture {
+        target,
+        camera,
+        frame: 0,
+        total_frames: settings.total_frames(),
+        frame_duration,
+        held: false,
+        sender: Some(sender),
+        written,
+        writer,
+    })
+}
+
+fn sync_capture_camera(
+    main_cameras: Query<(&Transform, &Projection), (With<Camera3d>, Without<CaptureCamera>)>,
+    mut capture_cameras: Query<(&mut Transform, &mut Projection), With<CaptureCamera>>,
+) {
+    let Ok((main_transform, main_projection)) = main_cameras.single() else {
+        return;
+    };
+    for (mut transform, mut projection) in &mut capture_cameras {
+        *transform = *main_transform;
+        // only copy the field of view, the aspect ratio follows the capture resolution
+        if let (Projection::Perspective(main), Projection::Perspective(perspective)) =
+            (main_projection, projection.as_mut())
+        {
+            perspective.fov = main.fov;
+        }
+    }
+}
+
+pub(crate) fn capture_frame(mut commands: Commands, mut capture: ResMut<FrameCapture>) {
+    let Some(active) = capture.active.as_mut() else {
+        return;
+    };
+
+    if active.frame < active.total_frames {
+        // hold the simulation instead of queueing more frames than the writer keeps up with, this
+        // frame is then requested again once time moves on
+        let queued = active.frame - active.written.load(Ordering::Relaxed);
+        let held = queued >= MAX_QUEUED_FRAMES;
+        if held != active.held {
+            active.held = held;
+            let duration = if held {
+                Duration::ZERO
+            } else {
+                active.frame_duration
+            };
+            commands.insert_resource(TimeUpdateStrategy::ManualDuration(duration));
+        }
+        if held {
+            return;
+        }
+
+        if let Some(sender) = active.sender.clone() {
+            let index = active.frame;
+            commands
+                .spawn(Screenshot::image(active.target.clone()))
+                .observe(move |trigger: Trigger<ScreenshotCaptured>| {
+                    // the writer is gone if it failed, there is nothing left to do with the frame
+                    let _ = sender.send(CapturedFrame {
+                        index,
+                        image: trigger.event().0.clone(),
+                    });
+                });
+        }
+        active.frame += 1;
+        return;
+    }
+
+    // every frame has been requested, the screenshots keep the channel open until they arrive
+    if active.sender.take().is_some() {
+        commands.entity(active.camera).despawn();
+        commands.insert_resource(TimeUpdateStrategy::Automatic);
+    }
+    if active.writer.is_finished() {
+        let written = active.written.load(Ordering::Relaxed);
+        capture.status = format!("Captured {written} frames");
+        capture.active = None;
+    }
+}
+
+pub fn capture_settings_ui(settings: &mut CaptureSettings, ui: &mut Ui) {
+    ui.label("Resolution");
+    ui.horizontal(|ui| {
+        ui.add(egui::DragValue::new(&mut settings.width).range(16..=7680));
+        ui.label("x");
+        ui.add(egui::DragValue::new(&mut settings.height).range(16..=4320));
+    });
+    ui.end_row();
+    ui.label("Frame rate");
+    ui.add(egui::Slider::new(&mut settings.fps, 1..=120).suffix(" fps"));
+    ui.end_row();
+    ui.label("Duration");
+    ui.add(egui::Slider::new(&mut settings.seconds, 0.5..=120.0).suffix(" s"));
+    ui.end_row();
+    ui.label("Output directory");
+    ui.text_edit_singleline(&mut settings.output_dir);
+    ui.end_row();
+    ui.label("PNG sequence");
+    ui.checkbox(&mut settings.write_png, "Write frames");
+    ui.end_row();
+    ui.checkbox(&mut settings.use_encoder, "Pipe to encoder");
+    ui.add_enabled(
+        settings.use_encoder,
+        egui::TextEdit::multiline(&mut settings.encoder_command).desired_rows(2),
+    );
+    ui.end_row();
+}
+
+fn capture_ui_system(
+    mut commands: Commands,
+    mut settings: ResMut<CaptureSettings>,
+    mut capture: ResMut<FrameCapture>,
+    mut images: ResMut<Assets<Image>>,
+    main_cameras: Query<(&Transform, &Projection), (With<Camera3d>, Without<CaptureCamera>)>,
+    mut contexts: EguiContexts,
+) {
+    let Ok(ctx) = contexts.ctx_mut() else {
+        return;
+    };
+    egui::Window::new("Capture")
+        .default_pos(Pos2 { x: 420., y: 700. })
+        .default_open(false)
+        .show(ctx, |ui| {
+            ui.add_enabled_ui(!capture.is_capturing(), |ui| {
+                egui::Grid::new("capture_grid")
+                    .num_columns(2)
+                    .spacing([40.0, 4.0])
+                    .striped(true)
+                    .show(ui, |ui| {
+                        capture_settings_ui(settings.as_mut(), ui);
+                    });
+            });
+            ui.separator();
+
+            if let Some(active) = &capture.active {
+                let written = active.written.load(Ordering::Relaxed);
+                ui.add(
+                    egui::ProgressBar::new(written as f32 / active.total_frames.max(1) as f32)
+                        .text(format!("{written} / {} frames", active.total_frames)),
+                );
+                return;
+            }
+
+            let label = format!("Capture {} seconds", settings.seconds);
+            if ui.button(label).clicked() {
+                let Ok(main_camera) = main_cameras.single() else {
+                    capture.status = "There is no camera to capture".to_string();
+                    return;
+                };
+                match start_capture(&mut commands, &settings, &mut images, main_camera) {
+                    Ok(active) => {
+                        capture.active = Some(active);
+                        capture.status.clear();
+                    }
+                    Err(error) => capture.status = error,
+                }
+            }
+            if !capture.status.is_empty() {
+                ui.label(&capture.status);
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::boids::boids_compute::{advance_simulation, BoidsSimulation};
+
+    #[test]
+    fn simulation_steps_once_per_requested_frame() {
+        let mut app = App::new();
+        app.init_resource::<BoidsSimulation>().add_systems(
+            PostUpdate,
+            (capture_frame, advance_simulation.after(capture_frame)),
+        );
+
+        let written = Arc::new(AtomicU32::new(0));
+        let camera = app.world_mut().spawn_empty().id();
+        let total_frames = 40;
+        app.insert_resource(FrameCapture {
+            active: Some(ActiveCapture {
+                target: Handle::default(),
+                camera,
+                frame: 0,
+                total_frames,
+                frame_duration: Duration::from_secs_f64(1.0 / 60.0),
+                held: false,
+                sender: Some(sync_channel(1).0),
+                written: written.clone(),
+                writer: std::thread::spawn(|| {}),
+            }),
+            status: String::new(),
+        });
+
+        let (mut steps, mut held_updates) = (0, 0);
+        for update in 0.. {
+            // a writer that only keeps up with every other frame
+            written.store(update / 2, Ordering::Relaxed);
+            app.update();
+
+            if app.world().resource::<BoidsSimulation>().advances() {
+                steps += 1;
+            }
+            let capture = app.world().resource::<FrameCapture>();
+            if capture.is_held() {
+                held_updates += 1;
+            }
+            let frame = capture.active.as_ref().unwrap().frame;
+            assert_eq!(steps, frame);
+            if frame == total_frames {
+                break;
+            }
+        }
+        assert!(held_updates > 0);
+    }
+}
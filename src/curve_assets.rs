@@ -0,0 +1,569 @@
+//! Color gradients and float curves as RON assets, so they can be tweaked by hand or in the
+//! editor window and hot reloaded.
+
+use std::marker::PhantomData;
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::Path;
+
+#[cfg(not(target_arch = "wasm32"))]
+use bevy::asset::io::file::FileAssetReader;
+use bevy::{
+    asset::{
+        io::Reader,
+        ron::{self, ser::PrettyConfig},
+        AssetLoader, LoadContext,
+    },
+    prelude::*,
+    reflect::{
+        serde::{TypedReflectDeserializer, TypedReflectSerializer},
+        GetTypeRegistration, TypeRegistry, TypeRegistryArc,
+    },
+    render::{
+        render_asset::RenderAssetUsages,
+        render_resource::{Extent3d, TextureDimension, TextureFormat},
+    },
+};
+use bevy_egui::{
+    egui::{self, Pos2, Ui},
+    EguiContexts,
+};
+
+use crate::{
+    boids::color::gradient_bar,
+    math::{
+        curve::{curve_ui, KeyframeCurve},
+        easing::Easing,
+    },
+};
+
+/// A type that is stored as reflected RON
+pub trait Ron: FromReflect + GetTypeRegistration {
+    /// Restore invariants the file might not uphold, like the order of keys
+    fn normalize(&mut self) {}
+}
+
+/// An asset that is stored as reflected RON, in files ending in one of its extensions
+pub trait RonAsset: Asset + Ron {
+    const EXTENSIONS: &'static [&'static str];
+}
+
+pub fn from_ron<A: Ron>(ron: &str, registry: &TypeRegistry) -> Result<A, String> {
+    let reflected = ron::Options::default()
+        .from_str_seed(ron, TypedReflectDeserializer::of::<A>(registry))
+        .map_err(|error| error.to_string())?;
+    let mut asset = A::from_reflect(&*reflected)
+        .ok_or_else(|| format!("does not contain a {}", A::type_path()))?;
+    asset.normalize();
+    Ok(asset)
+}
+
+pub fn to_ron<A: Ron>(asset: &A, registry: &TypeRegistry) -> Result<String, String> {
+    let serializer = TypedReflectSerializer::new(asset.as_partial_reflect(), registry);
+    ron::ser::to_string_pretty(&serializer, PrettyConfig::default())
+        .map_err(|error| error.to_string())
+}
+
+struct RonAssetLoader<A> {
+    registry: TypeRegistryArc,
+    marker: PhantomData<fn() -> A>,
+}
+
+impl<A> FromWorld for RonAssetLoader<A> {
+    fn from_world(world: &mut World) -> Self {
+        Self {
+            registry: world.resource::<AppTypeRegistry>().0.clone(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<A: RonAsset> AssetLoader for RonAssetLoader<A> {
+    type Asset = A;
+    type Settings = ();
+    type Error = String;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        load_context: &mut LoadContext<'_>,
+    ) -> Result<A, String> {
+        let mut bytes = Vec::new();
+        reader
+            .read_to_end(&mut bytes)
+            .await
+            .map_err(|error| error.to_string())?;
+        let ron = std::str::from_utf8(&bytes).map_err(|error| error.to_string())?;
+        from_ron(ron, &self.registry.read())
+            .map_err(|error| format!("{}: {error}", load_context.path().display()))
+    }
+
+    fn extensions(&self) -> &[&str] {
+        A::EXTENSIONS
+    }
+}
+
+/// Write an asset back to the file it was loaded from, which makes the file watcher reload it
+#[cfg(not(target_arch = "wasm32"))]
+fn save_asset<A: RonAsset>(
+    asset: &A,
+    path: &Path,
+    registry: &AppTypeRegistry,
+) -> Result<(), String> {
+    let ron = to_ron(asset, &registry.read())?;
+    let path = FileAssetReader::get_base_path().join("assets").join(path);
+    std::fs::write(path, ron).map_err(|error| error.to_string())
+}
+
+#[derive(Reflect, Clone, Copy, Debug, PartialEq)]
+pub struct GradientKey {
+    /// Where the key sits in `[0, 1]`
+    pub position: f32,
+    pub color: LinearRgba,
+}
+
+/// Colors linearly interpolated between keys, loaded from `.gradient.ron` files
+#[derive(Asset, Reflect, Clone, Debug, PartialEq)]
+pub struct ColorGradient {
+    /// Sorted by position
+    pub keys: Vec<GradientKey>,
+}
+
+impl Default for ColorGradient {
+    fn default() -> Self {
+        Self {
+            keys: vec![
+                GradientKey {
+                    position: 0.0,
+                    color: LinearRgba::BLACK,
+                },
+                GradientKey {
+                    position: 1.0,
+                    color: LinearRgba::WHITE,
+                },
+            ],
+        }
+    }
+}
+
+impl ColorGradient {
+    /// The color at `t`, which is held constant before the first and after the last key
+    pub fn sample(&self, t: f32) -> LinearRgba {
+        let index = self.keys.partition_point(|key| key.position <= t);
+        match (
+            index.checked_sub(1).map(|index| &self.keys[index]),
+            self.keys.get(index),
+        ) {
+            (Some(from), Some(to)) => {
+                let t = ((t - from.position) / (to.position - from.position)).clamp(0.0, 1.0);
+                from.color.mix(&to.color, t)
+            }
+            (Some(key), None) | (None, Some(key)) => key.color,
+            (None, None) => LinearRgba::NONE,
+        }
+    }
+}
+
+impl RonAsset for ColorGradient {
+    const EXTENSIONS: &'static [&'static str] = &["gradient.ron"];
+}
+
+impl Ron for ColorGradient {
+    fn normalize(&mut self) {
+        self.keys.sort_by(|a, b| a.position.total_cmp(&b.position));
+    }
+}
+
+/// A [`KeyframeCurve`] loaded from `.curve.ron` files
+#[derive(Asset, Reflect, Clone, Debug, PartialEq)]
+pub struct FloatCurve {
+    pub curve: KeyframeCurve,
+}
+
+impl Default for FloatCurve {
+    fn default() -> Self {
+        Self {
+            curve: KeyframeCurve::new(0.0, 1.0, Easing::Linear),
+        }
+    }
+}
+
+impl RonAsset for FloatCurve {
+    const EXTENSIONS: &'static [&'static str] = &["curve.ron"];
+}
+
+impl Ron for FloatCurve {
+    fn normalize(&mut self) {
+        self.curve.sort();
+    }
+}
+
+/// An asset that can be sampled in shaders from a small 1D texture
+pub trait BakeTexture: Asset {
+    fn bake(&self, width: u32) -> Image;
+}
+
+fn baked_image(width: u32, data: Vec<u8>, format: TextureFormat) -> Image {
+    Image::new(
+        Extent3d {
+            width,
+            height: 1,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D1,
+        data,
+        format,
+        RenderAssetUsages::RENDER_WORLD,
+    )
+}
+
+/// Fraction of the baked range at the center of texel `index`
+fn texel_center(index: u32, width: u32) -> f32 {
+    (index as f32 + 0.5) / width as f32
+}
+
+impl BakeTexture for ColorGradient {
+    /// Samples `[0, 1]` at the texel centers into an sRGB texture, so a linear sampler
+    /// interpolates between the samples like [`ColorGradient::sample`]
+    fn bake(&self, width: u32) -> Image {
+        let data = (0..width)
+            .flat_map(|index| Srgba::from(self.sample(texel_center(index, width))).to_u8_array())
+            .collect();
+        baked_image(width, data, TextureFormat::Rgba8UnormSrgb)
+    }
+}
+
+impl BakeTexture for FloatCurve {
+    /// Samples the curve from its first to its last key into an `R32Float` texture, which can
+    /// only be read with `textureLoad` or a non-filtering sampler
+    fn bake(&self, width: u32) -> Image {
+        let keys = &self.curve.keys;
+        let start = keys.first().map_or(0.0, |key| key.time);
+        let end = keys.last().map_or(1.0, |key| key.time);
+        let data = (0..width)
+            .flat_map(|index| {
+                let time = start + (end - start) * texel_center(index, width);
+                self.curve.sample(time).to_ne_bytes()
+            })
+            .collect();
+        baked_image(width, data, TextureFormat::R32Float)
+    }
+}
+
+/// Keeps `texture` baked from `source` as the source asset is loaded and edited
+#[derive(Component)]
+pub struct BakedTexture<A: BakeTexture> {
+    pub source: Handle<A>,
+    pub texture: Handle<Image>,
+    /// Number of texels
+    pub width: u32,
+}
+
+impl<A: BakeTexture> BakedTexture<A> {
+    pub fn new(source: Handle<A>, width: u32, images: &Assets<Image>) -> Self {
+        Self {
+            source,
+            texture: images.reserve_handle(),
+            width,
+        }
+    }
+}
+
+fn update_baked_textures<A: BakeTexture>(
+    mut events: EventReader<AssetEvent<A>>,
+    sources: Res<Assets<A>>,
+    mut images: ResMut<Assets<Image>>,
+    baked: Query<Ref<BakedTexture<A>>>,
+) {
+    let changed: Vec<AssetId<A>> = events
+        .read()
+        .filter_map(|event| match *event {
+            AssetEvent::Added { id } | AssetEvent::Modified { id } => Some(id),
+            _ => None,
+        })
+        .collect();
+
+    for baked in &baked {
+        if !baked.is_added() && !changed.contains(&baked.source.id()) {
+            continue;
+        }
+        if let Some(source) = sources.get(&baked.source) {
+            images.insert(&baked.texture, source.bake(baked.width));
+        }
+    }
+}
+
+/// A preview and rows to edit the keys of a gradient, in a grid with two columns
+pub fn color_gradient_ui(gradient: &mut ColorGradient, ui: &mut Ui) {
+    ui.label("Preview");
+    gradient_bar(ui, |t| gradient.sample(t));
+    ui.end_row();
+
+    let mut removed = None;
+    for (index, key) in gradient.keys.iter_mut().enumerate() {
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::DragValue::new(&mut key.position)
+                    .speed(0.01)
+                    .range(0.0..=1.0),
+            );
+            let mut rgba = key.color.to_f32_array();
+            if ui.color_edit_button_rgba_unmultiplied(&mut rgba).changed() {
+                key.color = LinearRgba::from_f32_array(rgba);
+            }
+        });
+        if ui.button("Remove").clicked() {
+            removed = Some(index);
+        }
+        ui.end_row();
+    }
+    if let Some(index) = removed {
+        gradient.keys.remove(index);
+    }
+    gradient.normalize();
+
+    if ui.button("Add key").clicked() {
+        // split the widest gap between keys
+        let mut positions: Vec<f32> = gradient.keys.iter().map(|key| key.position).collect();
+        positions.insert(0, 0.0);
+        positions.push(1.0);
+        let position = positions
+            .windows(2)
+            .max_by(|a, b| (a[1] - a[0]).total_cmp(&(b[1] - b[0])))
+            .map_or(0.5, |gap| (gap[0] + gap[1]) / 2.0);
+        gradient.keys.push(GradientKey {
+            position,
+            color: gradient.sample(position),
+        });
+        gradient.normalize();
+    }
+    ui.end_row();
+}
+
+/// Editors for every loaded asset of type `A`, which only touch the asset when a value changes
+// the web has no files to save to
+#[cfg_attr(target_arch = "wasm32", allow(unused_variables))]
+fn asset_editors_ui<A: RonAsset + Clone + PartialEq>(
+    assets: &mut Assets<A>,
+    asset_server: &AssetServer,
+    registry: &AppTypeRegistry,
+    status: &mut String,
+    ui: &mut Ui,
+    editor: impl Fn(&mut A, &str, &mut Ui),
+) {
+    let ids: Vec<AssetId<A>> = assets.ids().collect();
+    for id in ids {
+        let Some(path) = asset_server.get_path(id) else {
+            continue;
+        };
+        let name = path.to_string();
+        let mut edited = assets.get(id).unwrap().clone();
+        ui.collapsing(&name, |ui| {
+            egui::Grid::new(&name)
+                .num_columns(2)
+                .spacing([20.0, 4.0])
+                .striped(true)
+                .show(ui, |ui| editor(&mut edited, &name, ui));
+            #[cfg(not(target_arch = "wasm32"))]
+            if ui.button("Save").clicked() {
+                *status = match save_asset(&edited, path.path(), registry) {
+                    Ok(()) => format!("Saved {name}"),
+                    Err(error) => error,
+                };
+            }
+        });
+        if assets.get(id) != Some(&edited) {
+            *assets.get_mut(id).unwrap() = edited;
+        }
+    }
+}
+
+fn curve_assets_ui_system(
+    mut gradients: ResMut<Assets<ColorGradient>>,
+    mut curves: ResMut<Assets<FloatCurve>>,
+    asset_server: Res<AssetServer>,
+    registry: Res<AppTypeRegistry>,
+    mut status: Local<String>,
+    mut contexts: EguiContexts,
+) {
+    let Ok(ctx) = contexts.ctx_mut() else {
+        return;
+    };
+    egui::Window::new("Gradients and curves")
+        .default_pos(Pos2 { x: 10., y: 400. })
+        .default_open(false)
+        .show(ctx, |ui| {
+            asset_editors_ui(
+                &mut gradients,
+                &asset_server,
+                &registry,
+                &mut status,
+                ui,
+                |gradient, _, ui| color_gradient_ui(gradient, ui),
+            );
+            asset_editors_ui(
+                &mut curves,
+                &asset_server,
+                &registry,
+                &mut status,
+                ui,
+                |curve, id, ui| curve_ui(&mut curve.curve, id, ui),
+            );
+            if !status.is_empty() {
+                ui.separator();
+                ui.label(status.as_str());
+            }
+        });
+}
+
+pub struct CurveAssetsPlugin;
+
+impl Plugin for CurveAssetsPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<ColorGradient>()
+            .register_type::<FloatCurve>()
+            .init_asset::<ColorGradient>()
+            .init_asset::<FloatCurve>()
+            .init_asset_loader::<RonAssetLoader<ColorGradient>>()
+            .init_asset_loader::<RonAssetLoader<FloatCurve>>()
+            .add_systems(
+                Update,
+                (
+                    update_baked_textures::<ColorGradient>,
+                    update_baked_textures::<FloatCurve>,
+                    curve_assets_ui_system,
+                ),
+            );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::curve::CurveKey;
+
+    fn registry() -> TypeRegistry {
+        let mut registry = TypeRegistry::default();
+        registry.register::<ColorGradient>();
+        registry.register::<FloatCurve>();
+        registry
+    }
+
+    fn gradient() -> ColorGradient {
+        ColorGradient {
+            keys: vec![
+                GradientKey {
+                    position: 0.25,
+                    color: LinearRgba::RED,
+                },
+                GradientKey {
+                    position: 0.75,
+                    color: LinearRgba::new(0.0, 0.0, 1.0, 0.0),
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn gradient_sampling() {
+        let gradient = gradient();
+        assert_eq!(gradient.sample(0.25), LinearRgba::RED);
+        assert_eq!(gradient.sample(0.5), LinearRgba::new(0.5, 0.0, 0.5, 0.5));
+        assert_eq!(gradient.sample(0.75), LinearRgba::new(0.0, 0.0, 1.0, 0.0));
+        // held outside of the keys
+        assert_eq!(gradient.sample(0.0), LinearRgba::RED);
+        assert_eq!(gradient.sample(1.0), LinearRgba::new(0.0, 0.0, 1.0, 0.0));
+
+        assert_eq!(
+            ColorGradient { keys: Vec::new() }.sample(0.5),
+            LinearRgba::NONE
+        );
+    }
+
+    #[test]
+    fn baked_gradient_matches_sampling() {
+        let gradient = gradient();
+        let image = gradient.bake(16);
+        assert_eq!(image.texture_descriptor.dimension, TextureDimension::D1);
+        assert_eq!(
+            image.texture_descriptor.format,
+            TextureFormat::Rgba8UnormSrgb
+        );
+        assert_eq!(image.width(), 16);
+
+        let data = image.data.unwrap();
+        for (index, texel) in data.chunks_exact(4).enumerate() {
+            let baked = LinearRgba::from(Srgba::rgba_u8(texel[0], texel[1], texel[2], texel[3]));
+            let expected = gradient.sample(texel_center(index as u32, 16));
+            // 8 bit sRGB is coarsest in the highlights
+            assert!(
+                baked.to_vec4().abs_diff_eq(expected.to_vec4(), 0.01),
+                "texel {index}: {baked:?} != {expected:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn baked_curve_matches_sampling() {
+        let mut curve = FloatCurve::default();
+        curve.curve.keys.push(CurveKey {
+            time: 3.0,
+            value: -2.0,
+            easing: Easing::SineInOut,
+        });
+        let image = curve.bake(8);
+        assert_eq!(image.texture_descriptor.format, TextureFormat::R32Float);
+        assert_eq!(image.width(), 8);
+
+        let data = image.data.unwrap();
+        for (index, texel) in data.chunks_exact(4).enumerate() {
+            // spread from the first key at 0 to the last at 3
+            let time = 3.0 * texel_center(index as u32, 8);
+            assert_eq!(
+                f32::from_ne_bytes(texel.try_into().unwrap()),
+                curve.curve.sample(time)
+            );
+        }
+    }
+
+    #[test]
+    fn gradient_round_trip() {
+        let registry = registry();
+        let gradient = gradient();
+        let ron = to_ron(&gradient, &registry).unwrap();
+        assert_eq!(from_ron::<ColorGradient>(&ron, &registry), Ok(gradient));
+    }
+
+    #[test]
+    fn curve_round_trip() {
+        let registry = registry();
+        let mut curve = FloatCurve::default();
+        curve.curve.keys.push(CurveKey {
+            time: 2.5,
+            value: -3.0,
+            easing: Easing::BounceOut,
+        });
+        curve.curve.keys[0].easing = Easing::SineInOut;
+        let ron = to_ron(&curve, &registry).unwrap();
+        assert_eq!(from_ron::<FloatCurve>(&ron, &registry), Ok(curve));
+    }
+
+    #[test]
+    fn loading_sorts_the_keys() {
+        let registry = registry();
+        let mut gradient = gradient();
+        gradient.keys.reverse();
+        let ron = to_ron(&gradient, &registry).unwrap();
+        assert_eq!(
+            from_ron::<ColorGradient>(&ron, &registry),
+            Ok(self::gradient())
+        );
+    }
+
+    #[test]
+    fn invalid_ron_is_an_error() {
+        let registry = registry();
+        assert!(from_ron::<ColorGradient>("(keys: [(position: 0.5)])", &registry).is_err());
+        assert!(from_ron::<FloatCurve>("not ron", &registry).is_err());
+    }
+}
@@ -0,0 +1,353 @@
+use bevy::prelude::*;
+use bevy_egui::{
+    egui::{self, Pos2, Ui},
+    EguiContexts,
+};
+use bevy_panorbit_camera::PanOrbitCamera;
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::curve_assets::{from_ron, to_ron};
+use crate::{
+    curve_assets::Ron,
+    math::{curve::catmull_rom, smooth_step},
+};
+
+/// Time between a newly recorded keyframe and the previous one, in seconds
+const DEFAULT_KEYFRAME_INTERVAL: f32 = 2.0;
+
+/// A camera pose on a [`CameraPath`]
+#[derive(Reflect, Clone, Copy, Debug, PartialEq)]
+pub struct CameraKeyframe {
+    /// Time since the start of the path in seconds
+    pub time: f32,
+    pub position: Vec3,
+    /// The point the camera looks at
+    pub focus: Vec3,
+    /// Vertical field of view in radians
+    pub fov: f32,
+}
+
+/// Keyframes the camera is moved along with a Catmull-Rom spline, sorted by time
+#[derive(Resource, Reflect, Clone, Debug, Default)]
+pub struct CameraPath {
+    pub keyframes: Vec<CameraKeyframe>,
+    /// Ease in at the start and out at the end of the path, instead of a constant speed
+    pub ease_in_out: bool,
+}
+
+impl CameraPath {
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map_or(0.0, |keyframe| keyframe.time)
+    }
+
+    /// Insert a keyframe, keeping the keyframes sorted by time
+    pub fn insert(&mut self, keyframe: CameraKeyframe) {
+        let index = self
+            .keyframes
+            .partition_point(|other| other.time <= keyframe.time);
+        self.keyframes.insert(index, keyframe);
+    }
+
+    /// The camera pose at `time`, interpolated with Catmull-Rom splines through the keyframes
+    pub fn sample(&self, time: f32) -> Option<CameraKeyframe> {
+        let (first, last) = (self.keyframes.first()?, self.keyframes.last()?);
+        if self.keyframes.len() == 1 {
+            return Some(*first);
+        }
+
+        let duration = self.duration() - first.time;
+        let mut time = time.clamp(first.time, last.time);
+        if self.ease_in_out && duration > 0.0 {
+            time = first.time + smooth_step((time - first.time) / duration) * duration;
+        }
+
+        // the splines are parametrized by keyframe index, so map the time to a segment and
+        // the fraction of the segment that has passed
+        let segment = self
+            .keyframes
+            .partition_point(|keyframe| keyframe.time <= time)
+            .clamp(1, self.keyframes.len() - 1)
+            - 1;
+        let (start, end) = (
+            self.keyframes[segment].time,
+            self.keyframes[segment + 1].time,
+        );
+        let fraction = if end > start {
+            (time - start) / (end - start)
+        } else {
+            1.0
+        };
+        let t = segment as f32 + fraction;
+
+        let keyframes = self.keyframes.iter();
+        Some(CameraKeyframe {
+            time,
+            position: catmull_rom(keyframes.clone().map(|keyframe| keyframe.position), t)?,
+            focus: catmull_rom(keyframes.clone().map(|keyframe| keyframe.focus), t)?,
+            fov: catmull_rom(keyframes.map(|keyframe| keyframe.fov), t)?,
+        })
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save(&self, path: &str, registry: &AppTypeRegistry) -> Result<(), String> {
+        let ron = to_ron(self, &registry.read())?;
+        std::fs::write(path, ron).map_err(|error| error.to_string())
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load(path: &str, registry: &AppTypeRegistry) -> Result<Self, String> {
+        let ron = std::fs::read_to_string(path).map_err(|error| error.to_string())?;
+        from_ron(&ron, &registry.read()).map_err(|error| format!("{path}: {error}"))
+    }
+}
+
+impl Ron for CameraPath {
+    fn normalize(&mut self) {
+        self.keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+    }
+}
+
+/// Playback state of the [`CameraPath`]
+#[derive(Resource)]
+pub struct Flythrough {
+    pub playing: bool,
+    pub looping: bool,
+    /// Playback position in seconds
+    pub time: f32,
+    /// File the camera path is saved to and loaded from
+    pub file: String,
+    /// Set when the timeline is scrubbed, so the camera is moved while paused
+    scrubbed: bool,
+    status: String,
+}
+
+impl Default for Flythrough {
+    fn default() -> Self {
+        Self {
+            playing: false,
+            looping: true,
+            time: 0.0,
+            file: "camera_path.ron".to_string(),
+            scrubbed: false,
+            status: String::new(),
+        }
+    }
+}
+
+impl Flythrough {
+    /// Whether the camera is currently driven by the camera path
+    pub fn is_active(&self) -> bool {
+        self.playing || self.scrubbed
+    }
+}
+
+pub struct CameraFlythroughPlugin;
+
+impl Plugin for CameraFlythroughPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<CameraPath>()
+            .init_resource::<CameraPath>()
+            .init_resource::<Flythrough>()
+            .add_systems(Update, (flythrough_ui_system, play_flythrough).chain());
+    }
+}
+
+/// Place the orbit camera at `keyframe`, skipping its smoothing
+fn apply_keyframe(
+    keyframe: &CameraKeyframe,
+    camera: &mut PanOrbitCamera,
+    projection: &mut Projection,
+) {
+    let offset = keyframe.position - keyframe.focus;
+    let radius = offset.length().max(f32::EPSILON);
+    let yaw = offset.x.atan2(offset.z);
+    let pitch = (offset.y / radius).clamp(-1.0, 1.0).asin();
+
+    camera.focus = keyframe.focus;
+    camera.target_focus = keyframe.focus;
+    camera.radius = Some(radius);
+    camera.target_radius = radius;
+    camera.yaw = Some(yaw);
+    camera.target_yaw = yaw;
+    camera.pitch = Some(pitch);
+    camera.target_pitch = pitch;
+    camera.force_update = true;
+
+    if let Projection::Perspective(perspective) = projection {
+        perspective.fov = keyframe.fov;
+    }
+}
+
+fn play_flythrough(
+    time: Res<Time>,
+    mut flythrough: ResMut<Flythrough>,
+    camera_path: Res<CameraPath>,
+    mut cameras: Query<(&mut PanOrbitCamera, &mut Projection)>,
+) {
+    if !flythrough.is_active() {
+        return;
+    }
+    flythrough.scrubbed = false;
+
+    let duration = camera_path.duration();
+    if flythrough.playing {
+        flythrough.time += time.delta_secs();
+        if flythrough.time > duration {
+            if flythrough.looping && duration > 0.0 {
+                flythrough.time %= duration;
+            } else {
+                flythrough.time = duration;
+                flythrough.playing = false;
+            }
+        }
+    }
+
+    let Some(keyframe) = camera_path.sample(flythrough.time) else {
+        flythrough.playing = false;
+        return;
+    };
+    for (mut camera, mut projection) in &mut cameras {
+        apply_keyframe(&keyframe, &mut camera, &mut projection);
+    }
+}
+
+pub fn camera_path_ui(
+    camera_path: &mut CameraPath,
+    flythrough: &mut Flythrough,
+    current: Option<CameraKeyframe>,
+    ui: &mut Ui,
+) {
+    ui.horizontal(|ui| {
+        let label = if flythrough.playing { "Pause" } else { "Play" };
+        if ui
+            .add_enabled(camera_path.keyframes.len() > 1, egui::Button::new(label))
+            .clicked()
+        {
+            flythrough.playing = !flythrough.playing;
+            if flythrough.playing && flythrough.time >= camera_path.duration() {
+                flythrough.time = 0.0;
+            }
+        }
+        ui.checkbox(&mut flythrough.looping, "Loop");
+        ui.checkbox(&mut camera_path.ease_in_out, "Ease in/out");
+    });
+    ui.end_row();
+
+    let duration = camera_path.duration();
+    let timeline = ui.add(
+        egui::Slider::new(&mut flythrough.time, 0.0..=duration)
+            .suffix(" s")
+            .text("Time"),
+    );
+    if timeline.changed() {
+        flythrough.scrubbed = true;
+    }
+    ui.end_row();
+
+    let mut removed = None;
+    for (index, keyframe) in camera_path.keyframes.iter_mut().enumerate() {
+        ui.label(format!("Keyframe {}", index + 1));
+        ui.add(
+            egui::DragValue::new(&mut keyframe.time)
+                .range(0.0..=f32::MAX)
+                .speed(0.05)
+                .suffix(" s"),
+        );
+        if ui.button("Go to").clicked() {
+            flythrough.time = keyframe.time;
+            flythrough.scrubbed = true;
+        }
+        if ui.button("Remove").clicked() {
+            removed = Some(index);
+        }
+        ui.end_row();
+    }
+    if let Some(index) = removed {
+        camera_path.keyframes.remove(index);
+    }
+    camera_path
+        .keyframes
+        .sort_by(|a, b| a.time.total_cmp(&b.time));
+
+    ui.add_enabled_ui(current.is_some(), |ui| {
+        if ui.button("Record keyframe").clicked() {
+            if let Some(mut keyframe) = current {
+                keyframe.time = if camera_path.keyframes.is_empty() {
+                    0.0
+                } else {
+                    duration + DEFAULT_KEYFRAME_INTERVAL
+                };
+                camera_path.insert(keyframe);
+            }
+        }
+    });
+    if ui.button("Clear").clicked() {
+        camera_path.keyframes.clear();
+        flythrough.playing = false;
+        flythrough.time = 0.0;
+    }
+    ui.end_row();
+}
+
+#[cfg_attr(target_arch = "wasm32", allow(unused_variables))]
+fn flythrough_ui_system(
+    mut camera_path: ResMut<CameraPath>,
+    mut flythrough: ResMut<Flythrough>,
+    cameras: Query<(&Transform, &PanOrbitCamera, &Projection)>,
+    type_registry: Res<AppTypeRegistry>,
+    mut contexts: EguiContexts,
+) {
+    let Ok(ctx) = contexts.ctx_mut() else {
+        return;
+    };
+    let current = cameras
+        .single()
+        .ok()
+        .map(|(transform, camera, projection)| CameraKeyframe {
+            time: 0.0,
+            position: transform.translation,
+            focus: camera.focus,
+            fov: match projection {
+                Projection::Perspective(perspective) => perspective.fov,
+                _ => PerspectiveProjection::default().fov,
+            },
+        });
+
+    egui::Window::new("Camera path")
+        .default_pos(Pos2 { x: 10., y: 700. })
+        .default_open(false)
+        .show(ctx, |ui| {
+            egui::Grid::new("camera_path_grid")
+                .num_columns(4)
+                .spacing([20.0, 4.0])
+                .striped(true)
+                .show(ui, |ui| {
+                    camera_path_ui(camera_path.as_mut(), flythrough.as_mut(), current, ui);
+                });
+            #[cfg(not(target_arch = "wasm32"))]
+            ui.separator();
+            #[cfg(not(target_arch = "wasm32"))]
+            ui.horizontal(|ui| {
+                ui.text_edit_singleline(&mut flythrough.file);
+                if ui.button("Save").clicked() {
+                    flythrough.status = match camera_path.save(&flythrough.file, &type_registry) {
+                        Ok(()) => format!("Saved {}", flythrough.file),
+                        Err(error) => error,
+                    };
+                }
+                if ui.button("Load").clicked() {
+                    flythrough.status = match CameraPath::load(&flythrough.file, &type_registry) {
+                        Ok(loaded) => {
+                            *camera_path = loaded;
+                            flythrough.time = 0.0;
+                            format!("Loaded {}", flythrough.file)
+                        }
+                        Err(error) => error,
+                    };
+                }
+            });
+            if !flythrough.status.is_empty() {
+                ui.label(&flythrough.status);
+            }
+        });
+}
@@ -0,0 +1,193 @@
+//! The lava lamp shader ported from Shadertoy as a material for 2D quads, and as a
+//! [`MaterialExtension`] to cover 3D meshes like the terrain with lava.
+
+use bevy::{
+    asset::{load_internal_asset, weak_handle},
+    pbr::{ExtendedMaterial, MaterialExtension},
+    prelude::*,
+    render::render_resource::{AsBindGroup, ShaderRef, ShaderType},
+    sprite::{Material2d, Material2dPlugin},
+};
+use bevy_egui::{
+    egui::{self, Pos2, Ui},
+    EguiContexts,
+};
+
+use crate::math::noise::NOISE_SHADER_HANDLE;
+
+/// The raymarched lava shared by both materials, imported as `bevy_experiments::lava`
+pub const LAVA_SHADER_HANDLE: Handle<Shader> = weak_handle!("cf605362-e658-45a3-bdf1-1984411b87fe");
+
+const SHADER_ASSET_PATH: &str = "shaders/lava.wgsl";
+const EXTENSION_SHADER_ASSET_PATH: &str = "shaders/lava_extension.wgsl";
+
+#[derive(ShaderType, Clone, Copy, Debug, PartialEq)]
+pub struct LavaSettings {
+    /// The colors are `offset + amplitude * cos(frequency * x + phase)` per channel
+    pub palette_offset: Vec3,
+    /// Multiplies the time, for both the movement of the blobs and the color cycling
+    pub speed: f32,
+    pub palette_amplitude: Vec3,
+    /// Size of the blobs, the plane they move in is 6 units across at scale 1
+    pub scale: f32,
+    pub palette_frequency: Vec3,
+    /// How far fractal noise warps the plane, 0 keeps the blobs round
+    pub distortion: f32,
+    pub palette_phase: Vec3,
+    /// Octaves of the distortion noise
+    pub octaves: u32,
+    /// World units one copy of the lava covers on 3D meshes, it repeats beyond that
+    pub tile_size: f32,
+}
+
+impl Default for LavaSettings {
+    fn default() -> Self {
+        Self {
+            palette_offset: Vec3::splat(0.5),
+            speed: 1.0,
+            palette_amplitude: Vec3::splat(0.5),
+            scale: 1.0,
+            palette_frequency: Vec3::ONE,
+            distortion: 0.0,
+            palette_phase: Vec3::new(0.0, 2.0, 4.0),
+            octaves: 3,
+            tile_size: 100.0,
+        }
+    }
+}
+
+/// Fills a 2D mesh, usually a quad covering the screen
+#[derive(Asset, TypePath, AsBindGroup, Clone, Debug, Default)]
+pub struct LavaMaterial {
+    #[uniform(0)]
+    pub settings: LavaSettings,
+}
+
+impl Material2d for LavaMaterial {
+    fn fragment_shader() -> ShaderRef {
+        SHADER_ASSET_PATH.into()
+    }
+}
+
+/// Replaces the base color and emission of a [`StandardMaterial`] with lava, where the lava is
+/// thin the base color shows through as crust
+#[derive(Asset, TypePath, AsBindGroup, Clone, Debug, Default)]
+pub struct LavaExtension {
+    #[uniform(100)]
+    pub settings: LavaSettings,
+}
+
+impl MaterialExtension for LavaExtension {
+    fn fragment_shader() -> ShaderRef {
+        EXTENSION_SHADER_ASSET_PATH.into()
+    }
+
+    fn deferred_fragment_shader() -> ShaderRef {
+        EXTENSION_SHADER_ASSET_PATH.into()
+    }
+}
+
+pub type LavaMeshMaterial = ExtendedMaterial<StandardMaterial, LavaExtension>;
+
+fn vec3_ui(ui: &mut Ui, label: &str, value: &mut Vec3) {
+    ui.horizontal(|ui| {
+        ui.add(egui::DragValue::new(&mut value.x).speed(0.01));
+        ui.add(egui::DragValue::new(&mut value.y).speed(0.01));
+        ui.add(egui::DragValue::new(&mut value.z).speed(0.01));
+        ui.label(label);
+    });
+    ui.end_row();
+}
+
+pub fn lava_ui(settings: &mut LavaSettings, ui: &mut Ui) {
+    ui.add(egui::Slider::new(&mut settings.speed, 0.0..=5.0).text("Speed"));
+    ui.end_row();
+    ui.add(egui::Slider::new(&mut settings.scale, 0.1..=5.0).text("Scale"));
+    ui.end_row();
+    ui.add(egui::Slider::new(&mut settings.distortion, 0.0..=3.0).text("Distortion"));
+    ui.end_row();
+    ui.add(egui::Slider::new(&mut settings.octaves, 1..=8).text("Octaves"));
+    ui.end_row();
+    ui.add(egui::Slider::new(&mut settings.tile_size, 1.0..=1000.0).text("Tile size (3D)"));
+    ui.end_row();
+    vec3_ui(ui, "Palette offset", &mut settings.palette_offset);
+    vec3_ui(ui, "Palette amplitude", &mut settings.palette_amplitude);
+    vec3_ui(ui, "Palette frequency", &mut settings.palette_frequency);
+    vec3_ui(ui, "Palette phase", &mut settings.palette_phase);
+
+    if ui.button("Reset lava").clicked() {
+        *settings = LavaSettings::default();
+    }
+    ui.end_row();
+}
+
+/// Settings of every lava material, which are only touched when a value changes
+fn lava_materials_ui<M: Asset + Clone>(
+    materials: &mut Assets<M>,
+    name: &str,
+    settings: impl Fn(&mut M) -> &mut LavaSettings,
+    ui: &mut Ui,
+) {
+    let ids: Vec<AssetId<M>> = materials.ids().collect();
+    for (index, id) in ids.into_iter().enumerate() {
+        let mut edited = materials.get(id).unwrap().clone();
+        let before = *settings(&mut edited);
+        ui.collapsing(format!("{name} {index}"), |ui| {
+            egui::Grid::new((name, index))
+                .num_columns(1)
+                .spacing([40.0, 4.0])
+                .striped(true)
+                .show(ui, |ui| lava_ui(settings(&mut edited), ui));
+        });
+        if *settings(&mut edited) != before {
+            materials.insert(id, edited);
+        }
+    }
+}
+
+fn lava_ui_system(
+    mut materials: ResMut<Assets<LavaMaterial>>,
+    mut mesh_materials: ResMut<Assets<LavaMeshMaterial>>,
+    mut contexts: EguiContexts,
+) {
+    let Ok(ctx) = contexts.ctx_mut() else {
+        return;
+    };
+    egui::Window::new("Lava")
+        .default_pos(Pos2 { x: 10., y: 10. })
+        .show(ctx, |ui| {
+            lava_materials_ui(&mut materials, "Lava", |m| &mut m.settings, ui);
+            lava_materials_ui(
+                &mut mesh_materials,
+                "Lava mesh",
+                |m| &mut m.extension.settings,
+                ui,
+            );
+        });
+}
+
+pub struct LavaPlugin;
+
+impl Plugin for LavaPlugin {
+    fn build(&self, app: &mut App) {
+        // imported by the lava for the distortion
+        load_internal_asset!(
+            app,
+            NOISE_SHADER_HANDLE,
+            "../assets/shaders/noise.wgsl",
+            Shader::from_wgsl
+        );
+        load_internal_asset!(
+            app,
+            LAVA_SHADER_HANDLE,
+            "../assets/shaders/lava_functions.wgsl",
+            Shader::from_wgsl
+        );
+
+        app.add_plugins((
+            Material2dPlugin::<LavaMaterial>::default(),
+            MaterialPlugin::<LavaMeshMaterial>::default(),
+        ))
+        .add_systems(Update, lava_ui_system);
+    }
+}
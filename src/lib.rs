@@ -1,3 +1,4 @@
+use atmosphere::AtmosphereSkyPlugin;
 use bevy::app::App;
 #[cfg(debug_assertions)]
 use bevy::diagnostic::{FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin};
@@ -5,11 +6,20 @@ use bevy::prelude::*;
 use bevy::window::WindowResized;
 use bevy_panorbit_camera::PanOrbitCameraPlugin;
 use boids::LowPolyTerrainPlugin;
+use flythrough::CameraFlythroughPlugin;
 use simple_3d_scene::Simple3DScenePlugin;
+use time_of_day::TimeOfDayPlugin;
 
+pub mod atmosphere;
 pub mod boids;
+pub mod capture;
+pub mod curve_assets;
+pub mod flythrough;
+pub mod lava;
 pub mod math;
+pub mod particles;
 pub mod simple_3d_scene;
+pub mod time_of_day;
 
 pub fn on_resize_system(
     mut mesh: Single<(&Mesh2d, &mut Transform)>,
@@ -34,8 +44,25 @@ impl Plugin for GamePlugin {
             Simple3DScenePlugin,
             PanOrbitCameraPlugin,
             LowPolyTerrainPlugin,
+            CameraFlythroughPlugin,
+            AtmosphereSkyPlugin,
+            TimeOfDayPlugin,
         ));
 
+        // capturing writes files from a separate thread, neither is available on the web
+        #[cfg(not(target_arch = "wasm32"))]
+        app.add_plugins(capture::FrameCapturePlugin);
+
+        // scripts can only control the flock when asked for with `--remote`
+        #[cfg(not(target_arch = "wasm32"))]
+        match boids::remote::parse_cli_remote(std::env::args().skip(1)) {
+            Ok(Some(port)) => {
+                app.add_plugins(boids::remote::BoidsRemotePlugin { port });
+            }
+            Ok(None) => {}
+            Err(error) => error!("{error}"),
+        }
+
         #[cfg(debug_assertions)]
         {
             app.add_plugins((
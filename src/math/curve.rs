@@ -0,0 +1,191 @@
+//! Cubic interpolation between points, and an editable [`KeyframeCurve`].
+
+use bevy::{
+    math::{
+        cubic_splines::{CubicCardinalSpline, CubicGenerator},
+        VectorSpace,
+    },
+    prelude::*,
+};
+use bevy_egui::egui::{self, Ui};
+
+use super::easing::Easing;
+
+/// Cubic Bézier curve from `p0` to `p3`, pulled towards the control points `p1` and `p2`
+pub fn cubic_bezier<T: VectorSpace>(p0: T, p1: T, p2: T, p3: T, t: f32) -> T {
+    let u = 1.0 - t;
+    p0 * (u * u * u) + p1 * (3.0 * u * u * t) + p2 * (3.0 * u * t * t) + p3 * (t * t * t)
+}
+
+/// Cubic Hermite spline from `p0` to `p1`, leaving `p0` with tangent `m0` and arriving at `p1`
+/// with tangent `m1`
+pub fn hermite<T: VectorSpace>(p0: T, m0: T, p1: T, m1: T, t: f32) -> T {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    p0 * (2.0 * t3 - 3.0 * t2 + 1.0)
+        + m0 * (t3 - 2.0 * t2 + t)
+        + p1 * (-2.0 * t3 + 3.0 * t2)
+        + m1 * (t3 - t2)
+}
+
+/// Evaluate the uniform Catmull-Rom spline through `points` at `t`, where `t = i` is the i-th
+/// point. `None` if there are fewer than two points.
+pub fn catmull_rom<P: VectorSpace>(points: impl IntoIterator<Item = P>, t: f32) -> Option<P> {
+    CubicCardinalSpline::new_catmull_rom(points)
+        .to_curve()
+        .ok()
+        .map(|curve| curve.position(t))
+}
+
+#[derive(Reflect, Clone, Copy, Debug, PartialEq)]
+pub struct CurveKey {
+    pub time: f32,
+    pub value: f32,
+    /// How the curve moves from this key to the next one
+    pub easing: Easing,
+}
+
+/// Values over time, eased between keys
+#[derive(Reflect, Clone, Debug, Default, PartialEq)]
+pub struct KeyframeCurve {
+    /// Sorted by time
+    pub keys: Vec<CurveKey>,
+}
+
+impl KeyframeCurve {
+    /// Eases from `from` at time 0 to `to` at time 1
+    pub fn new(from: f32, to: f32, easing: Easing) -> Self {
+        Self {
+            keys: vec![
+                CurveKey {
+                    time: 0.0,
+                    value: from,
+                    easing,
+                },
+                CurveKey {
+                    time: 1.0,
+                    value: to,
+                    easing: Easing::Linear,
+                },
+            ],
+        }
+    }
+
+    /// The value at `time`, which is held constant before the first and after the last key
+    pub fn sample(&self, time: f32) -> f32 {
+        let index = self.keys.partition_point(|key| key.time <= time);
+        match (
+            index.checked_sub(1).map(|index| &self.keys[index]),
+            self.keys.get(index),
+        ) {
+            (Some(from), Some(to)) => {
+                let t = ((time - from.time) / (to.time - from.time)).clamp(0.0, 1.0);
+                from.value.lerp(to.value, from.easing.apply(t))
+            }
+            (Some(key), None) | (None, Some(key)) => key.value,
+            (None, None) => 0.0,
+        }
+    }
+
+    /// Restore the order of the keys after their times were edited
+    pub fn sort(&mut self) {
+        self.keys.sort_by(|a, b| a.time.total_cmp(&b.time));
+    }
+}
+
+/// Rows to edit the keys of a curve, in a grid with four columns
+pub fn curve_ui(curve: &mut KeyframeCurve, id: &str, ui: &mut Ui) {
+    let mut removed = None;
+    for (index, key) in curve.keys.iter_mut().enumerate() {
+        ui.add(
+            egui::DragValue::new(&mut key.time)
+                .speed(0.01)
+                .prefix("t: "),
+        );
+        ui.add(egui::DragValue::new(&mut key.value).speed(0.01));
+        egui::ComboBox::from_id_salt((id, index))
+            .selected_text(key.easing.label())
+            .show_ui(ui, |ui| {
+                for easing in Easing::ALL {
+                    ui.selectable_value(&mut key.easing, easing, easing.label());
+                }
+            });
+        if ui.button("Remove").clicked() {
+            removed = Some(index);
+        }
+        ui.end_row();
+    }
+    if let Some(index) = removed {
+        curve.keys.remove(index);
+    }
+    curve.sort();
+
+    if ui.button("Add key").clicked() {
+        let time = curve.keys.last().map_or(0.0, |key| key.time + 1.0);
+        curve.keys.push(CurveKey {
+            time,
+            value: curve.sample(time),
+            easing: Easing::Linear,
+        });
+    }
+    ui.end_row();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sampling_eases_between_keys() {
+        let mut curve = KeyframeCurve::new(1.0, 3.0, Easing::SmoothStart);
+        curve.keys.push(CurveKey {
+            time: 3.0,
+            value: 0.0,
+            easing: Easing::Linear,
+        });
+        assert_eq!(curve.sample(0.0), 1.0);
+        assert_eq!(curve.sample(0.5), 1.5);
+        assert_eq!(curve.sample(1.0), 3.0);
+        assert_eq!(curve.sample(2.0), 1.5);
+        assert_eq!(curve.sample(3.0), 0.0);
+        // held outside of the keys
+        assert_eq!(curve.sample(-1.0), 1.0);
+        assert_eq!(curve.sample(10.0), 0.0);
+    }
+
+    #[test]
+    fn sampling_without_keys() {
+        assert_eq!(KeyframeCurve::default().sample(0.5), 0.0);
+        let mut curve = KeyframeCurve::new(2.0, 0.0, Easing::Linear);
+        curve.keys.pop();
+        assert_eq!(curve.sample(-1.0), 2.0);
+        assert_eq!(curve.sample(1.0), 2.0);
+    }
+
+    #[test]
+    fn sort_by_time() {
+        let mut curve = KeyframeCurve::new(0.0, 1.0, Easing::Linear);
+        curve.keys[0].time = 2.0;
+        curve.sort();
+        assert_eq!(curve.keys[0].value, 1.0);
+        assert_eq!(curve.sample(1.5), 0.5);
+    }
+
+    #[test]
+    fn catmull_rom_passes_through_the_points() {
+        let points = [0.0f32, 2.0, -1.0, 4.0];
+        for (index, point) in points.into_iter().enumerate() {
+            let sampled = catmull_rom(points, index as f32).unwrap();
+            assert!((sampled - point).abs() < 1e-5, "{index}: {sampled}");
+        }
+        assert_eq!(catmull_rom([1.0f32], 0.0), None);
+    }
+
+    #[test]
+    fn cubic_endpoints() {
+        assert_eq!(cubic_bezier(1.0f32, 5.0, -3.0, 2.0, 0.0), 1.0);
+        assert_eq!(cubic_bezier(1.0f32, 5.0, -3.0, 2.0, 1.0), 2.0);
+        assert_eq!(hermite(1.0f32, 5.0, 2.0, -3.0, 0.0), 1.0);
+        assert_eq!(hermite(1.0f32, 5.0, 2.0, -3.0, 1.0), 2.0);
+    }
+}
@@ -0,0 +1,191 @@
+//! The standard easing functions, as on <https://easings.net>, to pick in the editor.
+//!
+//! The curves themselves are Bevy's [`EaseFunction`]s. All of them map `0` to `0` and `1` to `1`
+//! and expect `t` in `[0, 1]`. Most only ever increase, but the back and elastic functions
+//! overshoot in between and the bounce functions bounce back.
+
+use bevy::prelude::*;
+
+/// An [`EaseFunction`] that can be picked in the editor and stored with reflection.
+///
+/// Bevy's enum also has parameterized variants, this one only lists those without parameters.
+#[derive(Reflect, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Easing {
+    #[default]
+    Linear,
+    /// Quadratic ease in
+    SmoothStart,
+    /// Quadratic ease out
+    SmoothStop,
+    SmoothStep,
+    SmootherStep,
+    SineIn,
+    SineOut,
+    SineInOut,
+    ExpoIn,
+    ExpoOut,
+    ExpoInOut,
+    BackIn,
+    BackOut,
+    BackInOut,
+    ElasticIn,
+    ElasticOut,
+    ElasticInOut,
+    BounceIn,
+    BounceOut,
+    BounceInOut,
+}
+
+impl Easing {
+    pub const ALL: [Easing; 20] = [
+        Easing::Linear,
+        Easing::SmoothStart,
+        Easing::SmoothStop,
+        Easing::SmoothStep,
+        Easing::SmootherStep,
+        Easing::SineIn,
+        Easing::SineOut,
+        Easing::SineInOut,
+        Easing::ExpoIn,
+        Easing::ExpoOut,
+        Easing::ExpoInOut,
+        Easing::BackIn,
+        Easing::BackOut,
+        Easing::BackInOut,
+        Easing::ElasticIn,
+        Easing::ElasticOut,
+        Easing::ElasticInOut,
+        Easing::BounceIn,
+        Easing::BounceOut,
+        Easing::BounceInOut,
+    ];
+
+    pub fn apply(self, t: f32) -> f32 {
+        // Bevy's elastic functions are off by about 5e-4 at the ends
+        if t <= 0.0 {
+            0.0
+        } else if t >= 1.0 {
+            1.0
+        } else {
+            EaseFunction::from(self).sample_unchecked(t)
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Easing::Linear => "Linear",
+            Easing::SmoothStart => "Smooth start",
+            Easing::SmoothStop => "Smooth stop",
+            Easing::SmoothStep => "Smooth step",
+            Easing::SmootherStep => "Smoother step",
+            Easing::SineIn => "Sine in",
+            Easing::SineOut => "Sine out",
+            Easing::SineInOut => "Sine in out",
+            Easing::ExpoIn => "Expo in",
+            Easing::ExpoOut => "Expo out",
+            Easing::ExpoInOut => "Expo in out",
+            Easing::BackIn => "Back in",
+            Easing::BackOut => "Back out",
+            Easing::BackInOut => "Back in out",
+            Easing::ElasticIn => "Elastic in",
+            Easing::ElasticOut => "Elastic out",
+            Easing::ElasticInOut => "Elastic in out",
+            Easing::BounceIn => "Bounce in",
+            Easing::BounceOut => "Bounce out",
+            Easing::BounceInOut => "Bounce in out",
+        }
+    }
+}
+
+impl From<Easing> for EaseFunction {
+    fn from(easing: Easing) -> Self {
+        match easing {
+            Easing::Linear => EaseFunction::Linear,
+            Easing::SmoothStart => EaseFunction::QuadraticIn,
+            Easing::SmoothStop => EaseFunction::QuadraticOut,
+            Easing::SmoothStep => EaseFunction::SmoothStep,
+            Easing::SmootherStep => EaseFunction::SmootherStep,
+            Easing::SineIn => EaseFunction::SineIn,
+            Easing::SineOut => EaseFunction::SineOut,
+            Easing::SineInOut => EaseFunction::SineInOut,
+            Easing::ExpoIn => EaseFunction::ExponentialIn,
+            Easing::ExpoOut => EaseFunction::ExponentialOut,
+            Easing::ExpoInOut => EaseFunction::ExponentialInOut,
+            Easing::BackIn => EaseFunction::BackIn,
+            Easing::BackOut => EaseFunction::BackOut,
+            Easing::BackInOut => EaseFunction::BackInOut,
+            Easing::ElasticIn => EaseFunction::ElasticIn,
+            Easing::ElasticOut => EaseFunction::ElasticOut,
+            Easing::ElasticInOut => EaseFunction::ElasticInOut,
+            Easing::BounceIn => EaseFunction::BounceIn,
+            Easing::BounceOut => EaseFunction::BounceOut,
+            Easing::BounceInOut => EaseFunction::BounceInOut,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Samples per function for the monotonicity check
+    const STEPS: usize = 10_000;
+    /// f32 rounding where the functions flatten out near the ends
+    const EPSILON: f32 = 1e-5;
+
+    /// The functions that overshoot or bounce back on purpose
+    fn overshoots(easing: Easing) -> bool {
+        matches!(
+            easing,
+            Easing::BackIn
+                | Easing::BackOut
+                | Easing::BackInOut
+                | Easing::ElasticIn
+                | Easing::ElasticOut
+                | Easing::ElasticInOut
+                | Easing::BounceIn
+                | Easing::BounceOut
+                | Easing::BounceInOut
+        )
+    }
+
+    #[test]
+    fn endpoints() {
+        for easing in Easing::ALL {
+            assert_eq!(easing.apply(0.0), 0.0, "{easing:?}");
+            assert_eq!(easing.apply(1.0), 1.0, "{easing:?}");
+            // the ends are reached continuously, not only by clamping
+            assert!(easing.apply(1e-4).abs() < 1e-2, "{easing:?}");
+            assert!((easing.apply(1.0 - 1e-4) - 1.0).abs() < 1e-2, "{easing:?}");
+        }
+    }
+
+    #[test]
+    fn monotonic() {
+        for easing in Easing::ALL
+            .into_iter()
+            .filter(|&easing| !overshoots(easing))
+        {
+            let mut previous = easing.apply(0.0);
+            for step in 1..=STEPS {
+                let t = step as f32 / STEPS as f32;
+                let value = easing.apply(t);
+                assert!(value >= previous - EPSILON, "{easing:?} decreases at {t}");
+                previous = value;
+            }
+        }
+    }
+
+    #[test]
+    fn stays_in_range() {
+        for easing in Easing::ALL
+            .into_iter()
+            .filter(|&easing| !overshoots(easing))
+        {
+            for step in 0..=STEPS {
+                let value = easing.apply(step as f32 / STEPS as f32);
+                assert!((-EPSILON..=1.0 + EPSILON).contains(&value), "{easing:?}");
+            }
+        }
+    }
+}
@@ -0,0 +1,23 @@
+pub mod curve;
+pub mod easing;
+pub mod noise;
+
+/// Ease in that starts flat, `t` raised to `power`
+pub fn smooth_start(t: f32, power: i32) -> f32 {
+    t.powi(power)
+}
+
+/// Ease out that ends flat, the mirror image of [`smooth_start`]
+pub fn smooth_stop(t: f32, power: i32) -> f32 {
+    1.0 - (1.0 - t).powi(power)
+}
+
+/// Cubic ease in and out, with zero slope at `t = 0` and `t = 1`
+pub fn smooth_step(t: f32) -> f32 {
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// Quintic ease in and out, with zero slope and curvature at `t = 0` and `t = 1`
+pub fn smoother_step(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
@@ -0,0 +1,202 @@
+//! Seeded 3D simplex and curl noise.
+//!
+//! Gradients are picked with an integer hash instead of a permutation table, so
+//! `shaders/noise.wgsl` produces the same values on the GPU for the same seed.
+
+use bevy::{asset::weak_handle, prelude::*};
+
+/// The WGSL version of this module, imported as `bevy_experiments::noise`
+pub const NOISE_SHADER_HANDLE: Handle<Shader> =
+    weak_handle!("0b6fdf59-9e11-48f3-86dc-54790608cd60");
+
+/// Step of the finite differences used to take the curl of the noise
+const CURL_EPSILON: f32 = 1e-2;
+
+/// The edge midpoints of a cube, as in Ken Perlin's improved noise
+const GRADIENTS: [Vec3; 12] = [
+    Vec3::new(1.0, 1.0, 0.0),
+    Vec3::new(-1.0, 1.0, 0.0),
+    Vec3::new(1.0, -1.0, 0.0),
+    Vec3::new(-1.0, -1.0, 0.0),
+    Vec3::new(1.0, 0.0, 1.0),
+    Vec3::new(-1.0, 0.0, 1.0),
+    Vec3::new(1.0, 0.0, -1.0),
+    Vec3::new(-1.0, 0.0, -1.0),
+    Vec3::new(0.0, 1.0, 1.0),
+    Vec3::new(0.0, -1.0, 1.0),
+    Vec3::new(0.0, 1.0, -1.0),
+    Vec3::new(0.0, -1.0, -1.0),
+];
+
+/// PCG based integer hash, from "Hash Functions for GPU Rendering" by Jarzynski and Olano
+pub fn pcg_hash(input: u32) -> u32 {
+    let state = input.wrapping_mul(747796405).wrapping_add(2891336453);
+    let word = ((state >> ((state >> 28) + 4)) ^ state).wrapping_mul(277803737);
+    (word >> 22) ^ word
+}
+
+fn gradient(cell: IVec3, seed: u32) -> Vec3 {
+    let hash = pcg_hash(
+        cell.x as u32 ^ pcg_hash(cell.y as u32 ^ pcg_hash(cell.z as u32 ^ pcg_hash(seed))),
+    );
+    GRADIENTS[(hash % 12) as usize]
+}
+
+fn corner_contribution(offset: Vec3, cell: IVec3, seed: u32) -> f32 {
+    let falloff = 0.6 - offset.length_squared();
+    if falloff <= 0.0 {
+        return 0.0;
+    }
+    falloff.powi(4) * gradient(cell, seed).dot(offset)
+}
+
+/// 3D simplex noise in roughly `[-1, 1]`, different seeds give unrelated noise
+pub fn simplex(position: Vec3, seed: u32) -> f32 {
+    const SKEW: f32 = 1.0 / 3.0;
+    const UNSKEW: f32 = 1.0 / 6.0;
+
+    // find the simplex the position is in, and the offsets to its corners
+    let cell = (position + position.element_sum() * SKEW).floor();
+    let offset0 = position - (cell - cell.element_sum() * UNSKEW);
+    let (step1, step2) = if offset0.x >= offset0.y {
+        if offset0.y >= offset0.z {
+            (IVec3::X, IVec3::new(1, 1, 0))
+        } else if offset0.x >= offset0.z {
+            (IVec3::X, IVec3::new(1, 0, 1))
+        } else {
+            (IVec3::Z, IVec3::new(1, 0, 1))
+        }
+    } else if offset0.y < offset0.z {
+        (IVec3::Z, IVec3::new(0, 1, 1))
+    } else if offset0.x < offset0.z {
+        (IVec3::Y, IVec3::new(0, 1, 1))
+    } else {
+        (IVec3::Y, IVec3::new(1, 1, 0))
+    };
+    let offset1 = offset0 - step1.as_vec3() + UNSKEW;
+    let offset2 = offset0 - step2.as_vec3() + 2.0 * UNSKEW;
+    let offset3 = offset0 - 1.0 + 3.0 * UNSKEW;
+
+    let cell = cell.as_ivec3();
+    32.0 * (corner_contribution(offset0, cell, seed)
+        + corner_contribution(offset1, cell + step1, seed)
+        + corner_contribution(offset2, cell + step2, seed)
+        + corner_contribution(offset3, cell + IVec3::ONE, seed))
+}
+
+/// Divergence free noise, the curl of three simplex noise fields seeded from `seed`.
+///
+/// Moving particles along it makes them swirl without bunching up or spreading out, as in
+/// "Curl-Noise for Procedural Fluid Flow" by Bridson et al.
+pub fn curl(position: Vec3, seed: u32) -> Vec3 {
+    let potential =
+        |axis: usize, offset: Vec3| simplex(position + offset, seed.wrapping_add(axis as u32));
+    let derivative = |axis: usize, direction: Vec3| {
+        (potential(axis, direction * CURL_EPSILON) - potential(axis, -direction * CURL_EPSILON))
+            / (2.0 * CURL_EPSILON)
+    };
+
+    Vec3::new(
+        derivative(2, Vec3::Y) - derivative(1, Vec3::Z),
+        derivative(0, Vec3::Z) - derivative(2, Vec3::X),
+        derivative(1, Vec3::X) - derivative(0, Vec3::Y),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Positions spread over a few cells in every direction
+    fn grid() -> impl Iterator<Item = Vec3> {
+        (0..20).flat_map(|i| {
+            (0..20).flat_map(move |j| {
+                (0..20).map(move |k| {
+                    Vec3::new(i as f32, j as f32, k as f32) * Vec3::new(0.37, 0.29, 0.41)
+                        - Vec3::new(3.0, 2.0, 4.0)
+                })
+            })
+        })
+    }
+
+    #[test]
+    fn same_seed_same_noise() {
+        for position in grid().step_by(37) {
+            assert_eq!(simplex(position, 5), simplex(position, 5));
+            assert_eq!(curl(position, 5), curl(position, 5));
+        }
+    }
+
+    #[test]
+    fn different_seeds_differ() {
+        let differing = grid()
+            .filter(|&position| (simplex(position, 1) - simplex(position, 2)).abs() > 1e-3)
+            .count();
+        assert!(differing > grid().count() / 2);
+    }
+
+    #[test]
+    fn simplex_stays_in_range() {
+        let (mut min, mut max) = (f32::MAX, f32::MIN);
+        for position in grid() {
+            for seed in [0, 1, 99] {
+                let value = simplex(position, seed);
+                min = min.min(value);
+                max = max.max(value);
+            }
+        }
+        assert!(min >= -1.05 && max <= 1.05, "{min} to {max}");
+        // and uses most of it
+        assert!(min < -0.8 && max > 0.8, "{min} to {max}");
+    }
+
+    #[test]
+    fn curl_is_divergence_free() {
+        // with the same step as the curl the central differences cancel out exactly, up to
+        // rounding
+        let h = CURL_EPSILON;
+        for position in grid().step_by(7) {
+            let derivative = |axis: Vec3| {
+                (curl(position + axis * h, 3) - curl(position - axis * h, 3)) / (2.0 * h)
+            };
+            let divergence = derivative(Vec3::X).x + derivative(Vec3::Y).y + derivative(Vec3::Z).z;
+            assert!(divergence.abs() < 1e-2, "{divergence} at {position}");
+        }
+    }
+
+    /// Reference values for `shaders/noise.wgsl`, which should give the same results
+    #[test]
+    fn pinned_values() {
+        for (position, seed, expected_simplex, expected_curl) in [
+            (
+                Vec3::new(0.3, -1.7, 2.5),
+                0,
+                -0.462_863,
+                Vec3::new(-3.415_863, -4.059_593, 0.282_514),
+            ),
+            (
+                Vec3::new(12.25, 3.5, -7.75),
+                7,
+                0.520_435,
+                Vec3::new(5.280_563, -2.831_032, 4.295_951),
+            ),
+            (
+                Vec3::new(-0.6, 0.45, 0.1),
+                42,
+                -0.323_530,
+                Vec3::new(0.671_689, -0.914_057, 1.133_768),
+            ),
+        ] {
+            let value = simplex(position, seed);
+            assert!(
+                (value - expected_simplex).abs() < 1e-5,
+                "{value} at {position}"
+            );
+            let curl = curl(position, seed);
+            assert!(
+                curl.abs_diff_eq(expected_curl, 1e-3),
+                "{curl} at {position}"
+            );
+        }
+    }
+}
@@ -0,0 +1,584 @@
+use std::borrow::Cow;
+
+use bevy::{
+    asset::load_internal_asset,
+    ecs::query::QueryItem,
+    prelude::*,
+    render::{
+        extract_component::{ExtractComponent, ExtractComponentPlugin},
+        render_asset::{RenderAssetUsages, RenderAssets},
+        render_graph::{Node, NodeRunError, RenderGraph, RenderGraphContext, RenderLabel},
+        render_resource::{
+            binding_types::{storage_buffer_sized, texture_storage_2d, uniform_buffer},
+            BindGroup, BindGroupEntries, BindGroupLayout, BindGroupLayoutEntries, Buffer,
+            BufferDescriptor, BufferUsages, CachedComputePipelineId, ComputePassDescriptor,
+            ComputePipelineDescriptor, DynamicUniformBuffer, Extent3d, PipelineCache, ShaderDefVal,
+            ShaderStages, ShaderType, StorageTextureAccess, TextureDimension, TextureFormat,
+            TextureUsages, UniformBuffer,
+        },
+        renderer::{RenderContext, RenderDevice, RenderQueue},
+        texture::GpuImage,
+        view::NoFrustumCulling,
+        Render, RenderApp, RenderSet,
+    },
+};
+
+use super::{
+    render::{GpuParticlesRenderPlugin, ParticleRenderUniform},
+    ParticleEmitter, ParticleGradientPlugin, ParticleMesh,
+};
+use crate::{
+    curve_assets::{BakedTexture, ColorGradient},
+    math::noise::NOISE_SHADER_HANDLE,
+};
+
+const SHADER_PATH: &str = "shaders/particles_compute.wgsl";
+
+/// Number of invocations per compute workgroup, passed to the shader as the `WORKGROUP_SIZE` shader def
+const WORKGROUP_SIZE: u32 = 64;
+
+/// Width of the particle maps, particle `i` is stored at texel `(i % width, i / width)`
+pub const PARTICLE_MAP_WIDTH: u32 = 512;
+
+/// Size of a camera distance and particle index pair in the sort buffer
+const SORT_ENTRY_SIZE: u64 = 8;
+
+/// Number of texels the color gradient of an emitter is baked into
+const GRADIENT_TEXTURE_WIDTH: u32 = 64;
+
+/// Simulate the particles of a [`ParticleEmitter`] in a compute shader instead of as entities.
+///
+/// The particles live in a fixed-size pool that is reused as a ring buffer, so when
+/// `rate * lifetime` exceeds the capacity the oldest particles disappear early.
+///
+/// Every frame the pool is sorted back to front from the camera on the GPU, so overlapping
+/// particles blend in the right order from every angle.
+#[derive(Component, Clone, Copy)]
+#[require(ParticleEmitter)]
+pub struct GpuParticles {
+    pub capacity: u32,
+    /// Distance over which particles fade out in front of the scene, to hide where the quads
+    /// cut through geometry. Needs a [`DepthPrepass`](bevy::core_pipeline::prepass::DepthPrepass)
+    /// on the camera, `0.0` disables the fade.
+    pub soft_distance: f32,
+}
+
+impl Default for GpuParticles {
+    fn default() -> Self {
+        Self {
+            capacity: 100_000,
+            soft_distance: 0.0,
+        }
+    }
+}
+
+/// Position and remaining lifetime, and velocity and rotation of every particle in the pool
+#[derive(Component, Clone)]
+pub struct GpuParticleMaps {
+    pub position_map: Handle<Image>,
+    pub velocity_map: Handle<Image>,
+    /// Capacity rounded up to whole rows of the maps
+    pub capacity: u32,
+}
+
+/// The part of the pool that is emitted into this frame
+#[derive(Component, Default)]
+pub struct GpuParticleEmission {
+    start: u32,
+    count: u32,
+    /// Fraction of a particle left to emit in the next frame
+    accumulator: f32,
+    delta_time: f32,
+    frame: u32,
+    /// Position of the camera the particles are sorted for
+    sort_origin: Vec3,
+}
+
+#[derive(Clone, Default, ShaderType)]
+pub struct GpuParticleUniform {
+    /// Rotates the y axis onto the center of the velocity cone in world space
+    pub cone: Mat3,
+    pub origin: Vec3,
+    pub cos_spread: f32,
+    pub acceleration: Vec3,
+    pub drag: f32,
+    pub speed: f32,
+    pub speed_variation: f32,
+    pub lifetime: f32,
+    pub delta_time: f32,
+    pub emit_start: u32,
+    pub emit_count: u32,
+    pub capacity: u32,
+    pub seed: u32,
+    pub sort_origin: Vec3,
+    /// Capacity rounded up to a power of two, the bitonic sort only handles those
+    pub sort_count: u32,
+    pub wind: Vec3,
+    pub buoyancy: f32,
+    pub turbulence: f32,
+    pub turbulence_scale: f32,
+    pub noise_seed: u32,
+}
+
+/// A single compare and swap pass of the bitonic sort
+#[derive(Clone, Default, ShaderType)]
+struct SortStep {
+    /// Size of the sequences that are being merged
+    block: u32,
+    /// Distance between the compared elements
+    stride: u32,
+}
+
+/// The passes of a bitonic sort of `count` elements, which has to be a power of two
+fn bitonic_sort_steps(count: u32) -> Vec<SortStep> {
+    let mut steps = Vec::new();
+    let mut block = 2;
+    while block <= count {
+        let mut stride = block / 2;
+        while stride > 0 {
+            steps.push(SortStep { block, stride });
+            stride /= 2;
+        }
+        block *= 2;
+    }
+    steps
+}
+/// The emitter state of a [`GpuParticles`] entity in the render world
+#[derive(Component, Clone)]
+pub struct ExtractedGpuParticles {
+    pub maps: GpuParticleMaps,
+    pub texture: Option<Handle<Image>>,
+    /// Baked from the [`ColorGradient`] of the emitter, the particles are colored from it instead
+    /// of the colors in `render` once it is on the GPU
+    pub color_gradient: Option<Handle<Image>>,
+    pub simulation: GpuParticleUniform,
+    pub render: ParticleRenderUniform,
+}
+
+impl ExtractComponent for GpuParticles {
+    type QueryData = (
+        &'static ParticleEmitter,
+        &'static GpuParticles,
+        &'static GpuParticleMaps,
+        &'static GpuParticleEmission,
+        &'static GlobalTransform,
+        Option<&'static BakedTexture<ColorGradient>>,
+    );
+    type QueryFilter = With<GpuParticles>;
+    type Out = ExtractedGpuParticles;
+
+    fn extract_component(
+        (emitter, particles, maps, emission, transform, color_gradient): QueryItem<
+            '_,
+            Self::QueryData,
+        >,
+    ) -> Option<Self::Out> {
+        let (_, rotation, origin) = transform.to_scale_rotation_translation();
+        let cone =
+            rotation * Quat::from_rotation_arc(Vec3::Y, emitter.direction.normalize_or(Vec3::Y));
+
+        Some(ExtractedGpuParticles {
+            maps: maps.clone(),
+            texture: emitter.texture.clone(),
+            color_gradient: color_gradient.map(|baked| baked.texture.clone()),
+            simulation: GpuParticleUniform {
+                cone: Mat3::from_quat(cone),
+                origin,
+                cos_spread: emitter.spread.cos(),
+                acceleration: emitter.acceleration,
+                drag: emitter.drag,
+                speed: emitter.speed,
+                speed_variation: emitter.speed_variation,
+                lifetime: emitter.lifetime,
+                delta_time: emission.delta_time,
+                emit_start: emission.start,
+                emit_count: emission.count,
+                capacity: maps.capacity,
+                seed: emission.frame,
+                sort_origin: emission.sort_origin,
+                sort_count: maps.capacity.next_power_of_two(),
+                wind: emitter.wind,
+                buoyancy: emitter.buoyancy,
+                turbulence: emitter.turbulence,
+                turbulence_scale: emitter.turbulence_scale.max(f32::EPSILON),
+                noise_seed: emitter.noise_seed,
+            },
+            render: ParticleRenderUniform::new(emitter, particles.soft_distance),
+        })
+    }
+}
+
+/// Map with a texel per particle, filled with dead particles
+fn build_particle_map(capacity: u32) -> Image {
+    let mut image = Image::new_fill(
+        Extent3d {
+            width: PARTICLE_MAP_WIDTH,
+            height: capacity / PARTICLE_MAP_WIDTH,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &[0; 4 * 4],
+        TextureFormat::Rgba32Float,
+        RenderAssetUsages::RENDER_WORLD,
+    );
+    image.texture_descriptor.usage =
+        TextureUsages::COPY_DST | TextureUsages::STORAGE_BINDING | TextureUsages::TEXTURE_BINDING;
+    image
+}
+
+fn setup_gpu_particles(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    mesh: Res<ParticleMesh>,
+    emitters: Query<(Entity, &GpuParticles, &ParticleEmitter), Without<GpuParticleMaps>>,
+) {
+    for (entity, particles, emitter) in &emitters {
+        let capacity = particles
+            .capacity
+            .max(1)
+            .next_multiple_of(PARTICLE_MAP_WIDTH);
+
+        // The particles only exist on the GPU, the quad is drawn once per particle in the pool by
+        // `render::DrawGpuParticleInstances`.
+        commands.entity(entity).insert((
+            GpuParticleMaps {
+                position_map: images.add(build_particle_map(capacity)),
+                velocity_map: images.add(build_particle_map(capacity)),
+                capacity,
+            },
+            GpuParticleEmission::default(),
+            Mesh3d(mesh.0.clone()),
+            NoFrustumCulling,
+        ));
+        if let Some(gradient) = &emitter.color_gradient {
+            commands.entity(entity).insert(BakedTexture::new(
+                gradient.clone(),
+                GRADIENT_TEXTURE_WIDTH,
+                &images,
+            ));
+        }
+    }
+}
+
+/// Particles are sorted for the active 3D camera with the highest order, the camera that ends up
+/// on top. Other cameras at a different position see them in the wrong order.
+fn emit_gpu_particles(
+    time: Res<Time>,
+    cameras: Query<(&Camera, &GlobalTransform), With<Camera3d>>,
+    mut emitters: Query<(
+        &ParticleEmitter,
+        &GpuParticleMaps,
+        &mut GpuParticleEmission,
+        &InheritedVisibility,
+    )>,
+) {
+    let sort_origin = cameras
+        .iter()
+        .filter(|(camera, _)| camera.is_active)
+        .max_by_key(|(camera, _)| camera.order)
+        .map(|(_, transform)| transform.translation());
+
+    for (emitter, maps, mut emission, visibility) in &mut emitters {
+        emission.start = (emission.start + emission.count) % maps.capacity;
+        emission.delta_time = time.delta_secs();
+        emission.frame = emission.frame.wrapping_add(1);
+        if let Some(sort_origin) = sort_origin {
+            emission.sort_origin = sort_origin;
+        }
+
+        if !visibility.get() {
+            emission.count = 0;
+            continue;
+        }
+        emission.accumulator += emitter.rate * time.delta_secs();
+        let count = emission.accumulator as u32;
+        emission.accumulator -= count as f32;
+        emission.count = count.min(maps.capacity);
+    }
+}
+
+/// Buffers and bind groups of an emitter in the render world, kept between frames
+#[derive(Component)]
+pub(super) struct GpuParticleBindGroups {
+    simulation_buffer: UniformBuffer<GpuParticleUniform>,
+    pub(super) render_buffer: UniformBuffer<ParticleRenderUniform>,
+    /// Camera distance and index of every particle, sorted back to front
+    pub(super) sort_buffer: Buffer,
+    /// Offsets of the passes of the sort in its dynamic uniform buffer
+    sort_offsets: Vec<u32>,
+    simulation: BindGroup,
+    maps: BindGroup,
+    sort: BindGroup,
+    workgroups: u32,
+    sort_workgroups: u32,
+}
+
+pub(super) fn prepare_gpu_particles_bind_groups(
+    mut commands: Commands,
+    pipeline: Res<GpuParticlesPipeline>,
+    gpu_images: Res<RenderAssets<GpuImage>>,
+    mut emitters: Query<(
+        Entity,
+        &ExtractedGpuParticles,
+        Option<&mut GpuParticleBindGroups>,
+    )>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+) {
+    for (entity, particles, bind_groups) in &mut emitters {
+        let (Some(position_map), Some(velocity_map)) = (
+            gpu_images.get(&particles.maps.position_map),
+            gpu_images.get(&particles.maps.velocity_map),
+        ) else {
+            continue;
+        };
+
+        // the uniform colors stand in while the gradient is still loading
+        let render = ParticleRenderUniform {
+            baked_gradient: particles
+                .color_gradient
+                .as_ref()
+                .is_some_and(|gradient| gpu_images.get(gradient).is_some())
+                .into(),
+            ..particles.render.clone()
+        };
+
+        // the buffers are kept between frames, writing them only uploads the new values
+        if let Some(mut bind_groups) = bind_groups {
+            bind_groups
+                .simulation_buffer
+                .set(particles.simulation.clone());
+            bind_groups
+                .simulation_buffer
+                .write_buffer(&render_device, &render_queue);
+            bind_groups.render_buffer.set(render);
+            bind_groups
+                .render_buffer
+                .write_buffer(&render_device, &render_queue);
+            continue;
+        }
+
+        let mut simulation_buffer = UniformBuffer::from(particles.simulation.clone());
+        let mut render_buffer = UniformBuffer::from(render);
+        simulation_buffer.write_buffer(&render_device, &render_queue);
+        render_buffer.write_buffer(&render_device, &render_queue);
+        let simulation = render_device.create_bind_group(
+            None,
+            &pipeline.uniform_bind_group_layout,
+            &BindGroupEntries::single(simulation_buffer.binding().unwrap()),
+        );
+        let maps = render_device.create_bind_group(
+            None,
+            &pipeline.maps_bind_group_layout,
+            &BindGroupEntries::sequential((&position_map.texture_view, &velocity_map.texture_view)),
+        );
+
+        // the sort steps only depend on the capacity, so they are only uploaded once
+        let sort_count = particles.simulation.sort_count;
+        let sort_buffer = render_device.create_buffer(&BufferDescriptor {
+            label: Some("gpu_particles_sort_buffer"),
+            size: u64::from(sort_count) * SORT_ENTRY_SIZE,
+            usage: BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        let mut sort_steps = DynamicUniformBuffer::default();
+        let sort_offsets = bitonic_sort_steps(sort_count)
+            .into_iter()
+            .map(|step| sort_steps.push(&step))
+            .collect();
+        sort_steps.write_buffer(&render_device, &render_queue);
+        let sort = render_device.create_bind_group(
+            None,
+            &pipeline.sort_bind_group_layout,
+            &BindGroupEntries::sequential((
+                sort_buffer.as_entire_binding(),
+                sort_steps.binding().unwrap(),
+            )),
+        );
+
+        commands.entity(entity).insert(GpuParticleBindGroups {
+            simulation_buffer,
+            render_buffer,
+            sort_buffer,
+            sort_offsets,
+            simulation,
+            maps,
+            sort,
+            workgroups: particles.maps.capacity.div_ceil(WORKGROUP_SIZE),
+            sort_workgroups: sort_count.div_ceil(WORKGROUP_SIZE),
+        });
+    }
+}
+
+#[derive(Resource)]
+pub struct GpuParticlesPipeline {
+    pub uniform_bind_group_layout: BindGroupLayout,
+    pub maps_bind_group_layout: BindGroupLayout,
+    pub sort_bind_group_layout: BindGroupLayout,
+    update_pipeline: CachedComputePipelineId,
+    sort_keys_pipeline: CachedComputePipelineId,
+    sort_step_pipeline: CachedComputePipelineId,
+}
+
+impl FromWorld for GpuParticlesPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+        let shader = world.resource::<AssetServer>().load(SHADER_PATH);
+        let pipeline_cache = world.resource::<PipelineCache>();
+
+        let uniform_bind_group_layout = render_device.create_bind_group_layout(
+            "gpu_particles_uniform_bind_group_layout",
+            &BindGroupLayoutEntries::single(
+                ShaderStages::COMPUTE,
+                uniform_buffer::<GpuParticleUniform>(false),
+            ),
+        );
+        let maps_bind_group_layout = render_device.create_bind_group_layout(
+            "gpu_particles_maps_bind_group_layout",
+            &BindGroupLayoutEntries::sequential(
+                ShaderStages::COMPUTE,
+                (
+                    texture_storage_2d(TextureFormat::Rgba32Float, StorageTextureAccess::ReadWrite),
+                    texture_storage_2d(TextureFormat::Rgba32Float, StorageTextureAccess::ReadWrite),
+                ),
+            ),
+        );
+
+        let sort_bind_group_layout = render_device.create_bind_group_layout(
+            "gpu_particles_sort_bind_group_layout",
+            &BindGroupLayoutEntries::sequential(
+                ShaderStages::COMPUTE,
+                (
+                    storage_buffer_sized(false, None),
+                    uniform_buffer::<SortStep>(true),
+                ),
+            ),
+        );
+
+        let queue_pipeline = |entry_point: &'static str| {
+            pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+                zero_initialize_workgroup_memory: false,
+                label: None,
+                layout: vec![
+                    uniform_bind_group_layout.clone(),
+                    maps_bind_group_layout.clone(),
+                    sort_bind_group_layout.clone(),
+                ],
+                push_constant_ranges: Vec::new(),
+                shader: shader.clone(),
+                shader_defs: vec![ShaderDefVal::UInt("WORKGROUP_SIZE".into(), WORKGROUP_SIZE)],
+                entry_point: Cow::from(entry_point),
+            })
+        };
+        let update_pipeline = queue_pipeline("update");
+        let sort_keys_pipeline = queue_pipeline("sort_keys");
+        let sort_step_pipeline = queue_pipeline("sort_step");
+
+        GpuParticlesPipeline {
+            uniform_bind_group_layout,
+            maps_bind_group_layout,
+            sort_bind_group_layout,
+            update_pipeline,
+            sort_keys_pipeline,
+            sort_step_pipeline,
+        }
+    }
+}
+
+/// Emits, ages and integrates the particles of every [`GpuParticles`] emitter, then sorts them
+/// by their distance to the camera
+struct GpuParticlesNode {
+    emitters: QueryState<&'static GpuParticleBindGroups>,
+}
+
+impl FromWorld for GpuParticlesNode {
+    fn from_world(world: &mut World) -> Self {
+        Self {
+            emitters: QueryState::new(world),
+        }
+    }
+}
+
+impl Node for GpuParticlesNode {
+    fn update(&mut self, world: &mut World) {
+        self.emitters.update_archetypes(world);
+    }
+
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        let pipeline_cache = world.resource::<PipelineCache>();
+        let pipeline = world.resource::<GpuParticlesPipeline>();
+        let (Some(update_pipeline), Some(sort_keys_pipeline), Some(sort_step_pipeline)) = (
+            pipeline_cache.get_compute_pipeline(pipeline.update_pipeline),
+            pipeline_cache.get_compute_pipeline(pipeline.sort_keys_pipeline),
+            pipeline_cache.get_compute_pipeline(pipeline.sort_step_pipeline),
+        ) else {
+            return Ok(());
+        };
+
+        let mut pass = render_context
+            .command_encoder()
+            .begin_compute_pass(&ComputePassDescriptor::default());
+        for bind_groups in self.emitters.iter_manual(world) {
+            pass.set_bind_group(0, &bind_groups.simulation, &[]);
+            pass.set_bind_group(1, &bind_groups.maps, &[]);
+            pass.set_bind_group(2, &bind_groups.sort, &[bind_groups.sort_offsets[0]]);
+            pass.set_pipeline(update_pipeline);
+            pass.dispatch_workgroups(bind_groups.workgroups, 1, 1);
+            pass.set_pipeline(sort_keys_pipeline);
+            pass.dispatch_workgroups(bind_groups.sort_workgroups, 1, 1);
+
+            pass.set_pipeline(sort_step_pipeline);
+            for &offset in &bind_groups.sort_offsets {
+                pass.set_bind_group(2, &bind_groups.sort, &[offset]);
+                pass.dispatch_workgroups(bind_groups.sort_workgroups, 1, 1);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, RenderLabel)]
+struct GpuParticlesLabel;
+
+pub struct GpuParticlesPlugin;
+
+impl Plugin for GpuParticlesPlugin {
+    fn build(&self, app: &mut App) {
+        // imported by the compute shader for the turbulence
+        load_internal_asset!(
+            app,
+            NOISE_SHADER_HANDLE,
+            "../../assets/shaders/noise.wgsl",
+            Shader::from_wgsl
+        );
+
+        if !app.is_plugin_added::<ParticleGradientPlugin>() {
+            app.add_plugins(ParticleGradientPlugin);
+        }
+        app.add_plugins(ExtractComponentPlugin::<GpuParticles>::default())
+            .add_plugins(GpuParticlesRenderPlugin)
+            .init_resource::<ParticleMesh>()
+            .add_systems(Update, (setup_gpu_particles, emit_gpu_particles).chain());
+
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app.add_systems(
+            Render,
+            prepare_gpu_particles_bind_groups.in_set(RenderSet::PrepareBindGroups),
+        );
+
+        let gpu_particles_node = GpuParticlesNode::from_world(render_app.world_mut());
+        let mut render_graph = render_app.world_mut().resource_mut::<RenderGraph>();
+        render_graph.add_node(GpuParticlesLabel, gpu_particles_node);
+        render_graph.add_node_edge(GpuParticlesLabel, bevy::render::graph::CameraDriverLabel);
+    }
+
+    fn finish(&self, app: &mut App) {
+        app.sub_app_mut(RenderApp)
+            .init_resource::<GpuParticlesPipeline>();
+    }
+}
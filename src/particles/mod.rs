@@ -0,0 +1,391 @@
+pub mod gpu;
+mod render;
+
+use std::f32::consts::TAU;
+
+use bevy::{math::VectorSpace, prelude::*};
+use rand::{rng, RngExt};
+
+use self::gpu::GpuParticles;
+use crate::{
+    curve_assets::{ColorGradient, CurveAssetsPlugin},
+    math::noise::curl,
+};
+
+/// Number of materials an emitter bakes its color-over-life gradient into
+const COLOR_STEPS: usize = 16;
+
+/// Values at fractions of a particle's lifetime, linearly interpolated in between
+#[derive(Clone, Debug, PartialEq)]
+pub struct OverLife<T>(pub Vec<(f32, T)>);
+
+impl<T: VectorSpace> OverLife<T> {
+    pub fn constant(value: T) -> Self {
+        Self(vec![(0.0, value)])
+    }
+
+    /// The value at `t` in `[0, 1]`, keys are expected to be sorted by their fraction
+    pub fn sample(&self, t: f32) -> T {
+        let keys = &self.0;
+        let index = keys.partition_point(|(fraction, _)| *fraction <= t);
+        match (
+            index.checked_sub(1).map(|index| keys[index]),
+            keys.get(index),
+        ) {
+            (Some((start, from)), Some(&(end, to))) => {
+                from.lerp(to, ((t - start) / (end - start)).clamp(0.0, 1.0))
+            }
+            (Some((_, value)), None) | (None, Some(&(_, value))) => value,
+            (None, None) => T::ZERO,
+        }
+    }
+}
+
+/// Spawns camera facing particles from its [`GlobalTransform`], as entities unless it has
+/// [`GpuParticles`]
+///
+/// Entity particles are billboarded on the CPU and only sorted per particle by the transparent
+/// pass, [`GpuParticles`] billboard in the vertex shader and are sorted on the GPU, so they are
+/// the better fit for dense, overlapping effects like smoke.
+#[derive(Component, Clone)]
+#[require(Transform, Visibility)]
+pub struct ParticleEmitter {
+    /// Particles spawned per second
+    pub rate: f32,
+    /// Seconds a particle lives
+    pub lifetime: f32,
+    /// Center of the cone the initial velocities are picked from, in the emitter's local space
+    pub direction: Vec3,
+    /// Half angle of the velocity cone in radians
+    pub spread: f32,
+    /// Initial speed in units per second
+    pub speed: f32,
+    /// Initial speeds vary by up to this fraction of `speed`
+    pub speed_variation: f32,
+    /// Added to the velocity every second, for gravity or a constant rise
+    pub acceleration: Vec3,
+    /// Fraction of the velocity relative to the wind that is lost every second
+    pub drag: f32,
+    /// Velocity of the air, drag carries the particles along with it
+    pub wind: Vec3,
+    /// Upward acceleration of new particles, fading out over their lifetime as they cool down
+    pub buoyancy: f32,
+    /// Acceleration along the curl noise, which makes the particles swirl
+    pub turbulence: f32,
+    /// Size of the swirls in world units
+    pub turbulence_scale: f32,
+    pub noise_seed: u32,
+    /// Width of the particles in world units
+    pub size_over_life: OverLife<f32>,
+    /// Multiplied with the texture, the alpha fades the particles
+    pub color_over_life: OverLife<LinearRgba>,
+    /// Replaces `color_over_life` whenever the gradient is loaded or edited, [`GpuParticles`]
+    /// sample it from a baked texture at a finer resolution
+    pub color_gradient: Option<Handle<ColorGradient>>,
+    pub texture: Option<Handle<Image>>,
+    /// The emitter pauses while this many of its particles are alive
+    pub max_particles: usize,
+    /// Fraction of a particle left to spawn in the next frame
+    accumulator: f32,
+    alive: usize,
+    /// Materials for consecutive parts of the lifetime, baked from `color_over_life`
+    materials: Vec<Handle<StandardMaterial>>,
+}
+
+impl Default for ParticleEmitter {
+    fn default() -> Self {
+        Self {
+            rate: 60.0,
+            lifetime: 2.0,
+            direction: Vec3::Y,
+            spread: 0.3,
+            speed: 1.0,
+            speed_variation: 0.3,
+            acceleration: Vec3::ZERO,
+            drag: 0.0,
+            wind: Vec3::ZERO,
+            buoyancy: 0.0,
+            turbulence: 0.0,
+            turbulence_scale: 1.0,
+            noise_seed: 0,
+            size_over_life: OverLife::constant(0.1),
+            color_over_life: OverLife::constant(LinearRgba::WHITE),
+            color_gradient: None,
+            texture: None,
+            max_particles: 1000,
+            accumulator: 0.0,
+            alive: 0,
+            materials: Vec::new(),
+        }
+    }
+}
+
+impl ParticleEmitter {
+    pub fn alive(&self) -> usize {
+        self.alive
+    }
+
+    /// The velocity of a particle at `position` after `delta` seconds, `life` as in
+    /// [`Particle::life`]
+    pub fn integrate_velocity(
+        &self,
+        position: Vec3,
+        velocity: Vec3,
+        life: f32,
+        delta: f32,
+    ) -> Vec3 {
+        let mut acceleration = self.acceleration + Vec3::Y * self.buoyancy * (1.0 - life);
+        if self.turbulence != 0.0 {
+            let sample = position / self.turbulence_scale.max(f32::EPSILON);
+            acceleration += curl(sample, self.noise_seed) * self.turbulence;
+        }
+        let velocity = velocity + acceleration * delta;
+        self.wind + (velocity - self.wind) * (1.0 - self.drag * delta).max(0.0)
+    }
+
+    /// A random initial velocity within the cone around `direction`
+    fn initial_velocity(&self, rotation: Quat, rng: &mut impl RngExt) -> Vec3 {
+        // uniform over the spherical cap of the cone
+        let cos_theta = rng.random_range(self.spread.cos()..=1.0);
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+        let phi = rng.random_range(0.0..TAU);
+        let local = Vec3::new(sin_theta * phi.cos(), cos_theta, sin_theta * phi.sin());
+
+        let direction = self.direction.normalize_or(Vec3::Y);
+        let speed =
+            self.speed * (1.0 + rng.random_range(-self.speed_variation..=self.speed_variation));
+        rotation * Quat::from_rotation_arc(Vec3::Y, direction) * local * speed
+    }
+}
+
+#[derive(Component)]
+pub struct Particle {
+    pub emitter: Entity,
+    /// Seconds since the particle was spawned
+    pub age: f32,
+    pub lifetime: f32,
+    pub velocity: Vec3,
+    /// Rotation around the axis towards the camera, in radians
+    pub rotation: f32,
+}
+
+impl Particle {
+    /// Fraction of the lifetime that has passed, in `[0, 1]`
+    pub fn life(&self) -> f32 {
+        (self.age / self.lifetime).clamp(0.0, 1.0)
+    }
+}
+
+/// The quad all particles are drawn with
+#[derive(Resource)]
+struct ParticleMesh(Handle<Mesh>);
+
+impl FromWorld for ParticleMesh {
+    fn from_world(world: &mut World) -> Self {
+        let mut meshes = world.resource_mut::<Assets<Mesh>>();
+        Self(meshes.add(Plane3d::new(Vec3::Y, Vec2::splat(0.5))))
+    }
+}
+
+pub struct ParticlesPlugin;
+
+impl Plugin for ParticlesPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<ParticleGradientPlugin>() {
+            app.add_plugins(ParticleGradientPlugin);
+        }
+        app.init_resource::<ParticleMesh>().add_systems(
+            Update,
+            (bake_emitter_materials, spawn_particles, update_particles).chain(),
+        );
+    }
+}
+
+/// Shared by the entity and GPU particle plugins, which can be added together
+struct ParticleGradientPlugin;
+
+impl Plugin for ParticleGradientPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<CurveAssetsPlugin>() {
+            app.add_plugins(CurveAssetsPlugin);
+        }
+        app.add_systems(Update, sync_color_gradients);
+    }
+}
+
+/// Copy the keys of gradient assets into the emitters using them
+fn sync_color_gradients(
+    mut events: EventReader<AssetEvent<ColorGradient>>,
+    gradients: Res<Assets<ColorGradient>>,
+    mut emitters: Query<&mut ParticleEmitter>,
+) {
+    let changed: Vec<AssetId<ColorGradient>> = events
+        .read()
+        .filter_map(|event| match *event {
+            AssetEvent::Added { id } | AssetEvent::Modified { id } => Some(id),
+            _ => None,
+        })
+        .collect();
+
+    for mut emitter in &mut emitters {
+        let Some(id) = emitter.color_gradient.as_ref().map(Handle::id) else {
+            continue;
+        };
+        if !emitter.is_added() && !changed.contains(&id) {
+            continue;
+        }
+        let Some(gradient) = gradients.get(id) else {
+            continue;
+        };
+        emitter.color_over_life = OverLife(
+            gradient
+                .keys
+                .iter()
+                .map(|key| (key.position, key.color))
+                .collect(),
+        );
+    }
+}
+
+/// Rotation that turns the normal of a `Plane3d` towards the camera
+pub fn billboard_rotation(camera_translation: Vec3, translation: Vec3) -> Quat {
+    let x_dir = (camera_translation - translation).normalize_or(Vec3::Z);
+    let z_dir = x_dir.cross(Vec3::Y).normalize_or(Vec3::X);
+    let y_dir = z_dir.cross(x_dir);
+    Quat::from_mat3(&Mat3::from_cols(z_dir, x_dir, y_dir))
+}
+
+fn bake_emitter_materials(
+    mut emitters: Query<&mut ParticleEmitter, (Changed<ParticleEmitter>, Without<GpuParticles>)>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    for mut emitter in &mut emitters {
+        let emitter = emitter.bypass_change_detection();
+        let steps = (0..COLOR_STEPS).map(|step| {
+            let color = emitter
+                .color_over_life
+                .sample((step as f32 + 0.5) / COLOR_STEPS as f32);
+            StandardMaterial {
+                base_color: color.into(),
+                base_color_texture: emitter.texture.clone(),
+                unlit: true,
+                alpha_mode: AlphaMode::Blend,
+                ..default()
+            }
+        });
+
+        if emitter.materials.is_empty() {
+            emitter.materials = steps.map(|material| materials.add(material)).collect();
+        } else {
+            // update in place, so particles that are alive pick up the new colors
+            for (handle, material) in emitter.materials.iter().zip(steps) {
+                materials.insert(handle, material);
+            }
+        }
+    }
+}
+
+fn material_for_life(emitter: &ParticleEmitter, life: f32) -> Option<Handle<StandardMaterial>> {
+    let step = ((life * COLOR_STEPS as f32) as usize).min(COLOR_STEPS - 1);
+    emitter.materials.get(step).cloned()
+}
+
+fn spawn_particles(
+    mut commands: Commands,
+    time: Res<Time>,
+    mesh: Res<ParticleMesh>,
+    mut emitters: Query<
+        (
+            Entity,
+            &mut ParticleEmitter,
+            &GlobalTransform,
+            &InheritedVisibility,
+        ),
+        Without<GpuParticles>,
+    >,
+) {
+    let mut rng = rng();
+    for (entity, mut emitter, transform, visibility) in &mut emitters {
+        if !visibility.get() {
+            continue;
+        }
+        // bypass change detection, so the materials aren't baked again every frame
+        let emitter = emitter.bypass_change_detection();
+        emitter.accumulator += emitter.rate * time.delta_secs();
+        let count = emitter.accumulator as usize;
+        emitter.accumulator -= count as f32;
+
+        let count = count.min(emitter.max_particles.saturating_sub(emitter.alive));
+        let Some(material) = material_for_life(emitter, 0.0) else {
+            continue;
+        };
+        let (_, rotation, translation) = transform.to_scale_rotation_translation();
+        for _ in 0..count {
+            commands.spawn((
+                Mesh3d(mesh.0.clone()),
+                MeshMaterial3d(material.clone()),
+                Transform::from_translation(translation)
+                    .with_scale(Vec3::splat(emitter.size_over_life.sample(0.0))),
+                Particle {
+                    emitter: entity,
+                    age: 0.0,
+                    lifetime: emitter.lifetime,
+                    velocity: emitter.initial_velocity(rotation, &mut rng),
+                    rotation: rng.random_range(0.0..TAU),
+                },
+            ));
+        }
+        emitter.alive += count;
+    }
+}
+
+fn update_particles(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut emitters: Query<&mut ParticleEmitter>,
+    mut particles: Query<(
+        Entity,
+        &mut Particle,
+        &mut Transform,
+        &mut MeshMaterial3d<StandardMaterial>,
+    )>,
+    camera: Option<Single<&GlobalTransform, With<Camera3d>>>,
+) {
+    let camera_translation = camera.map(|camera| camera.translation());
+    let delta = time.delta_secs();
+
+    for mut emitter in &mut emitters {
+        emitter.bypass_change_detection().alive = 0;
+    }
+
+    for (entity, mut particle, mut transform, mut material) in &mut particles {
+        particle.age += delta;
+        // particles are moved with the settings of their emitter, so they go along with it
+        let Ok(mut emitter) = emitters.get_mut(particle.emitter) else {
+            commands.entity(entity).despawn();
+            continue;
+        };
+        if particle.age >= particle.lifetime {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        let emitter = emitter.bypass_change_detection();
+        emitter.alive += 1;
+
+        let life = particle.life();
+        particle.velocity =
+            emitter.integrate_velocity(transform.translation, particle.velocity, life, delta);
+        transform.translation += particle.velocity * delta;
+
+        transform.scale = Vec3::splat(emitter.size_over_life.sample(life));
+        if let Some(camera_translation) = camera_translation {
+            transform.rotation = billboard_rotation(camera_translation, transform.translation);
+            transform.rotate_local_y(particle.rotation);
+        }
+        if let Some(step) = material_for_life(emitter, life) {
+            if material.0 != step {
+                material.0 = step;
+            }
+        }
+    }
+}
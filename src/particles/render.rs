@@ -0,0 +1,361 @@
+use bevy::{
+    core_pipeline::{core_3d::Transparent3d, prepass::DepthPrepass},
+    ecs::system::{
+        lifetimeless::{Read, SRes},
+        SystemParamItem,
+    },
+    pbr::{
+        MeshPipeline, MeshPipelineKey, RenderMeshInstances, SetMeshBindGroup, SetMeshViewBindGroup,
+    },
+    prelude::*,
+    render::{
+        mesh::{
+            allocator::MeshAllocator, MeshVertexBufferLayoutRef, RenderMesh, RenderMeshBufferInfo,
+        },
+        render_asset::RenderAssets,
+        render_phase::{
+            AddRenderCommand, DrawFunctions, PhaseItem, PhaseItemExtraIndex, RenderCommand,
+            RenderCommandResult, SetItemPipeline, TrackedRenderPass, ViewSortedRenderPhases,
+        },
+        render_resource::{
+            binding_types::{
+                sampler, storage_buffer_read_only_sized, texture_1d, texture_2d, uniform_buffer,
+            },
+            BindGroup, BindGroupEntries, BindGroupLayout, BindGroupLayoutEntries, PipelineCache,
+            RenderPipelineDescriptor, SamplerBindingType, ShaderStages, ShaderType,
+            SpecializedMeshPipeline, SpecializedMeshPipelineError, SpecializedMeshPipelines,
+            TextureSampleType,
+        },
+        renderer::RenderDevice,
+        sync_world::MainEntity,
+        texture::{FallbackImage, GpuImage},
+        view::{ExtractedView, Msaa},
+        Render, RenderApp, RenderSet,
+    },
+};
+
+use super::{
+    gpu::{ExtractedGpuParticles, GpuParticleBindGroups},
+    ParticleEmitter,
+};
+
+const SHADER_PATH: &str = "shaders/particles_material.wgsl";
+
+/// Number of evenly spaced ages the size and color over life are sampled at for the shader
+pub const OVER_LIFE_SAMPLES: usize = 16;
+
+#[derive(Clone, Default, ShaderType)]
+pub struct ParticleRenderUniform {
+    /// The size over life samples, packed four to a vector
+    pub sizes: [Vec4; OVER_LIFE_SAMPLES / 4],
+    pub colors: [Vec4; OVER_LIFE_SAMPLES],
+    pub lifetime: f32,
+    pub soft_distance: f32,
+    /// Whether the particles are colored from the baked gradient instead of `colors`
+    pub baked_gradient: u32,
+}
+
+impl ParticleRenderUniform {
+    pub fn new(emitter: &ParticleEmitter, soft_distance: f32) -> Self {
+        let age = |sample: usize| sample as f32 / (OVER_LIFE_SAMPLES - 1) as f32;
+        let sizes: [f32; OVER_LIFE_SAMPLES] =
+            std::array::from_fn(|sample| emitter.size_over_life.sample(age(sample)));
+        Self {
+            sizes: std::array::from_fn(|index| Vec4::from_slice(&sizes[index * 4..])),
+            colors: std::array::from_fn(|sample| {
+                emitter.color_over_life.sample(age(sample)).to_vec4()
+            }),
+            lifetime: emitter.lifetime,
+            soft_distance,
+            baked_gradient: 0,
+        }
+    }
+}
+
+pub struct GpuParticlesRenderPlugin;
+
+impl Plugin for GpuParticlesRenderPlugin {
+    fn build(&self, app: &mut App) {
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app
+            .add_render_command::<Transparent3d, DrawGpuParticles>()
+            .init_resource::<SpecializedMeshPipelines<GpuParticlesRenderPipeline>>()
+            .add_systems(
+                Render,
+                (
+                    queue_gpu_particles.in_set(RenderSet::QueueMeshes),
+                    prepare_draw_bind_groups
+                        .in_set(RenderSet::PrepareBindGroups)
+                        .after(super::gpu::prepare_gpu_particles_bind_groups),
+                ),
+            );
+    }
+
+    fn finish(&self, app: &mut App) {
+        app.sub_app_mut(RenderApp)
+            .init_resource::<GpuParticlesRenderPipeline>();
+    }
+}
+
+#[derive(Resource)]
+struct GpuParticlesRenderPipeline {
+    shader: Handle<Shader>,
+    mesh_pipeline: MeshPipeline,
+    bind_group_layout: BindGroupLayout,
+}
+
+impl FromWorld for GpuParticlesRenderPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+
+        // bindings start at 100 to stay clear of the standard material bindings imported by bevy_pbr
+        let entries = BindGroupLayoutEntries::with_indices(
+            ShaderStages::VERTEX_FRAGMENT,
+            (
+                (
+                    100,
+                    texture_2d(TextureSampleType::Float { filterable: false }),
+                ),
+                (
+                    101,
+                    texture_2d(TextureSampleType::Float { filterable: false }),
+                ),
+                (102, uniform_buffer::<ParticleRenderUniform>(false)),
+                (
+                    103,
+                    texture_2d(TextureSampleType::Float { filterable: true }),
+                ),
+                (104, sampler(SamplerBindingType::Filtering)),
+                (105, storage_buffer_read_only_sized(false, None)),
+                (
+                    106,
+                    texture_1d(TextureSampleType::Float { filterable: true }),
+                ),
+                (107, sampler(SamplerBindingType::Filtering)),
+            ),
+        );
+        let bind_group_layout = render_device
+            .create_bind_group_layout("gpu_particles_render_bind_group_layout", &entries);
+
+        GpuParticlesRenderPipeline {
+            shader: world.resource::<AssetServer>().load(SHADER_PATH),
+            mesh_pipeline: world.resource::<MeshPipeline>().clone(),
+            bind_group_layout,
+        }
+    }
+}
+
+impl SpecializedMeshPipeline for GpuParticlesRenderPipeline {
+    type Key = MeshPipelineKey;
+
+    fn specialize(
+        &self,
+        key: Self::Key,
+        layout: &MeshVertexBufferLayoutRef,
+    ) -> Result<RenderPipelineDescriptor, SpecializedMeshPipelineError> {
+        let mut descriptor = self.mesh_pipeline.specialize(key, layout)?;
+
+        descriptor.label = Some("gpu_particles_render_pipeline".into());
+        descriptor.layout.push(self.bind_group_layout.clone());
+        descriptor.vertex.shader = self.shader.clone();
+        descriptor.fragment.as_mut().unwrap().shader = self.shader.clone();
+        Ok(descriptor)
+    }
+}
+
+#[derive(Component)]
+struct GpuParticlesDrawBindGroup(BindGroup);
+
+fn prepare_draw_bind_groups(
+    mut commands: Commands,
+    pipeline: Res<GpuParticlesRenderPipeline>,
+    gpu_images: Res<RenderAssets<GpuImage>>,
+    fallback_image: Res<FallbackImage>,
+    emitters: Query<(Entity, &ExtractedGpuParticles, &GpuParticleBindGroups)>,
+    render_device: Res<RenderDevice>,
+) {
+    for (entity, particles, bind_groups) in &emitters {
+        let (Some(position_map), Some(velocity_map), Some(render_uniform)) = (
+            gpu_images.get(&particles.maps.position_map),
+            gpu_images.get(&particles.maps.velocity_map),
+            bind_groups.render_buffer.binding(),
+        ) else {
+            continue;
+        };
+        // untextured particles, or particles whose texture is still loading, are plain quads
+        let texture = particles
+            .texture
+            .as_ref()
+            .and_then(|texture| gpu_images.get(texture))
+            .unwrap_or(&fallback_image.d2);
+        let color_gradient = particles
+            .color_gradient
+            .as_ref()
+            .and_then(|gradient| gpu_images.get(gradient))
+            .unwrap_or(&fallback_image.d1);
+
+        let bind_group = render_device.create_bind_group(
+            "gpu_particles_render_bind_group",
+            &pipeline.bind_group_layout,
+            &BindGroupEntries::with_indices((
+                (100, &position_map.texture_view),
+                (101, &velocity_map.texture_view),
+                (102, render_uniform),
+                (103, &texture.texture_view),
+                (104, &texture.sampler),
+                (105, bind_groups.sort_buffer.as_entire_binding()),
+                (106, &color_gradient.texture_view),
+                (107, &color_gradient.sampler),
+            )),
+        );
+        commands
+            .entity(entity)
+            .insert(GpuParticlesDrawBindGroup(bind_group));
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn queue_gpu_particles(
+    transparent_3d_draw_functions: Res<DrawFunctions<Transparent3d>>,
+    particles_pipeline: Res<GpuParticlesRenderPipeline>,
+    mut pipelines: ResMut<SpecializedMeshPipelines<GpuParticlesRenderPipeline>>,
+    pipeline_cache: Res<PipelineCache>,
+    meshes: Res<RenderAssets<RenderMesh>>,
+    render_mesh_instances: Res<RenderMeshInstances>,
+    emitters: Query<(Entity, &MainEntity), With<GpuParticlesDrawBindGroup>>,
+    mut transparent_render_phases: ResMut<ViewSortedRenderPhases<Transparent3d>>,
+    views: Query<(&ExtractedView, &Msaa, Has<DepthPrepass>)>,
+) {
+    let draw_particles = transparent_3d_draw_functions
+        .read()
+        .id::<DrawGpuParticles>();
+
+    for (view, msaa, depth_prepass) in &views {
+        let Some(transparent_phase) = transparent_render_phases.get_mut(&view.retained_view_entity)
+        else {
+            continue;
+        };
+
+        let mut view_key = MeshPipelineKey::from_msaa_samples(msaa.samples())
+            | MeshPipelineKey::from_hdr(view.hdr)
+            | MeshPipelineKey::BLEND_ALPHA;
+        // soft particles fade out against the scene depth, when the camera renders it
+        if depth_prepass {
+            view_key |= MeshPipelineKey::DEPTH_PREPASS;
+        }
+        let rangefinder = view.rangefinder3d();
+        for (entity, main_entity) in &emitters {
+            let Some(mesh_instance) = render_mesh_instances.render_mesh_queue_data(*main_entity)
+            else {
+                continue;
+            };
+            let Some(mesh) = meshes.get(mesh_instance.mesh_asset_id) else {
+                continue;
+            };
+            let key =
+                view_key | MeshPipelineKey::from_primitive_topology(mesh.primitive_topology());
+            let Ok(pipeline) =
+                pipelines.specialize(&pipeline_cache, &particles_pipeline, key, &mesh.layout)
+            else {
+                continue;
+            };
+
+            transparent_phase.add(Transparent3d {
+                entity: (entity, *main_entity),
+                pipeline,
+                draw_function: draw_particles,
+                distance: rangefinder.distance_translation(&mesh_instance.translation),
+                batch_range: 0..1,
+                extra_index: PhaseItemExtraIndex::None,
+                indexed: true,
+            });
+        }
+    }
+}
+
+type DrawGpuParticles = (
+    SetItemPipeline,
+    SetMeshViewBindGroup<0>,
+    SetMeshBindGroup<1>,
+    SetGpuParticlesBindGroup<2>,
+    DrawGpuParticleInstances,
+);
+
+struct SetGpuParticlesBindGroup<const I: usize>;
+
+impl<P: PhaseItem, const I: usize> RenderCommand<P> for SetGpuParticlesBindGroup<I> {
+    type Param = ();
+    type ViewQuery = ();
+    type ItemQuery = Read<GpuParticlesDrawBindGroup>;
+
+    fn render<'w>(
+        _item: &P,
+        _view: (),
+        bind_group: Option<&'w GpuParticlesDrawBindGroup>,
+        _param: SystemParamItem<'w, '_, Self::Param>,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        let Some(bind_group) = bind_group else {
+            return RenderCommandResult::Skip;
+        };
+
+        pass.set_bind_group(I, &bind_group.0, &[]);
+        RenderCommandResult::Success
+    }
+}
+
+/// Draw the quad once for every particle in the pool, in the order of the sort buffer. Dead
+/// particles are collapsed by the vertex shader
+struct DrawGpuParticleInstances;
+
+impl<P: PhaseItem> RenderCommand<P> for DrawGpuParticleInstances {
+    type Param = (
+        SRes<RenderAssets<RenderMesh>>,
+        SRes<RenderMeshInstances>,
+        SRes<MeshAllocator>,
+    );
+    type ViewQuery = ();
+    type ItemQuery = Read<ExtractedGpuParticles>;
+
+    fn render<'w>(
+        item: &P,
+        _view: (),
+        particles: Option<&'w ExtractedGpuParticles>,
+        (meshes, render_mesh_instances, mesh_allocator): SystemParamItem<'w, '_, Self::Param>,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        let Some(particles) = particles else {
+            return RenderCommandResult::Skip;
+        };
+        let mesh_allocator = mesh_allocator.into_inner();
+
+        let Some(mesh_id) = render_mesh_instances.mesh_asset_id(item.main_entity()) else {
+            return RenderCommandResult::Skip;
+        };
+        let Some(gpu_mesh) = meshes.into_inner().get(mesh_id) else {
+            return RenderCommandResult::Skip;
+        };
+        let (Some(vertex_slice), Some(index_slice)) = (
+            mesh_allocator.mesh_vertex_slice(&mesh_id),
+            mesh_allocator.mesh_index_slice(&mesh_id),
+        ) else {
+            return RenderCommandResult::Skip;
+        };
+        let RenderMeshBufferInfo::Indexed {
+            index_format,
+            count,
+        } = gpu_mesh.buffer_info
+        else {
+            return RenderCommandResult::Skip;
+        };
+
+        pass.set_vertex_buffer(0, vertex_slice.buffer.slice(..));
+        pass.set_index_buffer(index_slice.buffer.slice(..), 0, index_format);
+        pass.draw_indexed(
+            index_slice.range.start..index_slice.range.start + count,
+            vertex_slice.range.start as i32,
+            0..particles.maps.capacity,
+        );
+        RenderCommandResult::Success
+    }
+}
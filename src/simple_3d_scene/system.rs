@@ -7,6 +7,17 @@ use bevy_egui::{
 };
 use bevy_panorbit_camera::PanOrbitCamera;
 
+use crate::{
+    atmosphere::{
+        atmosphere_ui,
+        lut::{atmosphere_quality_ui, AtmosphereQuality},
+        AtmosphereSettings,
+    },
+    boids::framing::{camera_framing_ui, CameraFraming},
+    capture::CaptureCamera,
+    time_of_day::{time_of_day_ui, Moon, TimeOfDay},
+};
+
 /// set up a simple 3D scene
 pub fn simple_3d_scene(mut commands: Commands, mut ambient_light: ResMut<AmbientLight>) {
     let mut camera_transform = Transform::from_xyz(0.0, 0.0, 0.0);
@@ -37,15 +48,19 @@ pub fn simple_3d_scene(mut commands: Commands, mut ambient_light: ResMut<Ambient
     ambient_light.brightness = 200.0;
 }
 
+/// The intensity is only editable when it isn't driven by the time of day
 pub fn directional_light_ui(
     light: &mut DirectionalLight,
     camera_transform: (&Transform, &Camera),
+    edit_intensity: bool,
     ui: &mut Ui,
     // mut fog: Mut<DistanceFog>,
 ) {
-    ui.label("Intensity");
-    ui.add(egui::Slider::new(&mut light.illuminance, 100.0..=100_000.0));
-    ui.end_row();
+    if edit_intensity {
+        ui.label("Intensity");
+        ui.add(egui::Slider::new(&mut light.illuminance, 100.0..=100_000.0));
+        ui.end_row();
+    }
 
     ui.label("Shadows");
     ui.checkbox(&mut light.shadows_enabled, "Enabled");
@@ -69,9 +84,14 @@ pub fn directional_light_ui(
     ui.end_row();
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn ui_system(
-    mut light_query: Query<&mut DirectionalLight>,
-    camera_query: Query<(&Transform, &Camera)>,
+    mut light_query: Query<&mut DirectionalLight, Without<Moon>>,
+    camera_query: Query<(&Transform, &Camera), Without<CaptureCamera>>,
+    framing: Option<ResMut<CameraFraming>>,
+    atmosphere: Option<ResMut<AtmosphereSettings>>,
+    atmosphere_quality: Option<ResMut<AtmosphereQuality>>,
+    time_of_day: Option<ResMut<TimeOfDay>>,
     mut contexts: EguiContexts,
     // mut fog: Query<&mut DistanceFog>,
 ) {
@@ -86,15 +106,37 @@ pub fn ui_system(
                 .spacing([40.0, 4.0])
                 .striped(true)
                 .show(ui, |ui| {
+                    let edit_intensity = time_of_day.is_none();
+                    if let Some(mut time_of_day) = time_of_day {
+                        let mut edited = *time_of_day;
+                        time_of_day_ui(&mut edited, ui);
+                        time_of_day.set_if_neq(edited);
+                    }
                     light_query.iter_mut().for_each(|mut light| {
                         let Ok(camera) = camera_query.single() else {
                             return;
                         };
                         directional_light_ui(
-                            &mut light, camera, ui,
+                            &mut light,
+                            camera,
+                            edit_intensity,
+                            ui,
                             // fog.single_mut(),
                         )
                     });
+                    if let Some(mut framing) = framing {
+                        camera_framing_ui(framing.as_mut(), ui);
+                    }
+                    if let Some(mut atmosphere) = atmosphere {
+                        let mut edited = *atmosphere;
+                        atmosphere_ui(&mut edited, ui);
+                        atmosphere.set_if_neq(edited);
+                    }
+                    if let Some(mut quality) = atmosphere_quality {
+                        let mut edited = *quality;
+                        atmosphere_quality_ui(&mut edited, ui);
+                        quality.set_if_neq(edited);
+                    }
                 });
         });
 }
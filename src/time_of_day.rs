@@ -0,0 +1,328 @@
+//! A day and night cycle that moves the sun, and optionally a moon, across the sky.
+
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+use bevy_egui::egui::{self, Ui};
+
+use crate::math::smooth_step;
+
+/// Sun elevation in radians below which the sky is fully dark, a little below the horizon
+const TWILIGHT_ELEVATION: f32 = -6.0 / 180.0 * PI;
+
+/// Sun elevation in radians above which the sunlight has its full daytime color
+const DAYLIGHT_ELEVATION: f32 = 30.0 / 180.0 * PI;
+
+/// Color temperatures in kelvin of the sun at the horizon and high in the sky
+const SUNRISE_TEMPERATURE: f32 = 2000.0;
+const DAYLIGHT_TEMPERATURE: f32 = 5800.0;
+
+/// Cool color temperature of moonlight in kelvin
+const MOON_TEMPERATURE: f32 = 7500.0;
+
+#[derive(Resource, Clone, Copy, PartialEq)]
+pub struct TimeOfDay {
+    /// Hours since midnight, in `[0, 24)`
+    pub hour: f32,
+    /// Real seconds a full day takes
+    pub day_length: f32,
+    pub paused: bool,
+    /// Latitude of the scene in radians, positive is north
+    pub latitude: f32,
+    /// Latitude in radians at which the sun is overhead at noon, between -23.44° in December
+    /// and 23.44° in June
+    pub declination: f32,
+    /// Illuminance of the sun in lux when it is straight overhead
+    pub zenith_illuminance: f32,
+    /// Brightness of the ambient light during the day and at night
+    pub day_ambient: f32,
+    pub night_ambient: f32,
+    /// Adds a dim directional light opposite the sun
+    pub moon: bool,
+    /// Illuminance of the moon in lux when the sun is straight below
+    pub moon_illuminance: f32,
+}
+
+impl Default for TimeOfDay {
+    fn default() -> Self {
+        Self {
+            hour: 10.0,
+            day_length: 240.0,
+            paused: false,
+            latitude: 45.0 / 180.0 * PI,
+            declination: 0.0,
+            zenith_illuminance: 10_000.0,
+            day_ambient: 200.0,
+            night_ambient: 20.0,
+            moon: true,
+            moon_illuminance: 500.0,
+        }
+    }
+}
+
+impl TimeOfDay {
+    pub fn sun_direction(&self) -> Vec3 {
+        sun_direction(self.hour, self.latitude, self.declination)
+    }
+
+    /// Fraction of daylight, 0 after twilight and 1 while the sun is above the horizon
+    pub fn daylight(&self) -> f32 {
+        daylight(sun_elevation(self.sun_direction()))
+    }
+}
+
+/// Direction towards the sun in world space, with x to the east, y up and -z to the north.
+///
+/// Hour 12 is solar noon, at which the sun is due south on the northern hemisphere.
+pub fn sun_direction(hour: f32, latitude: f32, declination: f32) -> Vec3 {
+    let hour_angle = (hour - 12.0) / 24.0 * 2.0 * PI;
+    let east = -declination.cos() * hour_angle.sin();
+    let north =
+        latitude.cos() * declination.sin() - latitude.sin() * declination.cos() * hour_angle.cos();
+    let up =
+        latitude.sin() * declination.sin() + latitude.cos() * declination.cos() * hour_angle.cos();
+    Vec3::new(east, up, -north)
+}
+
+/// Angle in radians of `direction` above the horizon
+pub fn sun_elevation(direction: Vec3) -> f32 {
+    direction.normalize_or_zero().y.clamp(-1.0, 1.0).asin()
+}
+
+/// Fades in during twilight, from 6° below the horizon up to the horizon
+pub fn daylight(elevation: f32) -> f32 {
+    smooth_step(((elevation - TWILIGHT_ELEVATION) / -TWILIGHT_ELEVATION).clamp(0.0, 1.0))
+}
+
+/// Illuminance of the sun at `elevation`, falling off with the angle of its light on the ground
+pub fn sun_illuminance(elevation: f32, zenith_illuminance: f32) -> f32 {
+    // a small floor keeps some light while the sun sets
+    zenith_illuminance * (elevation.sin().max(0.0) + 0.02) * daylight(elevation)
+}
+
+/// Reddish at the horizon and white high in the sky
+pub fn sun_color_temperature(elevation: f32) -> f32 {
+    let t = (elevation / DAYLIGHT_ELEVATION).clamp(0.0, 1.0);
+    SUNRISE_TEMPERATURE.lerp(DAYLIGHT_TEMPERATURE, smooth_step(t))
+}
+
+/// Color of a black body at `kelvin`, the approximation by Tanner Helland which holds from
+/// 1000 K to 40000 K
+pub fn color_temperature(kelvin: f32) -> Color {
+    let t = kelvin.clamp(1000.0, 40_000.0) / 100.0;
+    let red = if t <= 66.0 {
+        255.0
+    } else {
+        329.699 * (t - 60.0).powf(-0.133_205)
+    };
+    let green = if t <= 66.0 {
+        99.470_8 * t.ln() - 161.119_57
+    } else {
+        288.122_16 * (t - 60.0).powf(-0.075_514_85)
+    };
+    let blue = if t >= 66.0 {
+        255.0
+    } else if t <= 19.0 {
+        0.0
+    } else {
+        138.517_73 * (t - 10.0).ln() - 305.044_8
+    };
+    Color::srgb(
+        (red / 255.0).clamp(0.0, 1.0),
+        (green / 255.0).clamp(0.0, 1.0),
+        (blue / 255.0).clamp(0.0, 1.0),
+    )
+}
+
+/// The directional light opposite the sun, spawned while [`TimeOfDay::moon`] is set
+#[derive(Component)]
+pub struct Moon;
+
+fn advance_time_of_day(mut time_of_day: ResMut<TimeOfDay>, time: Res<Time>) {
+    if time_of_day.paused || time_of_day.day_length <= 0.0 {
+        return;
+    }
+    let hours = time.delta_secs() / time_of_day.day_length * 24.0;
+    time_of_day.hour = (time_of_day.hour + hours).rem_euclid(24.0);
+}
+
+fn update_sun(
+    time_of_day: Res<TimeOfDay>,
+    mut sun: Query<(&mut DirectionalLight, &mut Transform), Without<Moon>>,
+    mut moon: Query<(&mut DirectionalLight, &mut Transform), With<Moon>>,
+    mut ambient_light: ResMut<AmbientLight>,
+) {
+    if !time_of_day.is_changed() {
+        return;
+    }
+    let direction = time_of_day.sun_direction();
+    let elevation = sun_elevation(direction);
+    let daylight = daylight(elevation);
+
+    for (mut light, mut transform) in &mut sun {
+        light.illuminance = sun_illuminance(elevation, time_of_day.zenith_illuminance);
+        light.color = color_temperature(sun_color_temperature(elevation));
+        *transform = Transform::IDENTITY.looking_to(-direction, Vec3::Y);
+    }
+    for (mut light, mut transform) in &mut moon {
+        light.illuminance = time_of_day.moon_illuminance * (-elevation.sin()).max(0.0);
+        *transform = Transform::IDENTITY.looking_to(direction, Vec3::Y);
+    }
+    ambient_light.brightness = time_of_day
+        .night_ambient
+        .lerp(time_of_day.day_ambient, daylight);
+}
+
+fn spawn_moon(
+    mut commands: Commands,
+    time_of_day: Res<TimeOfDay>,
+    moon: Query<Entity, With<Moon>>,
+) {
+    if !time_of_day.is_changed() {
+        return;
+    }
+    match (time_of_day.moon, moon.iter().next()) {
+        (true, None) => {
+            commands.spawn((
+                Moon,
+                DirectionalLight {
+                    color: color_temperature(MOON_TEMPERATURE),
+                    illuminance: 0.0,
+                    shadows_enabled: false,
+                    ..default()
+                },
+            ));
+        }
+        (false, Some(entity)) => commands.entity(entity).despawn(),
+        _ => {}
+    }
+}
+
+/// Shows the hour as `hh:mm`
+fn format_hour(hour: f64) -> String {
+    let minutes = (hour * 60.0).round() as u32 % (24 * 60);
+    format!("{:02}:{:02}", minutes / 60, minutes % 60)
+}
+
+pub fn time_of_day_ui(time_of_day: &mut TimeOfDay, ui: &mut Ui) {
+    ui.label("Time of day");
+    ui.horizontal(|ui| {
+        ui.add(
+            egui::Slider::new(&mut time_of_day.hour, 0.0..=23.99)
+                .custom_formatter(|hour, _| format_hour(hour)),
+        );
+        ui.checkbox(&mut time_of_day.paused, "Paused");
+    });
+    ui.end_row();
+
+    ui.label("Day length");
+    ui.add(egui::Slider::new(&mut time_of_day.day_length, 10.0..=3600.0).suffix(" s"));
+    ui.end_row();
+
+    let mut latitude = time_of_day.latitude.to_degrees();
+    ui.label("Latitude");
+    if ui
+        .add(egui::Slider::new(&mut latitude, -90.0..=90.0).suffix("°"))
+        .changed()
+    {
+        time_of_day.latitude = latitude.to_radians();
+    }
+    ui.end_row();
+
+    let mut declination = time_of_day.declination.to_degrees();
+    ui.label("Sun declination");
+    if ui
+        .add(egui::Slider::new(&mut declination, -23.44..=23.44).suffix("°"))
+        .changed()
+    {
+        time_of_day.declination = declination.to_radians();
+    }
+    ui.end_row();
+
+    ui.label("Sun illuminance");
+    ui.add(egui::Slider::new(&mut time_of_day.zenith_illuminance, 100.0..=100_000.0).suffix(" lx"));
+    ui.end_row();
+
+    ui.label("Moon");
+    ui.checkbox(&mut time_of_day.moon, "Enabled");
+    ui.end_row();
+}
+
+pub struct TimeOfDayPlugin;
+
+impl Plugin for TimeOfDayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TimeOfDay>().add_systems(
+            Update,
+            (advance_time_of_day, spawn_moon, update_sun).chain(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-5;
+
+    fn degrees(degrees: f32) -> f32 {
+        degrees / 180.0 * PI
+    }
+
+    #[test]
+    fn sun_rises_in_the_east_and_sets_in_the_west() {
+        for latitude in [-60.0, 0.0, 45.0] {
+            let latitude = degrees(latitude);
+            let sunrise = sun_direction(6.0, latitude, 0.0);
+            assert!(sunrise.abs_diff_eq(Vec3::X, EPSILON), "{sunrise:?}");
+            let sunset = sun_direction(18.0, latitude, 0.0);
+            assert!(sunset.abs_diff_eq(Vec3::NEG_X, EPSILON), "{sunset:?}");
+        }
+    }
+
+    #[test]
+    fn sun_is_due_south_at_noon() {
+        for (latitude, declination) in [(45.0, 0.0), (52.0, 23.44), (30.0, -23.44), (10.0, 5.0)] {
+            let direction = sun_direction(12.0, degrees(latitude), degrees(declination));
+            assert!(direction.x.abs() < EPSILON, "{direction:?}");
+            // south is +z
+            assert!(direction.z > 0.0, "{direction:?}");
+            let elevation = sun_elevation(direction);
+            assert!(
+                (elevation - degrees(90.0 - latitude + declination)).abs() < 1e-4,
+                "{latitude} {declination}: {elevation}"
+            );
+        }
+    }
+
+    #[test]
+    fn sun_is_below_the_horizon_at_midnight() {
+        for (latitude, declination) in [(0.0, 0.0), (45.0, 0.0), (45.0, 23.44), (-45.0, -23.44)] {
+            let direction = sun_direction(0.0, degrees(latitude), degrees(declination));
+            assert!(sun_elevation(direction) < 0.0, "{latitude} {declination}");
+        }
+    }
+
+    #[test]
+    fn daylight_fades_during_twilight() {
+        assert_eq!(daylight(degrees(-90.0)), 0.0);
+        assert_eq!(daylight(degrees(-6.0)), 0.0);
+        assert_eq!(daylight(degrees(-6.5)), 0.0);
+        assert_eq!(daylight(0.0), 1.0);
+        assert_eq!(daylight(degrees(45.0)), 1.0);
+        let dusk = daylight(degrees(-3.0));
+        assert!(dusk > 0.0 && dusk < 1.0, "{dusk}");
+    }
+
+    #[test]
+    fn sun_illuminance_grows_with_elevation() {
+        assert_eq!(sun_illuminance(degrees(-10.0), 10_000.0), 0.0);
+        let mut previous = 0.0;
+        for elevation in (-5..=90).map(|elevation| degrees(elevation as f32)) {
+            let illuminance = sun_illuminance(elevation, 10_000.0);
+            assert!(illuminance > previous, "{elevation}");
+            previous = illuminance;
+        }
+        assert!(previous >= 10_000.0);
+    }
+}